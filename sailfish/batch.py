@@ -0,0 +1,273 @@
+"""
+Run an ensemble of simulations on one node, for the `sailfish batch`
+subcommand.
+
+The runs are listed in a TOML file, as an array of `run` tables. Keys in an
+optional `defaults` table apply to every run, unless the run overrides them.
+Each run is a separate sailfish process, writing its checkpoints and log to
+its own output directory, named after the run. For example:
+
+.. code-block:: toml
+
+    [defaults]
+    setup = "circumbinary-disk"
+    resolution = 400
+    end_time = 100.0
+    checkpoint = 10.0
+
+    [[run]]
+    name = "q0.5"
+    model = { mass_ratio = 0.5 }
+
+    [[run]]
+    name = "q1.0"
+    model = { mass_ratio = 1.0 }
+
+The `setup` key is the setup name or restart file, `model` and `solver` are
+tables of model parameters and solver options, and the remaining keys are the
+long names of driver command line options, with underscores in place of
+dashes. Boolean options are passed as flags if they are true.
+"""
+
+import re
+from logging import getLogger
+from typing import NamedTuple
+from sailfish.driver import ConfigurationError
+
+logger = getLogger(__name__)
+
+RUN_NAME_PATTERN = re.compile(r"[A-Za-z0-9_+=-][A-Za-z0-9_.+=-]*")
+"""
+Run names are used as directory names under the batch output directory, so
+they may not contain path separators, or begin with a dot.
+"""
+
+
+class BatchResult(NamedTuple):
+    """
+    The outcome of a single run in a batch
+    """
+
+    name: str
+    outdir: str
+    returncode: int
+    wall_time: float
+
+    @property
+    def status(self):
+        return "done" if self.returncode == 0 else f"failed ({self.returncode})"
+
+
+def load_batch_file(filename):
+    """
+    Load a TOML batch file, and return a list of run dictionaries with the
+    defaults applied. A `ConfigurationError` is raised if the file cannot be
+    read or parsed, or if its runs are malformed.
+    """
+    try:
+        try:
+            import tomllib
+
+            with open(filename, "rb") as f:
+                config = tomllib.load(f)
+        except ImportError:
+            import toml
+
+            with open(filename) as f:
+                config = toml.load(f)
+    except OSError as e:
+        raise ConfigurationError(f"could not open batch file {filename}: {e}")
+    except ValueError as e:
+        raise ConfigurationError(f"batch file {filename} is malformed: {e}")
+
+    defaults = config.get("defaults", dict())
+    runs = config.get("run", list())
+
+    if not isinstance(defaults, dict):
+        raise ConfigurationError("defaults must be a table")
+
+    if not isinstance(runs, list) or not all(isinstance(r, dict) for r in runs):
+        raise ConfigurationError("run must be an array of tables")
+
+    runs = [{**defaults, **run} for run in runs]
+
+    for n, run in enumerate(runs):
+        run.setdefault("name", f"run{n:03d}")
+        name = run["name"]
+
+        if not isinstance(name, str) or not RUN_NAME_PATTERN.fullmatch(name):
+            raise ConfigurationError(
+                f"run name {name!r} must be letters, digits, or _.+=-, "
+                f"and not begin with a dot"
+            )
+
+        if "setup" not in run:
+            raise ConfigurationError(f"run {name} has no setup")
+
+        for key in ("model", "solver"):
+            if not isinstance(run.get(key, dict()), dict):
+                raise ConfigurationError(f"run {name} has a {key} which is not a table")
+
+    names = [run["name"] for run in runs]
+
+    if len(set(names)) != len(names):
+        raise ConfigurationError("run names must be unique")
+
+    return runs
+
+
+def run_command_line(run, outdir):
+    """
+    Return the sailfish command line arguments for a run dictionary.
+    """
+    args = [run["setup"], "--outdir", outdir]
+
+    for key, val in run.items():
+        if key in ("name", "setup"):
+            continue
+        elif key in ("model", "solver"):
+            args += [f"--{key}"] + [f"{k}={v}" for k, v in val.items()]
+        elif val is True:
+            args += [f"--{key.replace('_', '-')}"]
+        elif val is not False:
+            args += [f"--{key.replace('_', '-')}", str(val)]
+
+    return args
+
+
+def run_batch(runs, outdir=".", jobs=1, gpus=0):
+    """
+    Execute a list of run dictionaries, at most `jobs` at a time, and return
+    a list of :obj:`BatchResult` instances.
+
+    If `gpus` is nonzero, the runs are assigned round-robin to that many GPU
+    devices, through the `CUDA_VISIBLE_DEVICES` environment variable.
+    """
+    import os
+    import subprocess
+    import sys
+    import time
+    from pathlib import Path
+
+    package_root = str(Path(__file__).parent.parent)
+    pending = list(enumerate(runs))
+    running = dict()
+    results = dict()
+
+    while pending or running:
+        while pending and len(running) < jobs:
+            n, run = pending.pop(0)
+            run_outdir = os.path.join(outdir, run["name"])
+            Path(run_outdir).mkdir(parents=True, exist_ok=True)
+            env = dict(os.environ)
+            env["PYTHONPATH"] = os.pathsep.join(
+                filter(None, [package_root, env.get("PYTHONPATH")])
+            )
+
+            if gpus:
+                env["CUDA_VISIBLE_DEVICES"] = str(n % gpus)
+
+            argv = [sys.executable, "-c", "import sailfish.cli as c; exit(c.main())"]
+            argv += run_command_line(run, run_outdir)
+            log = open(os.path.join(run_outdir, "sailfish.log"), "w")
+            logger.info(f"start {run['name']}")
+            process = subprocess.Popen(
+                argv, stdout=log, stderr=subprocess.STDOUT, env=env
+            )
+            running[n] = (run, run_outdir, process, log, time.perf_counter())
+
+        time.sleep(0.1)
+
+        for n, (run, run_outdir, process, log, start) in list(running.items()):
+            if process.poll() is not None:
+                log.close()
+                del running[n]
+                results[n] = BatchResult(
+                    run["name"],
+                    run_outdir,
+                    process.returncode,
+                    time.perf_counter() - start,
+                )
+                logger.info(f"{run['name']}: {results[n].status}")
+
+    return [results[n] for n in sorted(results)]
+
+
+def print_summary(results):
+    """
+    Print a table of the completion status and wall time of each run.
+    """
+    print(f"{'run':<24} {'status':<14} {'wall time':>10}  output")
+    for r in results:
+        print(f"{r.name:<24} {r.status:<14} {r.wall_time:>9.1f}s  {r.outdir}")
+
+
+def argument_parser():
+    """
+    Return the argument parser for the `sailfish batch` subcommand.
+    """
+    import argparse
+
+    parser = argparse.ArgumentParser(
+        prog="sailfish batch",
+        description="run an ensemble of simulations listed in a TOML file",
+    )
+    parser.add_argument("batch_file", metavar="FILE", help="TOML file listing the runs")
+    parser.add_argument(
+        "--jobs",
+        "-j",
+        metavar="N",
+        type=int,
+        default=1,
+        help="number of runs to execute at once",
+    )
+    parser.add_argument(
+        "--gpus",
+        metavar="N",
+        type=int,
+        default=0,
+        help="assign runs round-robin to N GPU devices",
+    )
+    parser.add_argument(
+        "--outdir",
+        "-o",
+        metavar="D",
+        default=".",
+        help="directory in which each run's output directory is created",
+    )
+    parser.add_argument(
+        "--dry-run",
+        action="store_true",
+        help="print the command line of each run and exit",
+    )
+    return parser
+
+
+def main(argv):
+    """
+    Command line interface for the `sailfish batch` subcommand.
+
+    The exit status is 1 if any of the runs failed.
+    """
+    parser = argument_parser()
+    args = parser.parse_args(argv)
+
+    if args.jobs < 1:
+        parser.error("--jobs must be at least 1")
+
+    runs = load_batch_file(args.batch_file)
+
+    if args.dry_run:
+        from os.path import join
+        from shlex import quote
+
+        for run in runs:
+            argv = ["sailfish"] + run_command_line(run, join(args.outdir, run["name"]))
+            print(" ".join(map(quote, argv)))
+        return
+
+    results = run_batch(runs, outdir=args.outdir, jobs=args.jobs, gpus=args.gpus)
+    print_summary(results)
+
+    if any(r.returncode != 0 for r in results):
+        raise SystemExit(1)
@@ -0,0 +1,588 @@
+"""
+Command line interface to the simulation driver.
+
+The library parts of the driver, for running simulations from scripts or
+other applications, are in :mod:`sailfish.driver`, which does not depend on
+this module. This module adds the argument parser, the subcommands, and the
+handling of output directories, parameter sweeps, and event handler files.
+"""
+
+import os
+import re
+from logging import getLogger
+from sailfish.event import Recurrence, ParseRecurrenceError
+from sailfish.kernel.library import GpuError
+from sailfish.setup_base import SetupBase, SetupError
+from sailfish.solvers import SolverInitializationError
+from sailfish.driver import (
+    ConfigurationError,
+    DriverArgs,
+    ExtensionError,
+    TimestepError,
+    append_timeseries,
+    init_logging,
+    keyed_value,
+    load_user_config,
+    output_products,
+    resolve_setup_variant,
+    setup_variants,
+    simulate,
+    split_unquoted,
+    write_checkpoint,
+    write_lite_checkpoint,
+)
+
+logger = getLogger(__name__)
+
+
+def keyed_event(item):
+    """
+    Return a key, val pair where the value string describes a recurrence rule.
+    """
+    key, equals, val = item.partition("=")
+
+    if not equals:
+        raise ConfigurationError(f"badly formed event {item}, expected key=val")
+
+    return key, Recurrence.from_str(val)
+
+
+def keyed_sweep(item):
+    """
+    Return a key, list-of-values pair from a "key=val1,val2,..." string.
+    """
+    key, _, vals = item.partition("=")
+    return key, [keyed_value(f"{key}={val}")[1] for val in split_unquoted(vals, ",")]
+
+
+def sweep_directory_name(sweep_parameters):
+    """
+    Return a file system safe directory name for one run in a sweep, from a
+    dictionary of its swept model parameters.
+    """
+    name = ",".join(f"{k}={v}" for k, v in sweep_parameters.items())
+    return re.sub(r"[^\w.=,+-]", "_", name)
+
+
+def sweep_command_line(argv):
+    """
+    Return the command line `argv` with the sweep and output directory options
+    removed, for generating the command line of each run in a sweep.
+    """
+    result = ["sailfish"]
+    skip = False
+
+    for arg in argv[1:]:
+        if skip:
+            skip = False
+        elif arg in ("--sweep", "--outdir", "-o"):
+            skip = True
+        elif arg == "--sweep-jobs" or arg.startswith(("--sweep=", "--outdir=")):
+            pass
+        else:
+            result.append(arg)
+    return result
+
+
+def write_crash_checkpoint(outdir, state):
+    """
+    Write the last known-good state of a run which failed with a GPU error,
+    to `chkpt.crash.pk`, from which the run can be restarted explicitly.
+    Since the device may be unusable, failures to read the state from it are
+    logged rather than raised.
+    """
+    try:
+        write_checkpoint("crash", outdir, state)
+    except Exception as e:
+        logger.error(f"could not write the crash checkpoint: {e}")
+
+
+SUBCOMMANDS = dict(
+    convert="sailfish.export",
+    plot="sailfish.plot",
+    compare="sailfish.compare",
+    info="sailfish.info",
+    batch="sailfish.batch",
+    completions="sailfish.completions",
+)
+"""
+Modules implementing the subcommands, each of which has a `main(argv)`
+function, and an `argument_parser()` function used to generate shell
+completions.
+"""
+
+
+def argument_parser():
+    """
+    Return the argument parser for the main sailfish command.
+
+    Option values which are negative numbers in scientific notation must be
+    attached to the option with an equals sign, e.g. `--end-time=-1e-3`,
+    since argparse would otherwise take them for options.
+    """
+    import argparse
+    import sailfish
+    from sailfish.presets import PRESETS
+
+    class MakeDict(argparse.Action):
+        def __call__(self, parser, namespace, values, option_string=None):
+            setattr(namespace, self.dest, dict(values))
+
+    def add_dict_entry(key):
+        class AddDictEntry(argparse.Action):
+            def __call__(self, parser, namespace, values, option_string=None):
+                getattr(namespace, self.dest)[key] = values
+
+        return AddDictEntry
+
+    parser = argparse.ArgumentParser(
+        prog="sailfish",
+        usage=argparse.SUPPRESS,
+        description="sailfish is a GPU-accelerated astrophysical gasdynamics code",
+        epilog=f"other commands: sailfish [{'|'.join(SUBCOMMANDS)}] --help",
+    )
+    parser.add_argument(
+        "--version",
+        action="version",
+        version=f"%(prog)s {sailfish.__version__}",
+    )
+    parser.add_argument(
+        "command",
+        nargs="?",
+        help="setup name or restart file (if directory, then load newest checkpoint)",
+    )
+    parser.add_argument(
+        "--describe",
+        action="store_true",
+        help="print a description of the setup and exit",
+    )
+    parser.add_argument(
+        "--resolution",
+        "-n",
+        metavar="N",
+        type=int,
+        help="grid resolution",
+    )
+    parser.add_argument(
+        "--patches",
+        metavar="N",
+        type=int,
+        dest="num_patches",
+        help="number of patches for domain decomposition",
+    )
+    parser.add_argument(
+        "--cfl",
+        dest="cfl_number",
+        metavar="C",
+        type=float,
+        help="CFL parameter",
+    )
+    parser.add_argument(
+        "--preset",
+        choices=list(PRESETS),
+        help="a bundle of solver options and CFL number, which other options "
+        "override: " + "; ".join(f"{k}: {v.about}" for k, v in PRESETS.items()),
+    )
+    parser.add_argument(
+        "--fold",
+        "-f",
+        metavar="F",
+        type=int,
+        help="iterations between messages and side effects",
+    )
+    parser.add_argument(
+        "--new-timestep-cadence",
+        metavar="C",
+        type=int,
+        help="iterations between recomputing the timestep dt",
+    )
+    parser.add_argument(
+        "--cfl-retries",
+        metavar="N",
+        type=int,
+        help="retry steps violating the CFL condition up to N times, halving dt",
+    )
+    parser.add_argument(
+        "--max-dt",
+        metavar="DT",
+        type=float,
+        help="upper limit on the timestep, enforced along with the CFL condition",
+    )
+    parser.add_argument(
+        "--initial-dt-fraction",
+        metavar="F",
+        type=float,
+        help="start a new run with dt = F times the CFL limit, and let it grow",
+    )
+    parser.add_argument(
+        "--dt-ramp-steps",
+        metavar="N",
+        type=int,
+        help="ramp dt geometrically from the initial fraction (default 0.01) "
+        "to the CFL limit over N steps",
+    )
+    parser.add_argument(
+        "--events",
+        nargs="*",
+        metavar="E=V",
+        type=keyed_event,
+        action=MakeDict,
+        default=dict(),
+        help="a sequence of events and recurrence rules to be emitted",
+    )
+    parser.add_argument(
+        "--restart-dir",
+        action="store_true",
+        help="the command argument is a directory; restart from newest checkpoint therein",
+    )
+    parser.add_argument(
+        "--final-chkpt",
+        action="store_true",
+        help="write chkpt.final.pk on exit",
+    )
+    parser.add_argument(
+        "--checkpoint",
+        "-c",
+        metavar="C",
+        type=Recurrence.from_str,
+        action=add_dict_entry("checkpoint"),
+        dest="events",
+        help="checkpoint recurrence [<delta>|<log:mul>]",
+    )
+    parser.add_argument(
+        "--lite-checkpoint",
+        metavar="L",
+        type=Recurrence.from_str,
+        action=add_dict_entry("lite_checkpoint"),
+        dest="events",
+        help="restart-only checkpoint recurrence [<delta>|<log:mul>]",
+    )
+    parser.add_argument(
+        "--lite-precision",
+        choices=["f32", "f64"],
+        help="precision of the solution in lite checkpoints",
+    )
+    parser.add_argument(
+        "--lite-master-cadence",
+        metavar="N",
+        type=int,
+        help="with f32 lite checkpoints, write every Nth in f64 (default 10)",
+    )
+    parser.add_argument(
+        "--movie",
+        metavar="M",
+        type=Recurrence.from_str,
+        action=add_dict_entry("movie"),
+        dest="events",
+        help="downsampled movie frame recurrence [<delta>|<log:mul>]",
+    )
+    parser.add_argument(
+        "--movie-field",
+        metavar="F",
+        help="name of the primitive field written to movie frames",
+    )
+    parser.add_argument(
+        "--movie-size",
+        metavar="N",
+        type=int,
+        help="maximum number of zones per axis in movie frames (default 512)",
+    )
+    parser.add_argument(
+        "--timeseries",
+        "-t",
+        metavar="T",
+        type=Recurrence.from_str,
+        action=add_dict_entry("timeseries"),
+        dest="events",
+        help="timeseries recurrence [<delta>|<log:mul>]",
+    )
+    parser.add_argument(
+        "--model",
+        nargs="*",
+        metavar="K=V",
+        type=keyed_value,
+        action=MakeDict,
+        default=dict(),
+        dest="model_parameters",
+        help="key-value pairs given as models parameters to the setup",
+    )
+    parser.add_argument(
+        "--solver",
+        nargs="*",
+        metavar="K=V",
+        type=keyed_value,
+        action=MakeDict,
+        default=dict(),
+        dest="solver_options",
+        help="key-value pairs passed as options to the solver",
+    )
+    parser.add_argument(
+        "--outdir",
+        "-o",
+        metavar="D",
+        type=str,
+        dest="output_directory",
+        help="directory where checkpoints are written",
+    )
+    parser.add_argument(
+        "--end-time",
+        "-e",
+        metavar="T",
+        type=float,
+        help="when to end the simulation",
+    )
+    parser.add_argument(
+        "--event-handlers-file",
+        metavar="F",
+        type=str,
+        help="path to a module defining a get_event_handlers function",
+    )
+    parser.add_argument(
+        "--sweep",
+        metavar="K=V1,V2,...",
+        type=keyed_sweep,
+        action="append",
+        default=list(),
+        help="run each value of a model parameter (may be repeated for a grid)",
+    )
+    parser.add_argument(
+        "--sweep-jobs",
+        action="store_true",
+        help="print a command line for each run in the sweep, instead of running it",
+    )
+    parser.add_argument(
+        "--verbose-output",
+        metavar="P",
+        type=str,
+        default="",
+        help="detailed print solver structs [physics,options]",
+    )
+    exec_group = parser.add_mutually_exclusive_group()
+    exec_group.add_argument(
+        "--mode",
+        dest="execution_mode",
+        choices=["cpu", "omp", "gpu"],
+        help="execution mode",
+    )
+    exec_group.add_argument(
+        "--use-omp",
+        "-p",
+        dest="execution_mode",
+        action="store_const",
+        const="omp",
+        help="multi-core with OpenMP",
+    )
+    exec_group.add_argument(
+        "--use-gpu",
+        "-g",
+        dest="execution_mode",
+        action="store_const",
+        const="gpu",
+        help="gpu acceleration (falls back to omp or cpu if no gpu is found)",
+    )
+    parser.add_argument(
+        "--async-diagnostics",
+        action="store_true",
+        help="compute timeseries diagnostics on a worker thread",
+    )
+    parser.add_argument(
+        "--crash-snapshot-cadence",
+        metavar="N",
+        type=int,
+        help="on the GPU, keep a copy of the solver state every N folds, which "
+        "is written to chkpt.crash.pk if a CUDA error occurs",
+    )
+    parser.add_argument(
+        "--force",
+        action="store_true",
+        dest="force_restart",
+        help="on restart, allow immutable parameters to change, and migrate "
+        "the checkpoint's fields to the new configuration",
+    )
+    parser.add_argument(
+        "--require-gpu",
+        action="store_true",
+        help="exit with an error if gpu mode is requested but no gpu is found",
+    )
+    parser.add_argument(
+        "--threads",
+        metavar="N",
+        type=int,
+        dest="num_threads",
+        help="number of OpenMP threads (sets OMP_NUM_THREADS)",
+    )
+    parser.add_argument(
+        "--bind",
+        dest="thread_binding",
+        choices=["compact", "scatter"],
+        help="pin OpenMP threads to cores (sets OMP_PROC_BIND)",
+    )
+    return parser
+
+
+def main():
+    """
+    General-purpose command line interface.
+    """
+    import sys
+    import sailfish.setups
+    from sailfish.movie import write_movie_frame
+    from sailfish.pipeline import DiagnosticsPipeline
+    from sailfish.products import write_product
+
+    parser = argument_parser()
+
+    try:
+        init_logging()
+        load_user_config()
+
+        if len(sys.argv) > 1 and sys.argv[1] in SUBCOMMANDS:
+            from importlib import import_module
+
+            return import_module(SUBCOMMANDS[sys.argv[1]]).main(sys.argv[2:])
+
+        args = parser.parse_args()
+
+        if args.describe and args.command is not None:
+            setup_name = split_unquoted(args.command, ":")[0]
+            setup_name, _ = resolve_setup_variant(setup_name, None)
+            SetupBase.find_setup_class(setup_name).describe_class()
+
+        elif args.command is None:
+            print("specify setup:")
+            for setup in SetupBase.__subclasses__():
+                print(f"    {setup.dash_case_class_name()}")
+            for name, (base, _) in setup_variants.items():
+                print(f"    {name} (variant of {base})")
+
+        else:
+            driver = DriverArgs.from_namespace(args)
+            driver = driver._replace(
+                events={
+                    **{n: p.interval for n, p in output_products.items()},
+                    **driver.events,
+                }
+            )
+            outdir = (
+                args.output_directory
+                or (driver.chkpt_file and os.path.dirname(driver.chkpt_file))
+                or "."
+            )
+
+            if args.event_handlers_file is not None:
+                import importlib.util
+
+                spec = importlib.util.spec_from_file_location(
+                    "events_handler_module", args.event_handlers_file
+                )
+                events_handler_module = importlib.util.module_from_spec(spec)
+                spec.loader.exec_module(events_handler_module)
+                events_dict = events_handler_module.get_event_handlers()
+            else:
+                events_dict = dict()
+
+            def run_with_events(driver, outdir):
+                pipeline = None
+
+                for name, number, state in simulate(driver):
+                    product = output_products.get(name)
+                    kind = product.kind if product else name
+
+                    if pipeline is not None and kind != "timeseries":
+                        pipeline.flush()
+
+                    if kind == "timeseries" and driver.async_diagnostics:
+                        if pipeline is None:
+                            pipeline = DiagnosticsPipeline(state.timeseries)
+                        pipeline.submit(state.solver)
+                    elif kind == "timeseries":
+                        append_timeseries(state)
+                    elif product is not None:
+                        write_product(product, number, outdir, state)
+                    elif name == "checkpoint":
+                        write_checkpoint(number, outdir, state)
+                    elif name == "lite_checkpoint":
+                        write_lite_checkpoint(number, outdir, state)
+                    elif name == "movie":
+                        write_movie_frame(outdir, state)
+                    elif name == "end":
+                        if args.final_chkpt:
+                            write_checkpoint("final", outdir, state)
+                    elif name == "crash":
+                        write_crash_checkpoint(outdir, state)
+                    elif name in events_dict:
+                        events_dict[name](number, outdir, state, logger)
+                    else:
+                        logger.warning(f"unrecognized event {name}")
+
+                if pipeline is not None:
+                    pipeline.close()
+
+            if not args.sweep:
+                run_with_events(driver, outdir)
+            elif driver.setup_name is None:
+                raise ConfigurationError("a sweep requires a setup name, not a restart")
+            else:
+                from itertools import product
+                from shlex import quote
+
+                keys = [key for key, _ in args.sweep]
+
+                for vals in product(*(vals for _, vals in args.sweep)):
+                    sweep_parameters = dict(zip(keys, vals))
+                    sweep_name = sweep_directory_name(sweep_parameters)
+                    sweep_outdir = os.path.join(outdir, sweep_name)
+
+                    if args.sweep_jobs:
+                        argv = sweep_command_line(sys.argv)
+                        pairs = (f"{k}={v!r}" for k, v in sweep_parameters.items())
+                        command = ":".join([args.command, *pairs])
+                        argv = [command if a == args.command else a for a in argv]
+                        print(" ".join(map(quote, argv + ["-o", sweep_outdir])))
+                    else:
+                        logger.info(f"sweep: run {sweep_name}")
+                        model_parameters = dict(driver.model_parameters)
+                        model_parameters.update(sweep_parameters)
+                        run_with_events(
+                            driver._replace(model_parameters=model_parameters),
+                            sweep_outdir,
+                        )
+
+    except ConfigurationError as e:
+        print(f"bad configuration: {e}")
+        return 1
+
+    except ExtensionError as e:
+        print(f"bad extension: {e}")
+        return 1
+
+    except SetupError as e:
+        print(f"setup error: {e}")
+        return 1
+
+    except ParseRecurrenceError as e:
+        print(f"parse error: {e}")
+        return 1
+
+    except SolverInitializationError as e:
+        print(f"solver initialization error: {e}")
+        return 1
+
+    except TimestepError as e:
+        print(f"timestep error: {e}")
+        return 1
+
+    except GpuError as e:
+        print(f"gpu error: {e}")
+        return 1
+
+    except OSError as e:
+        print(f"file system error: {e}")
+        return 1
+
+    except ModuleNotFoundError as e:
+        print(f"unsatisfied dependency: {e}")
+        return 1
+
+    except KeyboardInterrupt:
+        print("")
@@ -0,0 +1,126 @@
+"""
+Compare the solution data in two checkpoint files, for the `sailfish compare`
+subcommand.
+
+This is intended for regression testing, for example to confirm that a
+refactored or GPU kernel reproduces the output of a reference run.
+"""
+
+from typing import NamedTuple
+from sailfish.export import checkpoint_fields
+
+
+class FieldDifference(NamedTuple):
+    """
+    Norms of the difference between a field in two checkpoints
+    """
+
+    field: str
+    l1: float
+    l2: float
+    linf: float
+    location: dict
+
+
+def compare_checkpoints(chkpt_a, chkpt_b):
+    """
+    Return a list of :obj:`FieldDifference` instances, one for each primitive
+    field in two checkpoint dictionaries.
+
+    The L1 and L2 norms are averages over the grid cells. The location is a
+    dictionary of the cell-center coordinates where the difference is
+    largest. The checkpoints must be from the same solver and mesh, and a
+    `ConfigurationError` is raised otherwise.
+    """
+    import numpy as np
+    from sailfish.driver import ConfigurationError
+
+    if chkpt_a["solver"] != chkpt_b["solver"]:
+        raise ConfigurationError(
+            f"checkpoints are from different solvers: "
+            f"{chkpt_a['solver']} and {chkpt_b['solver']}"
+        )
+    if chkpt_a["mesh"] != chkpt_b["mesh"]:
+        raise ConfigurationError("checkpoints have different meshes")
+
+    try:
+        fields_a = checkpoint_fields(chkpt_a, auxiliary=False)
+        fields_b = checkpoint_fields(chkpt_b, auxiliary=False)
+    except ValueError as e:
+        raise ConfigurationError(f"cannot compare checkpoints: {e}")
+
+    coordinates = [key for key in ("x", "y", "r", "theta") if key in fields_a]
+    result = []
+
+    for name in fields_a:
+        if name in coordinates:
+            continue
+
+        if name not in fields_b:
+            raise ConfigurationError(
+                f"field {name} is missing from the second checkpoint"
+            )
+
+        delta = abs(fields_a[name] - fields_b[name])
+        index = np.unravel_index(np.argmax(delta), delta.shape)
+        location = {key: float(fields_a[key][index]) for key in coordinates}
+        result.append(
+            FieldDifference(
+                field=name,
+                l1=float(delta.mean()),
+                l2=float((delta**2).mean() ** 0.5),
+                linf=float(delta[index]),
+                location=location,
+            )
+        )
+    return result
+
+
+def argument_parser():
+    """
+    Return the argument parser for the `sailfish compare` subcommand.
+    """
+    import argparse
+
+    parser = argparse.ArgumentParser(
+        prog="sailfish compare",
+        description="report the differences between two checkpoint files",
+    )
+    parser.add_argument("chkpt_a", metavar="A", help="first checkpoint file")
+    parser.add_argument("chkpt_b", metavar="B", help="second checkpoint file")
+    parser.add_argument(
+        "--tolerance",
+        metavar="T",
+        type=float,
+        help="exit with status 1 if any L-inf difference exceeds T",
+    )
+    return parser
+
+
+def main(argv):
+    """
+    Command line interface for the `sailfish compare` subcommand.
+
+    The exit status is 1 if a tolerance is given, and the largest difference
+    in any field exceeds it.
+    """
+    from sailfish.driver import load_checkpoint
+
+    parser = argument_parser()
+    args = parser.parse_args(argv)
+
+    chkpt_a = load_checkpoint(args.chkpt_a)
+    chkpt_b = load_checkpoint(args.chkpt_b)
+
+    if chkpt_a["time"] != chkpt_b["time"]:
+        print(f"warning: checkpoint times differ {chkpt_a['time']} {chkpt_b['time']}")
+
+    diffs = compare_checkpoints(chkpt_a, chkpt_b)
+
+    print(f"{'field':<16} {'L1':>12} {'L2':>12} {'Linf':>12}  location of Linf")
+    for d in diffs:
+        location = " ".join(f"{k}={v:+.4e}" for k, v in d.location.items())
+        print(f"{d.field:<16} {d.l1:12.4e} {d.l2:12.4e} {d.linf:12.4e}  {location}")
+
+    if args.tolerance is not None and any(d.linf > args.tolerance for d in diffs):
+        raise SystemExit(1)
@@ -0,0 +1,262 @@
+"""
+Generate shell completion scripts, for the `sailfish completions` subcommand.
+
+The scripts are generated from the argument parsers of the main command and
+each subcommand, so they cover every option, and the choices of options which
+have them, without being maintained by hand. The main command's positional
+argument completes to the setup names (including variants in the user
+configuration file), subcommand names, and files. To enable completions, add
+a line like this to the shell's startup file:
+
+.. code-block:: bash
+
+    source <(sailfish completions bash)  # ~/.bashrc
+    source <(sailfish completions zsh)  # ~/.zshrc, after compinit
+    sailfish completions fish | source  # ~/.config/fish/config.fish
+"""
+
+from typing import List, NamedTuple
+
+SHELLS = ("bash", "zsh", "fish")
+
+
+class Option(NamedTuple):
+    """
+    A command line option, as needed to generate completions
+    """
+
+    flags: List[str]
+    help: str
+    takes_value: bool
+    choices: List[str]
+
+
+def parser_options(parser):
+    """
+    Return a list of :obj:`Option` instances for the optional arguments of an
+    argparse parser.
+    """
+    from argparse import SUPPRESS
+
+    options = []
+
+    for action in parser._actions:
+        if not action.option_strings or action.help == SUPPRESS:
+            continue
+
+        options.append(
+            Option(
+                flags=list(action.option_strings),
+                help=(action.help or "").replace("%%", "%"),
+                takes_value=action.nargs != 0,
+                choices=[str(c) for c in action.choices or []],
+            )
+        )
+
+    return options
+
+
+def setup_names():
+    """
+    Return the names of the setups and setup variants.
+    """
+    import sailfish.setups
+    from sailfish.driver import setup_variants
+    from sailfish.setup_base import SetupBase
+
+    names = [setup.dash_case_class_name() for setup in SetupBase.__subclasses__()]
+    return names + list(setup_variants)
+
+
+def command_table():
+    """
+    Return a dictionary of the options of each command, keyed by subcommand
+    name, with the main command's options under the empty string.
+    """
+    from importlib import import_module
+    from sailfish.cli import SUBCOMMANDS, argument_parser
+
+    table = {"": parser_options(argument_parser())}
+
+    for name, module in SUBCOMMANDS.items():
+        table[name] = parser_options(import_module(module).argument_parser())
+
+    return table
+
+
+def bash_script(table, setups):
+    """
+    Return a bash completion script.
+    """
+    subcommands = [name for name in table if name]
+    lines = [
+        "# bash completion for sailfish",
+        "_sailfish()",
+        "{",
+        '    local cur="${COMP_WORDS[COMP_CWORD]}"',
+        '    local prev="${COMP_WORDS[COMP_CWORD-1]}"',
+        '    local opts=""',
+        '    case "${COMP_WORDS[1]}" in',
+    ]
+
+    for name, options in sorted(table.items(), key=lambda item: item[0] == ""):
+        flags = " ".join(f for option in options for f in option.flags)
+        lines.append(f"        {name or '*'})")
+        lines.append(f'            opts="{flags}"')
+        lines.append('            case "${prev}" in')
+
+        for option in options:
+            if option.choices:
+                words = " ".join(option.choices)
+                lines.append(f"                {'|'.join(option.flags)})")
+                reply = f'COMPREPLY=($(compgen -W "{words}" -- "${{cur}}"))'
+                lines.append(f"                    {reply}")
+                lines.append("                    return;;")
+
+        lines.append("            esac;;")
+
+    lines += [
+        "    esac",
+        '    if [[ "${cur}" == -* ]]; then',
+        '        COMPREPLY=($(compgen -W "${opts}" -- "${cur}"))',
+        "    elif [[ ${COMP_CWORD} -eq 1 ]]; then",
+        f'        local words="{" ".join(subcommands + setups)}"',
+        '        COMPREPLY=($(compgen -W "${words}" -- "${cur}")',
+        '                   $(compgen -f -- "${cur}"))',
+        "    else",
+        '        COMPREPLY=($(compgen -f -- "${cur}"))',
+        "    fi",
+        "}",
+        "complete -F _sailfish sailfish",
+    ]
+    return "\n".join(lines) + "\n"
+
+
+def zsh_script(table, setups):
+    """
+    Return a zsh completion script.
+    """
+
+    def escape(text):
+        text = text.replace("\\", "\\\\")
+        for c in "[]:":
+            text = text.replace(c, "\\" + c)
+        return text.replace("'", "'\\''")
+
+    def specs(options):
+        for option in options:
+            for flag in option.flags:
+                spec = f"{flag}[{escape(option.help)}]"
+                if option.choices:
+                    spec += f":value:({' '.join(option.choices)})"
+                elif option.takes_value:
+                    spec += ":value:_files"
+                yield f"'{spec}'"
+
+    subcommands = [name for name in table if name]
+    lines = [
+        "#compdef sailfish",
+        "_sailfish() {",
+        "    case $words[2] in",
+    ]
+
+    for name in subcommands:
+        lines.append(f"        {name})")
+        lines.append("            shift words; (( CURRENT-- ))")
+        lines.append("            _arguments \\")
+        lines += [f"                {spec} \\" for spec in specs(table[name])]
+        lines.append("                '*:file:_files';;")
+
+    lines.append("        *)")
+    lines.append("            _arguments \\")
+    lines += [f"                {spec} \\" for spec in specs(table[""])]
+    lines.append(f"                '1:command:({' '.join(subcommands + setups)})' \\")
+    lines.append("                '*:file:_files';;")
+    lines += [
+        "    esac",
+        "}",
+        "compdef _sailfish sailfish",
+    ]
+    return "\n".join(lines) + "\n"
+
+
+def fish_script(table, setups):
+    """
+    Return a fish completion script.
+    """
+
+    def escape(text):
+        return text.replace("\\", "\\\\").replace("'", "\\'")
+
+    subcommands = [name for name in table if name]
+    main_condition = f"not __fish_seen_subcommand_from {' '.join(subcommands)}"
+    words = " ".join(subcommands + setups)
+    lines = [
+        "# fish completion for sailfish",
+        "complete -c sailfish -f",
+        f"complete -c sailfish -n '__fish_use_subcommand' -a '{words}' -F",
+    ]
+
+    for name, options in table.items():
+        if name:
+            condition = f"__fish_seen_subcommand_from {name}"
+            lines.append(f"complete -c sailfish -n '{condition}' -F")
+        else:
+            condition = main_condition
+
+        for option in options:
+            line = f"complete -c sailfish -n '{condition}'"
+
+            for flag in option.flags:
+                if flag.startswith("--"):
+                    line += f" -l {flag[2:]}"
+                elif len(flag) == 2:
+                    line += f" -s {flag[1:]}"
+                else:
+                    line += f" -o {flag[1:]}"
+
+            line += f" -d '{escape(option.help)}'"
+
+            if option.choices:
+                line += f" -x -a '{' '.join(option.choices)}'"
+            elif option.takes_value:
+                line += " -r"
+
+            lines.append(line)
+
+    return "\n".join(lines) + "\n"
+
+
+def completion_script(shell):
+    """
+    Return the completion script for the given shell: bash, zsh, or fish.
+    """
+    scripts = dict(bash=bash_script, zsh=zsh_script, fish=fish_script)
+
+    if shell not in scripts:
+        raise ValueError(f"unknown shell {shell}, must be one of {SHELLS}")
+
+    return scripts[shell](command_table(), setup_names())
+
+
+def argument_parser():
+    """
+    Return the argument parser for the `sailfish completions` subcommand.
+    """
+    import argparse
+
+    parser = argparse.ArgumentParser(
+        prog="sailfish completions",
+        description="print a shell completion script for sailfish",
+    )
+    parser.add_argument("shell", choices=SHELLS, help="the shell to generate for")
+    return parser
+
+
+def main(argv):
+    """
+    Command line interface for the `sailfish completions` subcommand.
+    """
+    parser = argument_parser()
+    args = parser.parse_args(argv)
+    print(completion_script(args.shell), end="")
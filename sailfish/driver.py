@@ -1,21 +1,24 @@
 """
-Library functions and command-line access to the simulation driver.
+Library functions to run simulations, and to read and write checkpoints.
+
+The command line interface is in :mod:`sailfish.cli`; this module does not
+depend on it, except for the deprecated `main` alias, which forwards to it.
 """
 
 import os, pickle, pathlib
+from array import array
 from typing import NamedTuple, Dict
 from logging import getLogger
-from sailfish.event import Recurrence, RecurringEvent, ParseRecurrenceError
-from sailfish.setup_base import SetupBase, SetupError
+from sailfish.event import Recurrence, RecurringEvent
+from sailfish.setup_base import SetupBase
 from sailfish.solver_base import SolverBase
-from sailfish.solvers import (
-    SolverInitializationError,
-    register_solver_extension,
-    make_solver,
-)
+from sailfish.status import STATUS
+from sailfish.solvers import register_solver_extension, make_solver
 
 logger = getLogger(__name__)
 user_build_config = dict()
+setup_variants = dict()
+output_products = dict()
 
 
 class ConfigurationError(Exception):
@@ -26,32 +29,92 @@ class ExtensionError(Exception):
     """An invalid extension was specified"""
 
 
-def keyed_event(item):
+class TimestepError(Exception):
+    """A time step could not be completed within the CFL condition"""
+
+
+def split_unquoted(string, separator):
     """
-    Return a key, val pair where the value string describes a recurrence rule.
+    Split a string at each separator character which is not inside single or
+    double quotes. The quotes are kept in the parts.
+
+    This is used to split the `setup:key=val:key=val` command argument, and
+    lists of sweep values, where a quoted value (e.g. a file name or a
+    string) may contain the separator.
     """
-    key, val = item.split("=")
-    return key, Recurrence.from_str(val)
+    parts = []
+    part = ""
+    quote = None
+
+    for c in string:
+        if quote is not None:
+            part += c
+            if c == quote:
+                quote = None
+        elif c in "'\"":
+            quote = c
+            part += c
+        elif c == separator:
+            parts.append(part)
+            part = ""
+        else:
+            part += c
+
+    if quote is not None:
+        raise ConfigurationError(f"unterminated quote in {string}")
+
+    return parts + [part]
 
 
 def keyed_value(item):
     """
     Return a key, val pair from a "key=val" string.
 
-    The value string is python-eval'd so it must be a valid Python expression.
+    The value string is python-eval'd so it must be a valid Python expression,
+    such as `-5.0`, `1e3`, or `(0.1, 0.2)`. Values which are bare words, such
+    as `plummer`, are kept as strings; any other value which fails to evaluate
+    is a configuration error. Values containing the characters `:`,
+    `,`, or `=` can be given as quoted strings, e.g. `profile='run:1.dat'`.
     """
+    key, equals, val = item.partition("=")
+
+    if not equals or not key:
+        raise ConfigurationError(f"badly formed model parameter {item}")
+
     try:
-        key, val = item.split("=")
-        return key, eval(val)
+        return key, eval(val, dict(__builtins__=dict()))
 
     except NameError:
-        return key, val
+        if val.isidentifier():
+            return key, val
+        raise ConfigurationError(f"model parameter value {val} in {item} is undefined")
 
     except SyntaxError:
         raise ConfigurationError(f"badly formed model parameter value {val} in {item}")
 
-    except ValueError:
-        raise ConfigurationError(f"badly formed model parameter {item}")
+    except Exception as e:
+        raise ConfigurationError(f"model parameter value {val} in {item} failed: {e}")
+
+
+def resolve_setup_variant(setup_name, model_parameters):
+    """
+    Return the name of the setup class, and the model parameters, for a setup
+    name which may be a variant defined in the user configuration file.
+
+    The variant's parameters are overridden by the given model parameters.
+    Variants may be based on other variants.
+    """
+    model_parameters = dict(model_parameters or dict())
+    visited = set()
+
+    while setup_name in setup_variants:
+        if setup_name in visited:
+            raise ConfigurationError(f"setup variant {setup_name} is circular")
+        visited.add(setup_name)
+        setup_name, variant_parameters = setup_variants[setup_name]
+        model_parameters = {**variant_parameters, **model_parameters}
+
+    return setup_name, model_parameters
 
 
 def first_not_none(*args):
@@ -140,6 +203,34 @@ def update_where_none(new, old, frozen=[]):
 #         return d
 
 
+def checkpoint_dict(state):
+    """
+    Return a dictionary of the simulation state, as written to checkpoints.
+    """
+    return dict(
+        iteration=state.iteration,
+        time=state.solver.time,
+        timestep_dt=state.timestep_dt,
+        cfl_number=state.cfl_number,
+        solution=state.solver.solution,
+        primitive=state.solver.primitive,
+        auxiliary_fields=state.solver.auxiliary_fields(),
+        timeseries=state.timeseries,
+        run_history=state.run_history,
+        provenance=state.provenance,
+        solver=state.setup.solver,
+        solver_options=state.solver.options,
+        event_states=state.event_states,
+        driver=state.driver,
+        model_parameters=state.setup.model_parameter_dict(),
+        setup_name=state.setup.dash_case_class_name(),
+        mesh=state.mesh,
+        setup_state=state.setup.checkpoint_state(),
+        unit_system=state.setup.unit_system,
+        **state.setup.checkpoint_diagnostics(state.solver.time),
+    )
+
+
 def write_checkpoint(number, outdir, state):
     """
     Write the simulation state to a file, as a pickle.
@@ -155,14 +246,49 @@ def write_checkpoint(number, outdir, state):
         pathlib.Path(outdir).mkdir(parents=True, exist_ok=True)
         filename = os.path.join(outdir, filename)
 
-    state_checkpoint_dict = dict(
+    with open(filename, "wb") as chkpt:
+        logger.info(f"write checkpoint {chkpt.name}")
+        pickle.dump(checkpoint_dict(state), chkpt)
+
+    STATUS.update(last_checkpoint=filename)
+
+
+def write_lite_checkpoint(number, outdir, state):
+    """
+    Write a restart-only ("lite") checkpoint file, as a pickle.
+
+    Lite checkpoints contain only what is needed to restart the simulation:
+    the solution array, the driver and event states, and the setup. They have
+    no primitive, auxiliary, or diagnostic data, and no time series, so they
+    are cheap to write at a finer cadence than full checkpoints. A run
+    restarted from a lite checkpoint begins a new time series.
+
+    If the driver's lite precision is `f32`, the solution is stored in single
+    precision, except in every Nth file (the master cadence), which is stored
+    in double precision. Older lite files are removed, keeping only the
+    newest one, and the newest double precision one.
+    """
+    import numpy as np
+    from glob import glob
+
+    driver = state.driver
+    master_cadence = driver.lite_master_cadence or 10
+    single = driver.lite_precision == "f32" and number % master_cadence != 0
+    filename = f"restart.{number:04d}.f32.pk" if single else f"restart.{number:04d}.pk"
+    solution = state.solver.solution
+
+    if outdir is not None:
+        pathlib.Path(outdir).mkdir(parents=True, exist_ok=True)
+        filename = os.path.join(outdir, filename)
+
+    lite_checkpoint_dict = dict(
         iteration=state.iteration,
         time=state.solver.time,
         timestep_dt=state.timestep_dt,
         cfl_number=state.cfl_number,
-        solution=state.solver.solution,
-        primitive=state.solver.primitive,
-        timeseries=state.timeseries,
+        solution=solution.astype(np.float32) if single else solution,
+        precision="f32" if single else "f64",
+        provenance=state.provenance,
         solver=state.setup.solver,
         solver_options=state.solver.options,
         event_states=state.event_states,
@@ -170,12 +296,26 @@ def write_checkpoint(number, outdir, state):
         model_parameters=state.setup.model_parameter_dict(),
         setup_name=state.setup.dash_case_class_name(),
         mesh=state.mesh,
-        **state.setup.checkpoint_diagnostics(state.solver.time),
+        setup_state=state.setup.checkpoint_state(),
     )
 
     with open(filename, "wb") as chkpt:
-        logger.info(f"write checkpoint {chkpt.name}")
-        pickle.dump(state_checkpoint_dict, chkpt)
+        logger.info(f"write lite checkpoint {chkpt.name}")
+        pickle.dump(lite_checkpoint_dict, chkpt)
+
+    STATUS.update(last_checkpoint=filename)
+
+    def file_number(f):
+        return int(os.path.basename(f).split(".")[1])
+
+    existing = glob(os.path.join(outdir or ".", "restart.*.pk"))
+    existing.sort(key=file_number)
+    masters = [f for f in existing if not f.endswith(".f32.pk")]
+    keep = {existing[-1]} | set(masters[-1:])
+
+    for f in existing:
+        if f not in keep:
+            os.remove(f)
 
 
 def load_checkpoint(chkpt_file):
@@ -187,24 +327,145 @@ def load_checkpoint(chkpt_file):
             return pickle.load(file)
     except FileNotFoundError:
         raise ConfigurationError(f"could not open checkpoint file {chkpt_file}")
+    except (pickle.UnpicklingError, EOFError) as e:
+        raise ConfigurationError(f"checkpoint file {chkpt_file} is corrupt: {e}")
+    except (AttributeError, ImportError) as e:
+        raise ConfigurationError(
+            f"checkpoint file {chkpt_file} refers to code which no longer "
+            f"exists in this version of sailfish: {e}"
+        )
+
+
+def describe_field_count(solver, num_fields):
+    """
+    Return a description of the number of fields in a solution array, in
+    terms of passive tracers if the solver evolves them.
+    """
+    from sailfish.fields import SOLVER_FIELDS, TRACER_SOLVERS
+
+    if solver in TRACER_SOLVERS:
+        return f"{num_fields - len(SOLVER_FIELDS[solver])} tracers"
+    else:
+        return f"{num_fields} fields"
+
+
+def check_restart(chkpt, setup, mesh, force=False, initial_solution=None):
+    """
+    Return the solution array of a checkpoint, after confirming that it is
+    compatible with the configuration of the restarted run.
+
+    The checkpoint's solver, mesh, solution shape, number of fields, and
+    precision are compared with the configuration, and a
+    `ConfigurationError` is raised which lists every mismatch. Single
+    precision data is converted to double precision, with a warning.
+
+    If `force` is true, a mismatch in the number of fields is migrated
+    rather than raised: extra fields at the end of the checkpoint's solution
+    are dropped, and missing ones are taken from the solution returned by
+    the `initial_solution` function. The other mismatches cannot be migrated.
+    """
+    import numpy as np
+    from sailfish.solvers import solver_num_fields
+
+    solution = chkpt["solution"]
+    rank = len(mesh.shape)
+    errors = []
+
+    if chkpt["solver"] != setup.solver:
+        errors.append(
+            f"checkpoint is from solver {chkpt['solver']}, "
+            f"configuration uses solver {setup.solver}"
+        )
+    if chkpt["mesh"] != mesh:
+        errors.append(f"checkpoint mesh is {chkpt['mesh']}, configuration is {mesh}")
+    if solution.shape[:rank] != mesh.shape:
+        errors.append(
+            f"checkpoint solution has shape {solution.shape}, "
+            f"mesh has shape {mesh.shape}"
+        )
+    if solution.dtype.kind != "f":
+        errors.append(f"checkpoint solution has data type {solution.dtype}")
+
+    num_fields = solution.shape[-1] if solution.ndim > rank else None
+    expected = solver_num_fields(setup.solver, setup.physics)
+    field_mismatch = errors == [] and None not in (num_fields, expected)
+    field_mismatch = field_mismatch and num_fields != expected
+
+    if field_mismatch:
+        message = (
+            f"checkpoint has {describe_field_count(setup.solver, num_fields)}, "
+            f"configuration requests {describe_field_count(setup.solver, expected)}"
+        )
+        if force:
+            logger.warning(f"{message}; migrating the solution (--force)")
+        else:
+            errors.append(message)
+
+    if errors:
+        raise ConfigurationError(
+            "checkpoint is not compatible with the configuration:\n    "
+            + "\n    ".join(errors)
+            + ("\n(use --force to migrate the solution)" if field_mismatch else "")
+        )
+
+    if solution.dtype != np.float64:
+        logger.warning(
+            "restart from single precision data; the run will not be "
+            "identical to a continuous one"
+        )
+        solution = solution.astype(np.float64)
+
+    if field_mismatch and num_fields > expected:
+        solution = solution[..., :expected].copy()
+
+    elif field_mismatch:
+        migrated = np.array(initial_solution())
+        migrated[..., :num_fields] = solution
+        solution = migrated
+
+    return solution
+
+
+def file_sha256(filename):
+    """
+    Return the SHA-256 hex digest of a file's contents.
+    """
+    from hashlib import sha256
+
+    digest = sha256()
+
+    with open(filename, "rb") as f:
+        for block in iter(lambda: f.read(1 << 20), b""):
+            digest.update(block)
+
+    return digest.hexdigest()
 
 
 def newest_chkpt_in_directory(directory_name):
+    """
+    Return the path to the newest usable checkpoint in a directory.
+
+    The newest full checkpoint is preferred, even if a lite checkpoint is
+    newer, since a lite checkpoint has no time series or run history, and
+    restarting from it would drop them. The newest lite checkpoint is used
+    only if the directory has no usable full checkpoint.
+    """
     import re
 
-    expr = re.compile("chkpt\.([0-9]+)\.pk")
-    list_of_matches = list(
-        filter(None, (expr.search(f) for f in os.listdir(directory_name)))
-    )
-    list_of_matches.sort(key=lambda l: int(l.groups()[0]))
+    for pattern in (r"chkpt\.([0-9]+)\.pk", r"restart\.([0-9]+)(\.f32)?\.pk"):
+        expr = re.compile(pattern)
+        list_of_matches = list(
+            filter(None, (expr.fullmatch(f) for f in os.listdir(directory_name)))
+        )
+        list_of_matches.sort(key=lambda l: int(l.groups()[0]))
 
-    for match in reversed(list_of_matches):
-        try:
-            path = os.path.join(directory_name, match.group())
-            load_checkpoint(path)  # exception if checkpoint is corrupted
-            return path
-        except:
-            logger.warning(f"skipping corrupt checkpoint file {path}")
+        for match in reversed(list_of_matches):
+            try:
+                path = os.path.join(directory_name, match.group())
+                load_checkpoint(path)  # exception if checkpoint is corrupted
+                return path
+            except:
+                logger.warning(f"skipping corrupt checkpoint file {path}")
 
     raise ConfigurationError("the specified directory did not have a usable checkpoint")
 
@@ -235,13 +496,28 @@ class DriverArgs(NamedTuple):
     model_parameters: dict = None
     solver_options: dict = None
     cfl_number: float = None
+    preset: str = None
     end_time: float = None
     execution_mode: str = None
+    require_gpu: bool = False
+    force_restart: bool = False
+    num_threads: int = None
+    thread_binding: str = None
     fold: int = None
     resolution: int = None
     num_patches: int = None
     events: Dict[str, Recurrence] = dict()
     new_timestep_cadence: int = None
+    cfl_retries: int = None
+    max_dt: float = None
+    initial_dt_fraction: float = None
+    dt_ramp_steps: int = None
+    async_diagnostics: bool = False
+    crash_snapshot_cadence: int = None
+    lite_precision: str = None
+    lite_master_cadence: int = None
+    movie_field: str = None
+    movie_size: int = None
     verbose_output: str = ""
 
     def from_namespace(args):
@@ -251,7 +527,7 @@ class DriverArgs(NamedTuple):
         driver = DriverArgs(
             **{k: w for k, w in vars(args).items() if k in DriverArgs._fields}
         )
-        parts = args.command.split(":")
+        parts = split_unquoted(args.command, ":")
 
         if args.restart_dir:
             setup_name = None
@@ -263,10 +539,7 @@ class DriverArgs(NamedTuple):
             setup_name = parts[0]
             chkpt_file = None
 
-        try:
-            model_parameters = dict(keyed_value(a) for a in parts[1:])
-        except IndexError:
-            model_parameters = dict()
+        model_parameters = dict(keyed_value(a) for a in parts[1:])
 
         model_parameters.update(args.model_parameters)
         return driver._replace(
@@ -276,6 +549,90 @@ class DriverArgs(NamedTuple):
         )
 
 
+class RunHistory(NamedTuple):
+    """
+    A record of the timestep and performance after each fold of iterations.
+
+    The history is written to checkpoints and continued on restarts, so the
+    timestep and performance over a whole run can be analyzed afterwards,
+    e.g. to find where the timestep collapsed. Each field is an array of
+    doubles with one entry per fold.
+    """
+
+    iteration: array
+    """ iteration number at the end of the fold """
+
+    time: array
+    """ user time at the end of the fold """
+
+    dt: array
+    """ the most recent timestep in the fold """
+
+    wall_time: array
+    """ wall time in seconds taken by the fold """
+
+    mzps: array
+    """ million zones updated per second over the fold """
+
+    @classmethod
+    def empty(cls):
+        return cls(*(array("d") for _ in cls._fields))
+
+    def append(self, iteration, time, dt, wall_time, mzps):
+        for field, value in zip(self, (iteration, time, dt, wall_time, mzps)):
+            field.append(value)
+
+    def timestep_collapses(self, factor=10.0):
+        """
+        Return a list of the iterations at the end of folds where the timestep
+        fell by more than the given factor from the previous fold.
+        """
+        return [
+            int(self.iteration[n])
+            for n in range(1, len(self.dt))
+            if self.dt[n] * factor < self.dt[n - 1]
+        ]
+
+
+class ProvenanceRecord(NamedTuple):
+    """
+    Describes one restart of a run: the checkpoint it was restarted from,
+    and what was changed on the command line.
+
+    Each restart appends a record to the provenance chain, which is written
+    to subsequent checkpoints, so the history of a run that was restarted
+    many times can be reconstructed from its final checkpoint. A run that
+    was not restarted has an empty chain.
+    """
+
+    parent: str
+    """ path of the checkpoint the run was restarted from """
+
+    parent_sha256: str
+    """ SHA-256 digest of the parent checkpoint file """
+
+    iteration: int
+    """ iteration number at the restart """
+
+    time: float
+    """ simulation time at the restart """
+
+    model_parameters: dict
+    """ model parameters given on the command line at the restart """
+
+    solver_options: dict
+    """ solver options given on the command line at the restart """
+
+    driver_args: dict
+    """ driver arguments which differ from those of the parent run """
+
+    version: str
+    """ sailfish version of the restarted run """
+
+    date: str
+    """ date and time of the restart, in ISO format """
+
+
 class DriverState(NamedTuple):
     """
     Contains the stateful variables in use by the `simulate` function.
@@ -293,6 +650,8 @@ class DriverState(NamedTuple):
     setup: SetupBase
     cfl_number: float
     timestep_dt: float
+    run_history: RunHistory
+    provenance: list
 
 
 def simulate(driver):
@@ -304,11 +663,31 @@ def simulate(driver):
     paramters, and keyword arguments.
 
     This function is a generator: it yields its state at a sequence of
-    pause points, defined by the `events` dictionary.
+    pause points, defined by the `events` dictionary. Its progress is also
+    published to :obj:`sailfish.status.STATUS`, which other threads may poll.
+
+    If a CUDA error occurs on the GPU, the error is raised again as a
+    :obj:`sailfish.kernel.library.GpuError`. If `crash_snapshot_cadence` is
+    set, a device copy of the solver state is kept every that many folds;
+    the solver is then restored to the most recent copy, if the solver
+    supports it and the device is still usable, and a `crash` event is
+    yielded with that state before the error is raised. The timeseries and
+    event states are rolled back along with the solver, so the crash state
+    is consistent, e.g. for a checkpoint to be restarted from.
     """
+    from datetime import datetime
     from sailfish import __version__ as version
-    from sailfish.kernel.system import configure_build, log_system_info, measure_time
+    from sailfish.kernel.system import (
+        configure_build,
+        configure_threads,
+        enable_peer_access,
+        gpu_available,
+        log_system_info,
+        measure_time,
+    )
     from sailfish.event import Recurrence
+    from sailfish.kernel.library import GpuError, cuda_error_types
+    from sailfish.presets import preset_cfl_number, preset_solver_options
     from sailfish import solvers
 
     main_logger = getLogger("main_logger")
@@ -321,10 +700,16 @@ def simulate(driver):
         """
 
         logger.info(f"start new simulation with setup {driver.setup_name}")
-        setup = SetupBase.find_setup_class(driver.setup_name)(
-            **driver.model_parameters or dict()
+        setup_name, model_parameters = resolve_setup_variant(
+            driver.setup_name, driver.model_parameters
         )
+        setup = SetupBase.find_setup_class(setup_name)(**model_parameters)
         driver = driver._replace(
+            setup_name=setup_name,
+            model_parameters=model_parameters,
+            solver_options=preset_solver_options(
+                driver.preset, setup.solver, driver.solver_options
+            ),
             resolution=driver.resolution or setup.default_resolution,
         )
 
@@ -332,7 +717,10 @@ def simulate(driver):
         time = setup.start_time
         event_states = {name: RecurringEvent() for name in driver.events}
         solution = None
+        chkpt = None
         timeseries = list()
+        run_history = RunHistory.empty()
+        provenance = list()
         dt = None
 
     elif driver.chkpt_file:
@@ -346,12 +734,29 @@ def simulate(driver):
         logger.info(f"load checkpoint {driver.chkpt_file}")
         chkpt = load_checkpoint(driver.chkpt_file)
         setup_class = SetupBase.find_setup_class(chkpt["setup_name"])
+        restart_args = driver._replace(
+            model_parameters=dict(driver.model_parameters or {}),
+            solver_options=dict(driver.solver_options or {}),
+        )
         driver = update_where_none(driver, chkpt["driver"], frozen=["resolution"])
+        driver = driver._replace(
+            solver_options=preset_solver_options(
+                restart_args.preset, chkpt["solver"], restart_args.solver_options
+            )
+        )
+
+        immutable = list(setup_class.immutable_parameter_keys())
+
+        if restart_args.force_restart:
+            for key in immutable:
+                val = driver.model_parameters.get(key)
+                if val is not None and val != chkpt["model_parameters"].get(key):
+                    logger.warning(f"changing immutable parameter {key} (--force)")
 
         update_dict_where_none(
             driver.model_parameters,
             chkpt["model_parameters"],
-            frozen=list(setup_class.immutable_parameter_keys()),
+            frozen=[] if restart_args.force_restart else immutable,
         )
 
         update_dict_where_none(
@@ -361,10 +766,12 @@ def simulate(driver):
 
         setup = setup_class(**driver.model_parameters)
 
+        if chkpt.get("setup_state") is not None:
+            setup.restore_state(chkpt["setup_state"])
+
         iteration = chkpt["iteration"]
         time = chkpt["time"]
         event_states = chkpt["event_states"]
-        solution = chkpt["solution"]
 
         try:
             dt = chkpt["timestep_dt"]
@@ -388,7 +795,31 @@ def simulate(driver):
         try:
             timeseries = chkpt["timeseries"]
         except KeyError:
-            logger.warning("older checkpoint version: no timeseries")
+            logger.warning("no timeseries in checkpoint, starting a new one")
+            timeseries = list()
+
+        run_history = chkpt.get("run_history") or RunHistory.empty()
+        provenance = list(chkpt.get("provenance") or [])
+        provenance.append(
+            ProvenanceRecord(
+                parent=os.path.abspath(restart_args.chkpt_file),
+                parent_sha256=file_sha256(restart_args.chkpt_file),
+                iteration=iteration,
+                time=time,
+                model_parameters=restart_args.model_parameters,
+                solver_options=restart_args.solver_options,
+                driver_args={
+                    key: value
+                    for key, value in restart_args._asdict().items()
+                    if key not in ("chkpt_file", "model_parameters", "solver_options")
+                    and value is not None
+                    and value != getattr(chkpt["driver"], key, None)
+                },
+                version=version,
+                date=datetime.now().isoformat(timespec="seconds"),
+            )
+        )
+        logger.info(f"restart {len(provenance)} of this run")
 
         for event in driver.events:
             if event not in event_states:
@@ -401,14 +832,18 @@ def simulate(driver):
     This line ensures that if a checkpoint event is present, then it is
     emitted last, ensuring that any modifications to the driver state (e.g.
     time series sample) happening in response to other events triggered in the
-    same iteration, are reflected in the checkpoint file that is written.
+    same iteration, are reflected in the checkpoint file that is written. The
+    same is done for any output products which are checkpoints.
 
     Note: The Python 3.7+ specifications guarantee to that dictionary
     iteration order reflects the insertion order. This behavior is also
     present in the CPython implementation of Python 3.6
     """
-    if "checkpoint" in event_states:
-        event_states["checkpoint"] = event_states.pop("checkpoint")
+    for name in list(event_states):
+        product = output_products.get(name)
+
+        if name == "checkpoint" or product and product.kind == "checkpoint":
+            event_states[name] = event_states.pop(name)
 
     """
     Initialize and log state in the system module. The build system influences
@@ -416,17 +851,48 @@ def simulate(driver):
     the platform (Linux or MacOS), but in the future these should also be
     extensible by a system-specific rc-style configuration file.
     """
-    configure_build(**user_build_config, execution_mode=driver.execution_mode)
-    log_system_info(driver.execution_mode or "cpu")
-
     mode = driver.execution_mode or "cpu"
+
+    if mode == "gpu" and not gpu_available():
+        if driver.require_gpu:
+            raise ConfigurationError("gpu mode was requested, but no gpu was found")
+        openmp = user_build_config.get("enable_openmp", True) in (True, "True")
+        mode = "omp" if openmp else "cpu"
+        logger.warning(f"no gpu was found; falling back to {mode} mode")
+
+    driver = driver._replace(execution_mode=mode)
+    configure_build(**user_build_config, execution_mode=mode)
+    log_system_info(mode)
+    enable_peer_access(mode)
+
+    if mode == "omp":
+        configure_threads(driver.num_threads, driver.thread_binding)
+    elif driver.num_threads is not None or driver.thread_binding is not None:
+        logger.warning("thread count and binding are ignored unless mode is omp")
     fold = driver.fold or 10
     mesh = setup.mesh(driver.resolution)
     end_time = first_not_none(driver.end_time, setup.default_end_time, float("inf"))
     reference_time = setup.reference_time_scale
     new_timestep_cadence = driver.new_timestep_cadence or 1
+    max_dt = driver.max_dt or float("inf")
+    cfl_retries = driver.cfl_retries or 0
+    dt_ramp_steps = driver.dt_ramp_steps
+    initial_dt_fraction = driver.initial_dt_fraction
+
+    if dt_ramp_steps is not None and initial_dt_fraction is None:
+        initial_dt_fraction = 0.01
+
+    dt_ramp = initial_dt_fraction is not None and iteration == 0
+    dt_growth = 1.1
     dt = None
 
+    if initial_dt_fraction is not None:
+        if not 0.0 < initial_dt_fraction <= 1.0:
+            raise ConfigurationError("initial dt fraction must be in (0, 1]")
+
+    if dt_ramp_steps is not None and dt_ramp_steps < 1:
+        raise ConfigurationError("dt ramp steps must be a positive integer")
+
     if "physics" in driver.verbose_output:
         logger.info(f"physics struct (setup -> solver) {setup.physics}")
     if (
@@ -436,17 +902,29 @@ def simulate(driver):
     ):
         logger.info(f"options struct (cmdline -> solver) {driver.solver_options}")
 
-    solver = make_solver(
-        setup.solver,
-        setup.physics,
-        driver.solver_options,
-        setup=setup,
-        mesh=mesh,
-        time=time,
-        solution=solution,
-        num_patches=driver.num_patches or 1,
-        mode=mode,
-    )
+    def new_solver(solution):
+        return make_solver(
+            setup.solver,
+            setup.physics,
+            driver.solver_options,
+            setup=setup,
+            mesh=mesh,
+            time=time,
+            solution=solution,
+            num_patches=driver.num_patches or 1,
+            mode=mode,
+        )
+
+    if chkpt is not None:
+        solution = check_restart(
+            chkpt,
+            setup,
+            mesh,
+            force=driver.force_restart,
+            initial_solution=lambda: new_solver(None).solution,
+        )
+
+    solver = new_solver(solution)
 
     if driver.cfl_number is not None and driver.cfl_number > solver.maximum_cfl:
         raise ConfigurationError(
@@ -455,7 +933,11 @@ def simulate(driver):
             f"max allowed by solver {setup.solver}"
         )
 
-    cfl_number = driver.cfl_number or solver.recommended_cfl
+    cfl_number = (
+        driver.cfl_number
+        or preset_cfl_number(driver.preset, solver)
+        or solver.recommended_cfl
+    )
 
     for name, event in driver.events.items():
         logger.info(f"recurrence for {name} event is {event}")
@@ -464,8 +946,49 @@ def simulate(driver):
     logger.info(f"CFL number is {cfl_number}")
     logger.info(f"simulation time / user time is {reference_time:0.4f}")
     logger.info(f"recompute dt every {new_timestep_cadence} iterations")
+
+    if driver.max_dt is not None:
+        logger.info(f"timestep is limited to dt <= {max_dt}")
+    if dt_ramp and dt_ramp_steps is not None:
+        logger.info(
+            f"initial dt is {initial_dt_fraction} of the CFL limit, "
+            f"growing geometrically to the full limit over {dt_ramp_steps} steps"
+        )
+    elif dt_ramp:
+        logger.info(
+            f"initial dt is {initial_dt_fraction} of the CFL limit, "
+            f"growing by at most a factor {dt_growth} per recompute"
+        )
     setup.print_model_parameters(newlines=True, logger=main_logger)
 
+    if cfl_retries > 0 and solver.save_state() is None:
+        raise ConfigurationError(f"solver {setup.solver} does not support cfl retries")
+
+    def advance_with_retry(dt):
+        """
+        Advance the solver by one iteration. If the new wavespeeds show that
+        the step violated the CFL condition, then the step is discarded and
+        retried with half the timestep. Returns the timestep that was used.
+        """
+        if cfl_retries == 0:
+            solver.advance(dt)
+            return dt
+
+        state = solver.save_state()
+        dx = mesh.min_spacing(solver.time)
+
+        for _ in range(cfl_retries + 1):
+            solver.advance(dt)
+
+            if solver.maximum_wavespeed() * dt <= solver.maximum_cfl * dx:
+                return dt
+
+            logger.warning(f"CFL condition violated with dt={dt:.3e}, retry with dt/2")
+            solver.restore_state(state)
+            dt *= 0.5
+
+        raise TimestepError(f"CFL condition violated after {cfl_retries} retries")
+
     def grab_state():
         """
         Collect items from the driver and solver state, as well as run
@@ -481,40 +1004,123 @@ def simulate(driver):
             setup=setup,
             cfl_number=cfl_number,
             timestep_dt=dt,
+            run_history=run_history,
+            provenance=provenance,
         )
 
-    while True:
-        siml_time = solver.time
-        user_time = siml_time / reference_time
+    STATUS.reset(
+        setup_name=setup.dash_case_class_name(),
+        running=True,
+        iteration=iteration,
+        time=solver.time / reference_time,
+    )
 
-        """
-        Run the main simulation loop. Iterations are grouped according the
-        the fold parameter. Side effects including the iteration message are
-        performed between fold boundaries.
-        """
+    # On the GPU, a device copy of the solver state is optionally kept at the
+    # start of every few folds, so the state can be recovered if a CUDA error
+    # occurs. The copy costs a full pass over device memory, so it is off by
+    # default.
+    gpu_errors = (GpuError, *cuda_error_types()) if mode == "gpu" else (GpuError,)
+    crash_snapshot_cadence = driver.crash_snapshot_cadence if mode == "gpu" else None
+    known_good = None
+    num_folds = 0
 
-        for name in event_states:
-            event = driver.events[name]
-            state = event_states[name]
-            if event_states[name].is_due(user_time, event):
-                event_states[name] = state.next(user_time, event)
-                yield name, state.number, grab_state()
-
-        if end_time is not None and user_time >= end_time:
-            break
-
-        with measure_time(mode) as fold_time:
-            for _ in range(fold):
-                if dt is None or (iteration % new_timestep_cadence == 0):
-                    dx = mesh.min_spacing(siml_time)
-                    dt = dx / solver.maximum_wavespeed() * cfl_number
-                solver.advance(dt)
-                iteration += 1
-
-        Mzps = mesh.num_total_zones / fold_time() * 1e-6 * fold
-        main_logger.info(
-            f"[{iteration:04d}] t={user_time:0.3f} dt={dt:.3e} Mzps={Mzps:.3f}"
-        )
+    try:
+        while True:
+            siml_time = solver.time
+            user_time = siml_time / reference_time
+
+            """
+            Run the main simulation loop. Iterations are grouped according
+            the the fold parameter. Side effects including the iteration
+            message are performed between fold boundaries.
+            """
+
+            for name in event_states:
+                event = driver.events[name]
+                state = event_states[name]
+                if event_states[name].is_due(user_time, event):
+                    event_states[name] = state.next(user_time, event)
+                    yield name, state.number, grab_state()
+
+            if end_time is not None and user_time >= end_time:
+                break
+
+            if crash_snapshot_cadence and num_folds % crash_snapshot_cadence == 0:
+                solver_state = solver.save_state()
+
+                if solver_state is not None:
+                    events = dict(event_states)
+                    known_good = (iteration, dt, solver_state, len(timeseries), events)
+
+            num_folds += 1
+
+            with measure_time(mode) as fold_time:
+                for _ in range(fold):
+                    if (
+                        dt is None
+                        or (dt_ramp and dt_ramp_steps is not None)
+                        or (iteration % new_timestep_cadence == 0)
+                    ):
+                        dx = mesh.min_spacing(siml_time)
+                        dt_max = dx / solver.maximum_wavespeed() * cfl_number
+                        dt_max = min(dt_max, max_dt)
+
+                        if dt_ramp and dt_ramp_steps is not None:
+                            ramp = max(1.0 - iteration / dt_ramp_steps, 0.0)
+                            dt = dt_max * initial_dt_fraction**ramp
+                            dt_ramp = iteration < dt_ramp_steps
+                        elif dt_ramp and dt is None:
+                            dt = dt_max * initial_dt_fraction
+                        elif dt_ramp:
+                            dt = min(dt * dt_growth, dt_max)
+                            dt_ramp = dt < dt_max
+                        else:
+                            dt = dt_max
+                    dt = advance_with_retry(dt)
+                    iteration += 1
+
+            wall_time = fold_time()
+            Mzps = mesh.num_total_zones / wall_time * 1e-6 * fold
+            run_history.append(
+                iteration, solver.time / reference_time, dt, wall_time, Mzps
+            )
+            main_logger.info(
+                f"[{iteration:04d}] t={user_time:0.3f} dt={dt:.3e} Mzps={Mzps:.3f}"
+            )
+            STATUS.update(
+                iteration=iteration,
+                time=solver.time / reference_time,
+                dt=dt,
+                mzps=Mzps,
+            )
+    except GeneratorExit:
+        STATUS.update(running=False)
+        raise
+    except gpu_errors as e:
+        STATUS.update(running=False, error=f"{type(e).__name__}: {e}")
+        logger.error(f"gpu error at iteration {iteration}: {e}")
+
+        if known_good is not None:
+            try:
+                iteration, dt, solver_state, num_samples, events = known_good
+                solver.restore_state(solver_state)
+                del timeseries[num_samples:]
+                event_states.clear()
+                event_states.update(events)
+            except gpu_errors as recovery_error:
+                logger.error(f"could not recover the solver state: {recovery_error}")
+            else:
+                logger.info(f"recovered the solver state at iteration {iteration}")
+                yield "crash", None, grab_state()
+
+        if isinstance(e, GpuError):
+            raise
+        raise GpuError(str(e)) from e
+    except Exception as e:
+        STATUS.update(running=False, error=f"{type(e).__name__}: {e}")
+        raise
+
+    STATUS.update(running=False)
 
     yield "end", None, grab_state()
 
@@ -587,9 +1193,24 @@ def load_user_config():
     to load custom setups provided by the user. Extensions are defined in the
     `extensions` section of the .sailfish file. The .sailfish file is loaded
     from the current working directory.
+
+    Setup variants are defined in sections named `variant.<name>`, with a
+    `base` key naming the setup (or another variant), and the remaining keys
+    overriding its model parameters, e.g.
+
+    .. code-block:: ini
+
+        [variant.thin-disk]
+        base = circumbinary-disk
+        mach_number = 40.0
+        nu = 1e-4
+
+    Output products are defined in sections named `output.<name>`; see
+    :py:mod:`sailfish.products`.
     """
     from configparser import ConfigParser, ParsingError
     from importlib import import_module
+    from sailfish.products import ProductConfigError, product_from_config
 
     try:
         config = ConfigParser()
@@ -613,263 +1234,42 @@ def load_user_config():
         except KeyError:
             pass
 
+        for section in config.sections():
+            if section.startswith("variant."):
+                items = dict(config[section])
+
+                if "base" not in items:
+                    raise ConfigurationError(f"section [{section}] needs a base")
+
+                base = items.pop("base")
+                parameters = dict(keyed_value(f"{k}={v}") for k, v in items.items())
+                setup_variants[section[len("variant.") :]] = (base, parameters)
+
+            elif section.startswith("output."):
+                name = section[len("output.") :]
+                output_products[name] = product_from_config(name, config[section])
+
     except ModuleNotFoundError as e:
         raise ExtensionError(e)
 
+    except ProductConfigError as e:
+        raise ConfigurationError(e)
+
     except ParsingError as e:
         raise ConfigurationError(e)
 
 
 def main():
     """
-    General-purpose command line interface.
+    Deprecated alias of :func:`sailfish.cli.main`, kept so that existing
+    scripts which import the entry point from this module continue to work.
     """
-    import argparse
-    import sailfish
-    import sailfish.setups
-
-    class MakeDict(argparse.Action):
-        def __call__(self, parser, namespace, values, option_string=None):
-            setattr(namespace, self.dest, dict(values))
-
-    def add_dict_entry(key):
-        class AddDictEntry(argparse.Action):
-            def __call__(self, parser, namespace, values, option_string=None):
-                getattr(namespace, self.dest)[key] = values
+    from warnings import warn
+    from sailfish.cli import main
 
-        return AddDictEntry
-
-    parser = argparse.ArgumentParser(
-        prog="sailfish",
-        usage=argparse.SUPPRESS,
-        description="sailfish is a GPU-accelerated astrophysical gasdynamics code",
-    )
-    parser.add_argument(
-        "--version",
-        action="version",
-        version=f"%(prog)s {sailfish.__version__}",
-    )
-    parser.add_argument(
-        "command",
-        nargs="?",
-        help="setup name or restart file (if directory, then load newest checkpoint)",
-    )
-    parser.add_argument(
-        "--describe",
-        action="store_true",
-        help="print a description of the setup and exit",
-    )
-    parser.add_argument(
-        "--resolution",
-        "-n",
-        metavar="N",
-        type=int,
-        help="grid resolution",
-    )
-    parser.add_argument(
-        "--patches",
-        metavar="N",
-        type=int,
-        dest="num_patches",
-        help="number of patches for domain decomposition",
-    )
-    parser.add_argument(
-        "--cfl",
-        dest="cfl_number",
-        metavar="C",
-        type=float,
-        help="CFL parameter",
-    )
-    parser.add_argument(
-        "--fold",
-        "-f",
-        metavar="F",
-        type=int,
-        help="iterations between messages and side effects",
-    )
-    parser.add_argument(
-        "--new-timestep-cadence",
-        metavar="C",
-        type=int,
-        help="iterations between recomputing the timestep dt",
-    )
-    parser.add_argument(
-        "--events",
-        nargs="*",
-        metavar="E=V",
-        type=keyed_event,
-        action=MakeDict,
-        default=dict(),
-        help="a sequence of events and recurrence rules to be emitted",
-    )
-    parser.add_argument(
-        "--restart-dir",
-        action="store_true",
-        help="the command argument is a directory; restart from newest checkpoint therein",
-    )
-    parser.add_argument(
-        "--final-chkpt",
-        action="store_true",
-        help="write chkpt.final.pk on exit",
-    )
-    parser.add_argument(
-        "--checkpoint",
-        "-c",
-        metavar="C",
-        type=Recurrence.from_str,
-        action=add_dict_entry("checkpoint"),
-        dest="events",
-        help="checkpoint recurrence [<delta>|<log:mul>]",
-    )
-    parser.add_argument(
-        "--timeseries",
-        "-t",
-        metavar="T",
-        type=Recurrence.from_str,
-        action=add_dict_entry("timeseries"),
-        dest="events",
-        help="timeseries recurrence [<delta>|<log:mul>]",
-    )
-    parser.add_argument(
-        "--model",
-        nargs="*",
-        metavar="K=V",
-        type=keyed_value,
-        action=MakeDict,
-        default=dict(),
-        dest="model_parameters",
-        help="key-value pairs given as models parameters to the setup",
+    warn(
+        "sailfish.driver.main is deprecated, use sailfish.cli.main",
+        DeprecationWarning,
+        stacklevel=2,
     )
-    parser.add_argument(
-        "--solver",
-        nargs="*",
-        metavar="K=V",
-        type=keyed_value,
-        action=MakeDict,
-        default=dict(),
-        dest="solver_options",
-        help="key-value pairs passed as options to the solver",
-    )
-    parser.add_argument(
-        "--outdir",
-        "-o",
-        metavar="D",
-        type=str,
-        dest="output_directory",
-        help="directory where checkpoints are written",
-    )
-    parser.add_argument(
-        "--end-time",
-        "-e",
-        metavar="T",
-        type=float,
-        help="when to end the simulation",
-    )
-    parser.add_argument(
-        "--event-handlers-file",
-        metavar="F",
-        type=str,
-        help="path to a module defining a get_event_handlers function",
-    )
-    parser.add_argument(
-        "--verbose-output",
-        metavar="P",
-        type=str,
-        default="",
-        help="detailed print solver structs [physics,options]",
-    )
-    exec_group = parser.add_mutually_exclusive_group()
-    exec_group.add_argument(
-        "--mode",
-        dest="execution_mode",
-        choices=["cpu", "omp", "gpu"],
-        help="execution mode",
-    )
-    exec_group.add_argument(
-        "--use-omp",
-        "-p",
-        dest="execution_mode",
-        action="store_const",
-        const="omp",
-        help="multi-core with OpenMP",
-    )
-    exec_group.add_argument(
-        "--use-gpu",
-        "-g",
-        dest="execution_mode",
-        action="store_const",
-        const="gpu",
-        help="gpu acceleration",
-    )
-
-    try:
-        init_logging()
-        load_user_config()
-
-        args = parser.parse_args()
-
-        if args.describe and args.command is not None:
-            setup_name = args.command.split(":")[0]
-            SetupBase.find_setup_class(setup_name).describe_class()
-
-        elif args.command is None:
-            print("specify setup:")
-            for setup in SetupBase.__subclasses__():
-                print(f"    {setup.dash_case_class_name()}")
-
-        else:
-            driver = DriverArgs.from_namespace(args)
-            outdir = (
-                args.output_directory
-                or (driver.chkpt_file and os.path.dirname(driver.chkpt_file))
-                or "."
-            )
-
-            if args.event_handlers_file is not None:
-                import importlib.util
-
-                spec = importlib.util.spec_from_file_location(
-                    "events_handler_module", args.event_handlers_file
-                )
-                events_handler_module = importlib.util.module_from_spec(spec)
-                spec.loader.exec_module(events_handler_module)
-                events_dict = events_handler_module.get_event_handlers()
-            else:
-                events_dict = dict()
-
-            for name, number, state in simulate(driver):
-                if name == "timeseries":
-                    append_timeseries(state)
-                elif name == "checkpoint":
-                    write_checkpoint(number, outdir, state)
-                elif name == "end":
-                    if args.final_chkpt:
-                        write_checkpoint("final", outdir, state)
-                elif name in events_dict:
-                    events_dict[name](number, outdir, state, logger)
-                else:
-                    logger.warning(f"unrecognized event {name}")
-
-    except ConfigurationError as e:
-        print(f"bad configuration: {e}")
-
-    except ExtensionError as e:
-        print(f"bad extension: {e}")
-
-    except SetupError as e:
-        print(f"setup error: {e}")
-
-    except ParseRecurrenceError as e:
-        print(f"parse error: {e}")
-
-    except SolverInitializationError as e:
-        print(f"solver initialization error: {e}")
-
-    except OSError as e:
-        print(f"file system error: {e}")
-
-    except ModuleNotFoundError as e:
-        print(f"unsatisfied dependency: {e}")
-
-    except KeyboardInterrupt:
-        print("")
+    return main()
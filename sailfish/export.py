@@ -0,0 +1,441 @@
+"""
+Functions to convert checkpoint files to analysis-friendly file formats.
+
+Checkpoints are read with the same function used by the driver to restart
+simulations, so any checkpoint which can be restarted can also be converted.
+The supported formats are HDF5 (requires h5py), legacy VTK structured grids,
+CSV tables with one row per grid cell, and zstd-compressed msgpack frames for
+web viewers (requires msgpack and zstandard). Data is written in code units,
+or if the setup defined a unit system, optionally in CGS or SI units.
+"""
+
+from logging import getLogger
+from sailfish.fields import find_field, solver_fields
+from sailfish.units import SYSTEMS, unit_string
+from sailfish.mesh import PlanarCartesianMesh, PlanarCartesian2DMesh, LogSphericalMesh
+
+logger = getLogger(__name__)
+
+EXTENSIONS = dict(hdf5=".h5", vtk=".vtk", csv=".csv", msgpack=".msgpack.zst")
+
+
+def field_names(solver, num_fields, physics=None):
+    """
+    Return a list of names for the primitive fields of a given solver, from
+    the field registry in :py:mod:`sailfish.fields`.
+    """
+    return [field.name for field in solver_fields(solver, num_fields, physics)]
+
+
+def checkpoint_physics(chkpt):
+    """
+    Return the physics configuration of the setup which wrote a checkpoint,
+    which determines the layout of the solver's primitive fields. `None` is
+    returned if the setup cannot be reconstructed from the checkpoint.
+    """
+    import sailfish.setups
+    from sailfish.setup_base import SetupBase, SetupError
+
+    try:
+        setup_class = SetupBase.find_setup_class(chkpt["setup_name"])
+        return setup_class(**chkpt["model_parameters"]).physics
+    except (KeyError, SetupError) as e:
+        logger.warning(f"could not reconstruct the checkpoint setup: {e}")
+        return None
+
+
+def field_units(name, solver, units="code"):
+    """
+    Return the units string of a named field, or of a coordinate, in the
+    given unit system. Fields which are not in the registry are assumed to
+    be dimensionless.
+    """
+    if name in ("x", "y", "r"):
+        return unit_string((0, 1, 0), units)
+
+    field = find_field(name, solver)
+    return unit_string(field.dimensions, units) if field is not None else ""
+
+
+def cell_coordinates(mesh, time):
+    """
+    Return a dictionary of the cell-center coordinate arrays for a mesh.
+
+    Each array has the same shape as the mesh.
+    """
+    import numpy as np
+
+    if isinstance(mesh, PlanarCartesianMesh):
+        return dict(x=np.array(mesh.zone_centers(time)))
+
+    if isinstance(mesh, PlanarCartesian2DMesh):
+        ni, nj = mesh.shape
+        x = mesh.x0 + (np.arange(ni) + 0.5) * mesh.dx
+        y = mesh.y0 + (np.arange(nj) + 0.5) * mesh.dy
+        x, y = np.meshgrid(x, y, indexing="ij")
+        return dict(x=x, y=y)
+
+    if isinstance(mesh, LogSphericalMesh) and mesh.polar_grid:
+        r = np.array(mesh.zone_centers(time))
+        q = (np.arange(mesh.num_polar_zones) + 0.5) * mesh.polar_spacing
+        r, q = np.meshgrid(r, q, indexing="ij")
+        return dict(r=r, theta=q)
+
+    if isinstance(mesh, LogSphericalMesh):
+        return dict(r=np.array(mesh.zone_centers(time)))
+
+    raise ValueError(f"export does not support mesh {mesh}")
+
+
+def checkpoint_unit_system(chkpt, units):
+    """
+    Return the unit system of a checkpoint, or `None` if the units are code
+    units. A `ConfigurationError` is raised if physical units are requested
+    and the setup did not define a unit system.
+    """
+    from sailfish.driver import ConfigurationError
+
+    if units not in SYSTEMS:
+        raise ConfigurationError(f"unknown unit system {units}, must be {SYSTEMS}")
+
+    if units == "code":
+        return None
+
+    unit_system = chkpt.get("unit_system")
+
+    if unit_system is None:
+        raise ConfigurationError(
+            f"{units} units requested, but the setup has no unit system"
+        )
+
+    return unit_system
+
+
+def checkpoint_fields(chkpt, auxiliary=True, units="code"):
+    """
+    Return a dictionary of the coordinate and primitive field arrays in a
+    checkpoint, and the solver's auxiliary fields if `auxiliary` is true.
+
+    The fields are in code units, or in `cgs` or `si` units if the `units`
+    argument is given. Fields not in the registry are not converted.
+    """
+    unit_system = checkpoint_unit_system(chkpt, units)
+
+    primitive = chkpt.get("primitive")
+
+    if primitive is None:
+        # Solvers which evolve primitive data return it as the solution, and
+        # lite checkpoints have only the solution.
+        primitive = chkpt["solution"]
+
+    rank = len(chkpt["mesh"].shape)
+
+    if primitive.ndim == rank:
+        primitive = primitive[..., None]
+
+    if primitive.ndim != rank + 1:
+        raise ValueError(f"export does not support solver {chkpt['solver']}")
+
+    num_fields = primitive.shape[rank]
+
+    fields = cell_coordinates(chkpt["mesh"], chkpt["time"])
+    names = field_names(chkpt["solver"], num_fields, checkpoint_physics(chkpt))
+    fields.update((name, primitive[..., q]) for q, name in enumerate(names))
+
+    if auxiliary:
+        fields.update(chkpt.get("auxiliary_fields", dict()))
+
+    if unit_system is not None:
+        for name in fields:
+            if name in ("x", "y", "r"):
+                fields[name] = unit_system.convert(fields[name], (0, 1, 0), units)
+            elif find_field(name, chkpt["solver"]) is not None:
+                dimensions = find_field(name, chkpt["solver"]).dimensions
+                fields[name] = unit_system.convert(fields[name], dimensions, units)
+
+    return fields
+
+
+def checkpoint_timeseries(chkpt, units="code"):
+    """
+    Return a dictionary of the time series in a checkpoint, with one array
+    per diagnostic, keyed by its index and quantity, e.g. `01_mdot`.
+
+    The checkpoint must list the diagnostics that generated the time series.
+    Values are converted to `cgs` or `si` units if requested.
+    """
+    import numpy as np
+    from sailfish.physics.circumbinary import Diagnostic
+
+    unit_system = checkpoint_unit_system(chkpt, units)
+    diagnostics = chkpt.get("diagnostics") or []
+    timeseries = chkpt.get("timeseries") or []
+    result = dict()
+
+    for n, d in enumerate(diagnostics):
+        d = Diagnostic(**d) if isinstance(d, dict) else d
+        column = np.array([row[n] for row in timeseries])
+
+        if unit_system is not None:
+            column = unit_system.convert(column, d.dimensions, units)
+
+        result[f"{n:02d}_{d.quantity}"] = column
+
+    return result
+
+
+def write_hdf5(chkpt, filename, units="code"):
+    """
+    Write the checkpoint fields, time series, time, and model parameters to
+    an HDF5 file.
+    """
+    import h5py
+
+    with h5py.File(filename, "w") as h5f:
+        for key in ["time", "iteration", "solver", "setup_name"]:
+            h5f.attrs[key] = chkpt[key]
+
+        params = h5f.create_group("model_parameters")
+
+        for key, val in chkpt["model_parameters"].items():
+            if type(val) in (int, float, bool, str):
+                params.attrs[key] = val
+
+        h5f.attrs["units"] = units
+
+        if chkpt.get("unit_system") is not None:
+            for key, val in chkpt["unit_system"]._asdict().items():
+                h5f.attrs[f"unit_{key}_cgs"] = val
+
+        for name, data in checkpoint_fields(chkpt, units=units).items():
+            dataset = h5f.create_dataset(name, data=data)
+            field = find_field(name, chkpt["solver"])
+
+            if field is not None:
+                dataset.attrs["about"] = field.about
+                dataset.attrs["units"] = unit_string(field.dimensions, units)
+
+        timeseries = checkpoint_timeseries(chkpt, units=units)
+
+        if timeseries:
+            group = h5f.create_group("timeseries")
+
+            for name, data in timeseries.items():
+                group.create_dataset(name, data=data)
+
+
+def write_vtk(chkpt, filename, units="code"):
+    """
+    Write the checkpoint fields to a legacy-format ASCII VTK file.
+
+    The data is written as a structured grid, with points located at the
+    cell centers. Coordinates on spherical-polar meshes are converted to
+    cartesian (x, z) coordinates, with the polar axis along z.
+    """
+    import numpy as np
+
+    fields = checkpoint_fields(chkpt, units=units)
+    shape = chkpt["mesh"].shape
+    ni, nj = shape if len(shape) == 2 else (shape[0], 1)
+    points = np.zeros((ni, nj, 3))
+
+    if "theta" in fields:
+        points[..., 0] = fields["r"] * np.sin(fields["theta"])
+        points[..., 2] = fields["r"] * np.cos(fields["theta"])
+    else:
+        for n, key in enumerate(("x", "y")):
+            if key in fields:
+                points[..., n] = fields[key].reshape(ni, nj)
+        if "r" in fields:
+            points[..., 0] = fields["r"].reshape(ni, nj)
+
+    # VTK structured grids are ordered with the first index varying fastest.
+    points = points.transpose(1, 0, 2).reshape(-1, 3)
+    coordinates = ("x", "y", "r", "theta")
+
+    with open(filename, "w") as vtk:
+        vtk.write("# vtk DataFile Version 3.0\n")
+        vtk.write(f"sailfish {chkpt['setup_name']} t={chkpt['time']}\n")
+        vtk.write("ASCII\n")
+        vtk.write("DATASET STRUCTURED_GRID\n")
+        vtk.write(f"DIMENSIONS {ni} {nj} 1\n")
+        vtk.write(f"POINTS {ni * nj} double\n")
+        np.savetxt(vtk, points)
+        vtk.write(f"POINT_DATA {ni * nj}\n")
+
+        for name, data in fields.items():
+            if name not in coordinates:
+                vtk.write(f"SCALARS {name} double 1\n")
+                vtk.write("LOOKUP_TABLE default\n")
+                np.savetxt(vtk, data.reshape(ni, nj).T.flatten())
+
+
+def write_csv(chkpt, filename, units="code"):
+    """
+    Write the checkpoint fields to a CSV file, with one row per grid cell.
+    """
+    import numpy as np
+
+    fields = checkpoint_fields(chkpt, units=units)
+    table = np.stack([data.flatten() for data in fields.values()], axis=1)
+    header = ",".join(fields.keys())
+    np.savetxt(filename, table, delimiter=",", header=header, comments="")
+
+
+def write_msgpack(chkpt, filename, units="code"):
+    """
+    Write the checkpoint fields to a zstd-compressed msgpack file, and add the
+    file to the manifest of the directory it is written to.
+
+    These files are intended to be fetched individually by a browser-based
+    viewer. Each field is stored as little-endian 32-bit floats in C order,
+    under the key `fields`, along with its shape. The manifest is a JSON file
+    named `manifest.json`, listing the time, iteration, and field names of
+    each frame, so the viewer can find frames without downloading them.
+    """
+    import msgpack
+    import numpy as np
+    import zstandard
+
+    fields = checkpoint_fields(chkpt, units=units)
+    frame = dict(
+        time=chkpt["time"],
+        iteration=chkpt["iteration"],
+        setup_name=chkpt["setup_name"],
+        solver=chkpt["solver"],
+        fields={
+            name: dict(
+                shape=list(data.shape),
+                units=field_units(name, chkpt["solver"], units),
+                dtype="<f4",
+                data=np.ascontiguousarray(data, dtype="<f4").tobytes(),
+            )
+            for name, data in fields.items()
+        },
+    )
+
+    with open(filename, "wb") as f:
+        f.write(zstandard.ZstdCompressor().compress(msgpack.packb(frame)))
+
+    update_manifest(chkpt, filename, list(fields))
+
+
+def update_manifest(chkpt, filename, field_names):
+    """
+    Add or replace the entry for a frame file in the manifest of its
+    directory. Entries are kept sorted by simulation time.
+    """
+    import json
+    from os.path import basename, dirname, getsize, join
+
+    manifest_file = join(dirname(filename), "manifest.json")
+
+    try:
+        with open(manifest_file) as f:
+            manifest = json.load(f)
+    except FileNotFoundError:
+        manifest = dict(
+            setup_name=chkpt["setup_name"],
+            solver=chkpt["solver"],
+            model_parameters={
+                key: val
+                for key, val in chkpt["model_parameters"].items()
+                if type(val) in (int, float, bool, str)
+            },
+            frames=[],
+        )
+
+    entry = dict(
+        file=basename(filename),
+        time=chkpt["time"],
+        iteration=chkpt["iteration"],
+        fields=field_names,
+        size=getsize(filename),
+    )
+    frames = [f for f in manifest["frames"] if f["file"] != entry["file"]]
+    manifest["frames"] = sorted(frames + [entry], key=lambda f: f["time"])
+
+    with open(manifest_file, "w") as f:
+        json.dump(manifest, f, indent=2)
+
+
+WRITERS = dict(
+    hdf5=write_hdf5,
+    vtk=write_vtk,
+    csv=write_csv,
+    msgpack=write_msgpack,
+)
+"""
+The export writer for each format. Each is called as `writer(chkpt,
+filename, units)`, with a checkpoint dictionary as written by the driver.
+"""
+
+
+def convert(chkpt_file, to="hdf5", output=None, units="code"):
+    """
+    Convert a checkpoint file to the given format and units, and return the
+    name of the file written. If no output filename is given, it is generated
+    from the checkpoint filename.
+    """
+    from os.path import splitext
+    from sailfish.driver import ConfigurationError, load_checkpoint
+
+    if to not in WRITERS:
+        raise ConfigurationError(f"unknown export format {to}")
+
+    chkpt = load_checkpoint(chkpt_file)
+    output = output or splitext(chkpt_file)[0] + EXTENSIONS[to]
+    WRITERS[to](chkpt, output, units=units)
+    logger.info(f"write {output}")
+    return output
+
+
+def argument_parser():
+    """
+    Return the argument parser for the `sailfish convert` subcommand.
+    """
+    import argparse
+
+    parser = argparse.ArgumentParser(
+        prog="sailfish convert",
+        description="convert checkpoint files to analysis-friendly formats",
+    )
+    parser.add_argument(
+        "checkpoints",
+        nargs="+",
+        metavar="CHKPT",
+        help="checkpoint files to convert",
+    )
+    parser.add_argument(
+        "--to",
+        choices=list(EXTENSIONS),
+        default="hdf5",
+        help="output file format",
+    )
+    parser.add_argument(
+        "--units",
+        choices=list(SYSTEMS),
+        default="code",
+        help="unit system of the output (cgs and si need a setup unit system)",
+    )
+    parser.add_argument(
+        "--output",
+        "-o",
+        metavar="F",
+        help="output filename (only if a single checkpoint is given)",
+    )
+    return parser
+
+
+def main(argv):
+    """
+    Command line interface for the `sailfish convert` subcommand.
+    """
+    parser = argument_parser()
+    args = parser.parse_args(argv)
+
+    if args.output is not None and len(args.checkpoints) > 1:
+        parser.error("--output requires a single checkpoint")
+
+    for chkpt_file in args.checkpoints:
+        convert(chkpt_file, to=args.to, output=args.output, units=args.units)
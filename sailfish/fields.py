@@ -0,0 +1,202 @@
+"""
+A registry of the named output fields of each solver.
+
+Each solver's primitive fields are listed here in the order they appear on
+the last axis of its primitive array, along with their physical dimensions
+and a short description. Checkpoint export, plotting, and diagnostics look
+up fields by name through this registry, so a field added here is available
+to all of them.
+"""
+
+from typing import NamedTuple, Tuple
+from sailfish.units import unit_string
+
+
+class Field(NamedTuple):
+    """
+    A named output variable, with its physical dimensions in code units
+    """
+
+    name: str
+    about: str
+    dimensions: Tuple[int, int, int] = (0, 0, 0)
+    """ exponents of mass, length, and time """
+
+    @property
+    def units(self):
+        """
+        A string representation of the field's dimensions, e.g. `M L^-2`.
+        """
+        return unit_string(self.dimensions)
+
+
+SURFACE_DENSITY = (1, -2, 0)
+VELOCITY = (0, 1, -1)
+DISK_PRESSURE = (1, 0, -2)
+LENGTH = (0, 1, 0)
+DIMENSIONLESS = (0, 0, 0)
+TORQUE_DENSITY = (1, 0, -2)
+
+SOLVER_FIELDS = dict(
+    cbdiso_2d=[
+        Field("sigma", "surface density", SURFACE_DENSITY),
+        Field("vx", "x-velocity", VELOCITY),
+        Field("vy", "y-velocity", VELOCITY),
+    ],
+    cbdisodg_2d=[
+        Field("sigma", "surface density", SURFACE_DENSITY),
+        Field("vx", "x-velocity", VELOCITY),
+        Field("vy", "y-velocity", VELOCITY),
+    ],
+    cbdgam_2d=[
+        Field("sigma", "surface density", SURFACE_DENSITY),
+        Field("vx", "x-velocity", VELOCITY),
+        Field("vy", "y-velocity", VELOCITY),
+        Field("pressure", "vertically integrated pressure", DISK_PRESSURE),
+    ],
+    srhd_1d=[
+        Field("rho", "comoving mass density", (1, -3, 0)),
+        Field("gamma_beta", "radial four-velocity", DIMENSIONLESS),
+        Field("pressure", "gas pressure", (1, -3, 0)),
+        Field("scalar", "passive scalar concentration", DIMENSIONLESS),
+    ],
+    srhd_2d=[
+        Field("rho", "comoving mass density", (1, -3, 0)),
+        Field("ur", "radial four-velocity", DIMENSIONLESS),
+        Field("uq", "polar four-velocity", DIMENSIONLESS),
+        Field("pressure", "gas pressure", (1, -3, 0)),
+    ],
+)
+"""
+The primitive fields of each solver which are always present. The srhd
+solvers use units where the speed of light is 1, so their pressure has the
+dimensions of a density.
+"""
+
+VERTICAL_STRUCTURE_FIELDS = [
+    Field("scale_height", "disk scale height", LENGTH),
+    Field("dhdt", "rate of change of the scale height", VELOCITY),
+]
+"""
+The fields which follow the pressure in the cbdgam_2d solver, if the disk
+vertical structure is evolved.
+"""
+
+TRACER_SOLVERS = ("cbdiso_2d",)
+"""
+Solvers whose primitive fields past the registered ones are passive tracers.
+"""
+
+AUXILIARY_FIELDS = [
+    Field("density_floor", "number of density floor activations"),
+    Field("pressure_floor", "number of pressure floor activations"),
+    Field("velocity_ceiling", "number of velocity ceiling activations"),
+    Field("limiter", "number of slope limiter activations"),
+    Field("torque_density1", "torque density on point mass 1", TORQUE_DENSITY),
+    Field("torque_density2", "torque density on point mass 2", TORQUE_DENSITY),
+]
+"""
+Fields which some solvers write to checkpoints in addition to the primitive
+fields; see :py:meth:`sailfish.solver_base.SolverBase.auxiliary_fields`. The
+torque densities are the gravitational torques about the origin exerted by
+the gas on each point mass, per unit area.
+"""
+
+
+def physics_fields(solver, physics):
+    """
+    Return a list of :obj:`Field` instances for the primitive fields of a
+    solver, laid out according to a physics configuration, given either as
+    a dictionary (see :py:attr:`sailfish.setup_base.SetupBase.physics`) or as
+    a physics instance.
+
+    The cbdgam_2d solver has the scale height fields if the disk vertical
+    structure is evolved, followed by the internal energy of each energy
+    reservoir. Solvers which evolve passive tracers end with the tracers.
+    """
+    if isinstance(physics, dict):
+        get = physics.get
+    else:
+        get = lambda key, default: getattr(physics, key, default)
+
+    fields = list(SOLVER_FIELDS.get(solver, []))
+
+    if solver == "cbdgam_2d":
+        if get("vertical_structure", False):
+            fields += VERTICAL_STRUCTURE_FIELDS
+        fields += [
+            Field(f"energy{r}", f"internal energy of reservoir {r}", DISK_PRESSURE)
+            for r in range(get("num_energy_reservoirs", 0))
+        ]
+
+    if solver in TRACER_SOLVERS:
+        fields += [
+            Field(f"tracer{n}", f"passive tracer {n}")
+            for n in range(get("num_tracers", 0))
+        ]
+
+    return fields
+
+
+def solver_fields(solver, num_fields, physics=None):
+    """
+    Return a list of :obj:`Field` instances for the primitive fields of a
+    solver.
+
+    If the physics configuration is given, the fields are laid out by
+    :func:`physics_fields`. Otherwise, fields past the ones always present
+    are named `tracer0`, `tracer1`, etc. if the solver evolves passive
+    tracers, and by their index, as `field4`, `field5`, etc. if not.
+    """
+    if physics is not None:
+        fields = physics_fields(solver, physics)[:num_fields]
+    else:
+        fields = SOLVER_FIELDS.get(solver, [])[:num_fields]
+
+    for q in range(len(fields), num_fields):
+        if physics is None and solver in TRACER_SOLVERS:
+            n = q - len(SOLVER_FIELDS[solver])
+            fields.append(Field(f"tracer{n}", f"passive tracer {n}"))
+        else:
+            fields.append(Field(f"field{q}", f"primitive field {q}"))
+
+    return fields
+
+
+def field_index(solver, num_fields, name, physics=None):
+    """
+    Return the index of a named field on the last axis of a solver's
+    primitive array. A `ValueError` is raised if the solver has no field by
+    that name.
+    """
+    names = [field.name for field in solver_fields(solver, num_fields, physics)]
+
+    try:
+        return names.index(name)
+    except ValueError:
+        raise ValueError(f"unknown field {name}, options are {', '.join(names)}")
+
+
+def find_field(name, solver=None):
+    """
+    Return the registered :obj:`Field` with the given name, or `None`.
+
+    If a solver is given, its primitive fields are searched first. Tracer
+    fields and the auxiliary fields are also recognized.
+    """
+    candidates = list(SOLVER_FIELDS.get(solver, []))
+    candidates += [f for fields in SOLVER_FIELDS.values() for f in fields]
+    candidates += VERTICAL_STRUCTURE_FIELDS
+    candidates += AUXILIARY_FIELDS
+
+    for field in candidates:
+        if field.name == name:
+            return field
+
+    if name.startswith("tracer") and name[6:].isdigit():
+        return Field(name, f"passive tracer {name[6:]}")
+
+    if name.startswith("energy") and name[6:].isdigit():
+        return Field(name, f"internal energy of reservoir {name[6:]}", DISK_PRESSURE)
+
+    return None
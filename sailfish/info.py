@@ -0,0 +1,196 @@
+"""
+Print a summary of checkpoint files, for the `sailfish info` subcommand.
+
+Checkpoints are single pickles, so the metadata cannot be read without
+parsing the whole file. However the array data need not be loaded: the
+checkpoint is read with an unpickler which discards large byte strings as
+they are read, and replaces each array with an :obj:`ArraySummary` of its
+shape and data type. Only one field's worth of data is held in memory at a
+time, so summarizing a large checkpoint is fast and uses little memory.
+"""
+
+import pickle
+from typing import NamedTuple
+from sailfish.export import checkpoint_physics
+from sailfish.fields import solver_fields
+
+LARGE_BYTES = 1 << 12
+"""
+Byte strings at least this long are discarded when reading the header.
+"""
+
+
+class ArraySummary:
+    """
+    The shape and data type of an array in a checkpoint, in place of its data
+    """
+
+    def __init__(self, shape=(), dtype=None):
+        self.shape = tuple(shape)
+        self.dtype = dtype
+
+    def __setstate__(self, state):
+        # The state of an array pickled by numpy.ndarray.__reduce__ is
+        # (version, shape, dtype, is_fortran, data).
+        self.shape = tuple(state[1])
+        self.dtype = state[2]
+
+    @property
+    def ndim(self):
+        return len(self.shape)
+
+    def __repr__(self):
+        return f"array(shape={self.shape}, dtype={self.dtype})"
+
+
+class SkippedBytes(NamedTuple):
+    """
+    A placeholder for a byte string which was discarded
+    """
+
+    length: int
+
+
+def reconstruct_array(*args):
+    return ArraySummary()
+
+
+def array_from_buffer(buffer, dtype, shape, order):
+    return ArraySummary(shape, dtype)
+
+
+ARRAY_CONSTRUCTORS = {
+    ("numpy.core.multiarray", "_reconstruct"): reconstruct_array,
+    ("numpy._core.multiarray", "_reconstruct"): reconstruct_array,
+    ("numpy.core.numeric", "_frombuffer"): array_from_buffer,
+    ("numpy._core.numeric", "_frombuffer"): array_from_buffer,
+}
+
+
+class HeaderUnpickler(pickle._Unpickler):
+    """
+    An unpickler which reads the metadata in a checkpoint, but not its arrays
+
+    This is the pure-Python unpickler, whose opcode handlers can be replaced
+    by subclasses; the ones which read byte strings are replaced with ones
+    that discard the long strings.
+    """
+
+    dispatch = dict(pickle._Unpickler.dispatch)
+
+    def find_class(self, module, name):
+        if (module, name) in ARRAY_CONSTRUCTORS:
+            return ARRAY_CONSTRUCTORS[(module, name)]
+        return super().find_class(module, name)
+
+    def read_bytes(self, length):
+        if length < LARGE_BYTES:
+            self.append(self.read(length))
+        else:
+            remaining = length
+            while remaining > 0:
+                remaining -= len(self.read(min(remaining, 1 << 24)))
+            self.append(SkippedBytes(length))
+
+    def load_binbytes(self):
+        self.read_bytes(int.from_bytes(self.read(4), "little"))
+
+    def load_binbytes8(self):
+        self.read_bytes(int.from_bytes(self.read(8), "little"))
+
+    dispatch[pickle.BINBYTES[0]] = load_binbytes
+    dispatch[pickle.BINBYTES8[0]] = load_binbytes8
+    dispatch[pickle.BYTEARRAY8[0]] = load_binbytes8
+
+
+def load_checkpoint_header(chkpt_file):
+    """
+    Load a checkpoint file, with each array replaced by an
+    :obj:`ArraySummary`.
+    """
+    with open(chkpt_file, "rb") as f:
+        return HeaderUnpickler(f).load()
+
+
+def checkpoint_summary(chkpt):
+    """
+    Return a list of (label, value) pairs summarizing a checkpoint dictionary,
+    which may be loaded in full or by :func:`load_checkpoint_header`. The
+    model parameters are not included.
+    """
+    primitive = chkpt.get("primitive")
+
+    if primitive is None:
+        primitive = chkpt["solution"]
+
+    mesh = chkpt["mesh"]
+    rank = len(mesh.shape)
+    num_fields = primitive.shape[rank] if len(primitive.shape) > rank else 1
+    physics = checkpoint_physics(chkpt)
+    fields = [f.name for f in solver_fields(chkpt["solver"], num_fields, physics)]
+    auxiliary = list(chkpt.get("auxiliary_fields") or dict())
+    setup_state = chkpt.get("setup_state") or dict()
+    kind = "full" if "primitive" in chkpt else f"lite ({chkpt.get('precision')})"
+
+    summary = [
+        ("setup", chkpt["setup_name"]),
+        ("solver", chkpt["solver"]),
+        ("kind", kind),
+        ("time", chkpt["time"]),
+        ("iteration", chkpt["iteration"]),
+        ("resolution", " x ".join(str(n) for n in mesh.shape)),
+        ("mesh", mesh),
+        ("fields", " ".join(fields)),
+    ]
+
+    if auxiliary:
+        summary.append(("auxiliary fields", " ".join(auxiliary)))
+    if setup_state:
+        summary.append(("setup state", " ".join(setup_state)))
+    if chkpt.get("timeseries"):
+        summary.append(("timeseries samples", len(chkpt["timeseries"])))
+
+    return summary
+
+
+def argument_parser():
+    """
+    Return the argument parser for the `sailfish info` subcommand.
+    """
+    import argparse
+
+    parser = argparse.ArgumentParser(
+        prog="sailfish info",
+        description="print a summary of checkpoint files, without loading the data",
+    )
+    parser.add_argument("checkpoints", nargs="+", metavar="C", help="checkpoints")
+    return parser
+
+
+def main(argv):
+    """
+    Command line interface for the `sailfish info` subcommand.
+    """
+    parser = argument_parser()
+    args = parser.parse_args(argv)
+
+    for n, filename in enumerate(args.checkpoints):
+        if n > 0:
+            print()
+
+        try:
+            chkpt = load_checkpoint_header(filename)
+        except (OSError, pickle.UnpicklingError, EOFError) as e:
+            print(f"{filename}: could not read checkpoint: {e}")
+            continue
+
+        print(filename)
+
+        for label, value in checkpoint_summary(chkpt):
+            print(f"    {label + ':':<20} {value}")
+
+        if chkpt["model_parameters"]:
+            print("    parameters:")
+
+        for key, val in chkpt["model_parameters"].items():
+            print(f"        {key + ':':<24} {val}")
@@ -6,6 +6,30 @@ file itself are placed in this module's __pycache__ directory, and stored for
 reuse based on the SHA value of the source code and #define macros. GPU
 modules are JIT-compiled with cupy. No caching is presently done for the GPU
 modules.
+
+Each kernel is written once, in a single C source file, and compiled for the
+CPU, OpenMP, or CUDA backend on demand. The `KERNEL_LIB_HEADER` below is
+prepended to the source, and it defines the `PUBLIC` and `PRIVATE` function
+qualifiers and the `FOR_EACH_*` loop macros for the backend selected by the
+`EXEC_MODE` macro. Kernel sources should therefore not contain any
+backend-specific code of their own.
+
+GPU modules can also be created in a host-staged mode, in which kernel
+arguments are numpy arrays residing in host memory. Each invocation of a
+rank-2 kernel then streams the arrays through the device in tiles along the
+j axis, with the guard zones on either side of each tile, and copies back
+the interior of the arrays the kernel writes, i.e. those not declared
+`const`. The upload of each tile overlaps the kernel running on the previous
+one. This allows problems that exceed the device memory to be run, albeit
+more slowly. Arrays are recognized as zone arrays from their leading shape,
+`(ni + 2 * ng, nj + 2 * ng)` for any even number of guard zones, and a
+kernel's `patch_yl` and `patch_yr` arguments, if any, are set to the extent
+of each tile. Other arrays are copied to the device once per invocation.
+
+CUDA errors raised while compiling GPU modules or launching kernels are
+converted to :obj:`GpuError` exceptions, which name the failed kernel and
+its launch configuration. In debug mode, each launch is followed by a device
+synchronization, so errors are attributed to the kernel that caused them.
 """
 
 from platform import system
@@ -23,6 +47,35 @@ THREAD_BLOCK_SIZE_1D = (64,)
 THREAD_BLOCK_SIZE_2D = (8, 8)
 THREAD_BLOCK_SIZE_3D = (4, 4, 4)
 
+
+class GpuError(Exception):
+    """
+    A CUDA API call or kernel launch failed
+
+    If the error came from a kernel launch, the kernel name and the launch
+    configuration (the grid and block sizes) are kept as attributes, and are
+    included in the message.
+    """
+
+    def __init__(self, message, kernel=None, grid=None, block=None):
+        super().__init__(message)
+        self.kernel = kernel
+        self.grid = grid
+        self.block = block
+
+
+def cuda_error_types():
+    """
+    Return a tuple of the exception types cupy raises for CUDA errors; cupy is
+    imported lazily by this module.
+    """
+    from cupy.cuda.compiler import CompileException
+    from cupy.cuda.driver import CUDADriverError
+    from cupy.cuda.runtime import CUDARuntimeError
+
+    return CompileException, CUDADriverError, CUDARuntimeError
+
+
 KERNEL_LIB_HEADER = r"""
 #define EXEC_CPU 0
 #define EXEC_OMP 1
@@ -97,43 +150,163 @@ class KernelInvocation:
         self.shape = shape
 
     def __call__(self, *args):
+        args = list(self.shape) + list(args)
+
+        if self.kernel.lib.host_staged:
+            self.launch_staged(args)
+        else:
+            self.launch(args)
+
+    def launch_staged(self, args):
+        """
+        Launch the kernel on arrays residing in host memory, in tiles along
+        the j axis which are copied through the device.
+
+        Each tile is uploaded on the library's transfer stream, and the
+        kernel runs on its compute stream, so the upload of a tile overlaps
+        the kernel running on the previous tile. The interior of each tile
+        of the output arrays is copied back once its kernel has completed.
+        Arrays which are not zone arrays are uploaded once before the first
+        tile, and the outputs among them are copied back after the last.
+        """
+        import numpy
+
         lib = self.kernel.lib
-        rank = len(self.shape)
         name = self.kernel.symbol.name
         spec = self.kernel.symbol.args
-
-        if lib.cpu_mode:
-            kernel = getattr(lib.module, name)
+        compute, transfer = lib.streams
+        rank = len(self.shape)
+        ni, nj = self.shape if rank == 2 else (None, None)
+        arrays = [n for n, a in enumerate(args) if type(a) is numpy_ndarray()]
+        outputs = [n for n in arrays if not spec[n].is_const]
+        names = [arg.name for arg in spec]
+
+        def guard_zones(a):
+            if rank != 2 or a.ndim < 2:
+                return None
+            gi, gj = a.shape[0] - ni, a.shape[1] - nj
+            return gi // 2 if gi == gj and gi >= 0 and gi % 2 == 0 else None
+
+        guard = {n: guard_zones(args[n]) for n in arrays}
+
+        if rank == 2 and nj > 0:
+            tile_size = lib.tile_size or nj
+            tiles = [(j0, min(j0 + tile_size, nj)) for j0 in range(0, nj, tile_size)]
         else:
-            kernel = lib.module.get_function(name)
+            tiles = [None]
 
-        args = list(self.shape) + list(args)
+        def upload(tile):
+            tile_args = list(args)
+
+            with transfer:
+                for n in arrays:
+                    a, g = args[n], guard[n]
+                    if g is None:
+                        tile_args[n] = whole[n]
+                    else:
+                        j0, j1 = tile
+                        a = numpy.ascontiguousarray(a[:, j0 : j1 + 2 * g])
+                        tile_args[n] = lib.xp.asarray(a)
+
+            if tile is not None:
+                j0, j1 = tile
+                tile_args[1] = j1 - j0
+
+                if "patch_yl" in names and "patch_yr" in names:
+                    il, ir = names.index("patch_yl"), names.index("patch_yr")
+                    yl, yr = args[il], args[ir]
+                    tile_args[il] = yl + (yr - yl) * j0 / nj
+                    tile_args[ir] = yl + (yr - yl) * j1 / nj
+
+            return tile_args, transfer.record()
+
+        try:
+            with compute:
+                whole = {n: lib.xp.asarray(args[n]) for n in arrays if guard[n] is None}
+
+            pending = upload(tiles[0])
+
+            for k, tile in enumerate(tiles):
+                tile_args, uploaded = pending
+                compute.wait_event(uploaded)
+
+                with compute:
+                    self.launch(tile_args)
+
+                computed = compute.record()
+
+                if k + 1 < len(tiles):
+                    pending = upload(tiles[k + 1])
+
+                computed.synchronize()
+
+                for n in outputs:
+                    g = guard[n]
+                    if g is not None:
+                        j0, j1 = tile
+                        out = tile_args[n][g : g + ni, g : g + j1 - j0]
+                        args[n][g : g + ni, g + j0 : g + j1] = out.get(stream=compute)
+
+            for n in outputs:
+                if guard[n] is None:
+                    whole[n].get(stream=compute, out=args[n])
+
+            compute.synchronize()
+
+        except cuda_error_types() as e:
+            raise GpuError(f"staging kernel {name} failed: {e}", kernel=name) from e
+
+    def launch(self, args):
+        """
+        Launch the kernel with the given argument list, which starts with the
+        kernel shape.
+        """
+        lib = self.kernel.lib
+        rank = len(self.shape)
+        name = self.kernel.symbol.name
+        spec = self.kernel.symbol.args
+        shape = tuple(args[:rank])
 
         if lib.debug:
             validate_types(args, tuple(spec), name, lib.xp)
             validate_constraints(args, tuple(spec), name)
 
         if lib.cpu_mode:
+            kernel = getattr(lib.module, name)
             kernel(*to_ctypes(args, spec))
         else:
             if rank == 1:
                 (ti,) = bs = THREAD_BLOCK_SIZE_1D
-                (ni,) = self.shape
+                (ni,) = shape
                 nb = ((ni + ti - 1) // ti,)
-                kernel(nb, bs, args)
 
             elif rank == 2:
                 ti, tj = bs = THREAD_BLOCK_SIZE_2D
-                ni, nj = self.shape
+                ni, nj = shape
                 nb = ((ni + ti - 1) // ti, (nj + tj - 1) // tj)
-                kernel(nb, bs, args)
 
             elif rank == 3:
                 ti, tj, tk = bs = THREAD_BLOCK_SIZE_3D
-                ni, nj, nk = self.shape
+                ni, nj, nk = shape
                 nb = ((ni + ti - 1) // ti, (nj + tj - 1) // tj, (nk + tk - 1) // tk)
+
+            try:
+                kernel = lib.module.get_function(name)
                 kernel(nb, bs, args)
 
+                # Kernel launches are asynchronous, so errors in the kernel
+                # itself are otherwise reported by a later CUDA call.
+                if lib.debug:
+                    lib.xp.cuda.Device().synchronize()
+
+            except cuda_error_types() as e:
+                raise GpuError(
+                    f"kernel {name} failed, with grid {nb} and block {bs}: {e}",
+                    kernel=name,
+                    grid=nb,
+                    block=bs,
+                ) from e
+
 
 class Kernel:
     """
@@ -163,7 +336,14 @@ class Library:
     """
 
     def __init__(
-        self, code=None, mode="cpu", name="module", debug=True, define_macros=dict()
+        self,
+        code=None,
+        mode="cpu",
+        name="module",
+        debug=True,
+        define_macros=dict(),
+        host_staged=False,
+        tile_size=None,
     ):
         code = f"{KERNEL_LIB_HEADER} {code}"
         logger.info(f"debug mode {'enabled' if debug else 'disabled'}")
@@ -172,6 +352,8 @@ class Library:
         with measure_time(mode) as prep_time:
             self.debug = debug
             self.cpu_mode = mode != "gpu"
+            self.host_staged = host_staged and not self.cpu_mode
+            self.tile_size = tile_size
             self.api = parse_api(code)
 
             if self.cpu_mode:
@@ -241,21 +423,41 @@ class Library:
             "-D EXEC_MODE=2",
         )
         module = cupy.RawModule(code=code, options=options)
-        module.compile()
+
+        try:
+            module.compile()
+        except cuda_error_types() as e:
+            raise GpuError(f"could not compile the GPU module: {e}") from e
+
         self.module = module
         self.xp = cupy
 
+        if self.host_staged:
+            self.streams = (
+                cupy.cuda.Stream(non_blocking=True),
+                cupy.cuda.Stream(non_blocking=True),
+            )
+
     def __getattr__(self, symbol):
         return Kernel(self, self.api[symbol])
 
 
+def numpy_ndarray():
+    """
+    Return the numpy array type; numpy is imported lazily by this module.
+    """
+    import numpy
+
+    return numpy.ndarray
+
+
 def to_ctypes(args, spec):
     """
     Coerce a sequence of values to their appropriate ctype.
 
     The expected type is determined from the `spec` list.
     """
-    for arg, (typename, _, _) in zip(args, spec):
+    for arg, (typename, _, _, _) in zip(args, spec):
         if typename == "int":
             yield c_int(arg)
         elif typename == "double":
@@ -284,7 +486,7 @@ def validate_types(args, spec, symbol, xp):
     if len(args) != len(spec):
         raise arglen_error(symbol, args, spec)
 
-    for n, (arg, (typename, argname, constraint)) in enumerate(zip(args, spec)):
+    for n, (arg, (typename, argname, constraint, _)) in enumerate(zip(args, spec)):
         if typename == "int":
             if type(arg) not in [int, xp.int32]:
                 raise type_error(symbol, n, arg, "int")
@@ -308,7 +510,7 @@ def validate_constraints(args, spec, symbol):
     `parse_api` module.
     """
     scope = dict(zip([a[1] for a in spec], args))
-    for arg, (_, name, constraint) in zip(args, spec):
+    for arg, (_, name, constraint, _) in zip(args, spec):
         if constraint:
             c = constraint.replace("$", name)
             if not eval(c, None, scope):
@@ -9,6 +9,8 @@ class Argument(NamedTuple):
     dtype: str
     name: str
     constraint: str
+    is_const: bool = False
+    """ whether the argument is a pointer to data the kernel only reads """
 
 
 class Symbol(NamedTuple):
@@ -34,7 +36,8 @@ def scan(lines):
 
     function_name = re.compile(r"\s*PUBLIC\s+void\s+(?P<symbol>\w+)")
     argument_name = re.compile(
-        r"\s*(?P<dtype>\w+\s*\**)\s*(?P<argname>\w+)\s*[,\)]\s*(?://)?\s*(?P<comment>.*)"
+        r"\s*(?P<const>const\s+)?(?P<dtype>\w+\s*\**)\s*(?P<argname>\w+)\s*[,\)]"
+        r"\s*(?://)?\s*(?P<comment>.*)"
     )
     symbol = None
     for line in lines:
@@ -46,10 +49,10 @@ def scan(lines):
         else:
             match = argument_name.match(line)
             if match is not None:
-                dtype, argname, comment = match.groups()
+                const, dtype, argname, comment = match.groups()
                 dtype = dtype.replace(" ", "")
                 constraint = comment.partition("::")[2]
-                yield "argument", (dtype, argname, constraint)
+                yield "argument", (dtype, argname, constraint, const is not None)
             else:
                 symbol = None
                 yield "end_symbol", None
@@ -63,8 +66,9 @@ def parse_api(code):
     fully documented. This function returns a dictionary whose keys are the
     names of the public functions (or kernels) in the code, and the values are
     lists of the (positional) arguments describing the function signature. Each
-    function argument is a tuple of the data type, the argument name, and an
-    optional constraint which could be validated at runtime.
+    function argument is a tuple of the data type, the argument name, an
+    optional constraint which could be validated at runtime, and whether the
+    argument is declared `const`.
     """
     api = dict()
     for event, value in scan(code.splitlines()):
@@ -96,6 +96,38 @@ def configure_build(
     logger.info(f"OpenMP is {'enabled' if enable_openmp else 'disabled'}")
 
 
+THREAD_BINDINGS = dict(compact="close", scatter="spread")
+
+
+def configure_threads(num_threads=None, binding=None):
+    """
+    Configure the OpenMP runtime through its environment variables.
+
+    The binding may be "compact", which places threads on neighboring cores,
+    or "scatter", which spreads them evenly across the sockets; these set
+    `OMP_PROC_BIND` to "close" or "spread" respectively, with threads pinned
+    to cores. Arguments which are `None` leave the environment unchanged.
+    This function must be called before the first OpenMP-enabled extension is
+    loaded, because the runtime reads its environment when it initializes.
+    """
+    import os
+
+    if num_threads is not None:
+        if num_threads < 1:
+            raise ValueError(f"number of threads must be positive, got {num_threads}")
+        os.environ["OMP_NUM_THREADS"] = str(num_threads)
+
+    if binding is not None:
+        if binding not in THREAD_BINDINGS:
+            raise ValueError(f"unknown thread binding {binding}, [compact|scatter]")
+        os.environ["OMP_PROC_BIND"] = THREAD_BINDINGS[binding]
+        os.environ["OMP_PLACES"] = "cores"
+
+    for key in ["OMP_NUM_THREADS", "OMP_PROC_BIND", "OMP_PLACES"]:
+        if key in os.environ:
+            logger.info(f"{key}={os.environ[key]}")
+
+
 def get_array_module(mode):
     """
     Return either the numpy or cupy module, depending on the value of mode.
@@ -119,6 +151,18 @@ def get_array_module(mode):
         raise ValueError(f"unknown execution mode {mode}, must be [cpu|omp|gpu]")
 
 
+def gpu_available():
+    """
+    Return True if cupy can be imported and at least one GPU device is found.
+    """
+    try:
+        from cupy.cuda.runtime import getDeviceCount
+
+        return getDeviceCount() > 0
+    except Exception:
+        return False
+
+
 def execution_context(mode, device_id=None):
     """
     Return a context manager appropriate for the given exuction mode.
@@ -145,6 +189,132 @@ def num_devices(mode):
         return getDeviceCount()
 
 
+def enable_peer_access(mode):
+    """
+    Enable direct (peer-to-peer) memory access between pairs of GPU devices.
+
+    Where peer access is supported, e.g. over NVLink or a shared PCIe switch,
+    guard zone data is copied directly between devices, rather than being
+    staged through host memory. Pairs of devices which do not support it are
+    skipped. Returns the number of device pairs for which access was enabled.
+    """
+    if mode != "gpu":
+        return 0
+
+    from cupy.cuda import Device
+    from cupy.cuda.runtime import (
+        CUDARuntimeError,
+        deviceCanAccessPeer,
+        deviceEnablePeerAccess,
+        getDeviceCount,
+    )
+
+    num_devices = getDeviceCount()
+    num_enabled = 0
+
+    for a in range(num_devices):
+        for b in range(num_devices):
+            if a != b and deviceCanAccessPeer(a, b):
+                with Device(a):
+                    try:
+                        deviceEnablePeerAccess(b)
+                    except CUDARuntimeError:
+                        pass  # access was already enabled
+                num_enabled += 1
+
+    if num_devices > 1:
+        logger.info(f"peer access enabled for {num_enabled} gpu device pairs")
+
+    return num_enabled
+
+
+def copy_guard_zones(dst, src):
+    """
+    Copy the array `src` into `dst`, which may be on a different device.
+
+    If the arrays are on different GPU devices, and both are contiguous, the
+    copy is a single peer-to-peer transfer, ordered after any pending work on
+    both devices. This is direct if peer access was enabled (see
+    :py:func:`enable_peer_access`), and otherwise staged by the CUDA driver.
+    In all other cases, the copy is a regular array assignment.
+    """
+    device_dst = getattr(dst, "device", None)
+    device_src = getattr(src, "device", None)
+
+    if (
+        device_dst is None
+        or device_src is None
+        or device_dst.id == device_src.id
+        or not dst.flags.c_contiguous
+        or not src.flags.c_contiguous
+        or dst.shape != src.shape
+    ):
+        dst[...] = src
+        return
+
+    from cupy.cuda.runtime import memcpyPeer
+
+    memcpyPeer(dst.data.ptr, device_dst.id, src.data.ptr, device_src.id, dst.nbytes)
+
+
+class KernelGraphCache:
+    """
+    Replays fixed sequences of GPU operations as CUDA graphs.
+
+    A sequence of many small operations, such as the array copies that fill
+    the guard zones, can take much longer to launch than to run. When the
+    cache is enabled, a sequence identified by a key is run normally the
+    first time, captured into a CUDA graph the second time, and replayed
+    from the graph after that, with a single launch. The captured operations
+    are frozen into the graph: a sequence must not depend on any values
+    which change between calls, other than the contents of the arrays it
+    operates on, and it must not allocate memory. Arrays in the key are
+    identified by their device address, shape, and strides, so that a new
+    graph is captured if an array is replaced.
+
+    When the cache is not enabled, the operations are run normally.
+    """
+
+    def __init__(self, enabled=False):
+        self.enabled = enabled
+        self.graphs = dict()
+
+    def __call__(self, operations, *key):
+        if not self.enabled:
+            return operations()
+
+        key = tuple(
+            (k.data.ptr, k.shape, k.strides) if hasattr(k, "data") else k
+            for k in key
+        )
+
+        if key not in self.graphs:
+            self.graphs[key] = None
+            return operations()
+
+        if self.graphs[key] is None:
+            from cupy.cuda import Stream
+
+            stream = Stream(non_blocking=True)
+
+            with stream:
+                stream.begin_capture()
+                try:
+                    operations()
+                finally:
+                    self.graphs[key] = stream.end_capture()
+
+            logger.debug(f"captured CUDA graph {len(self.graphs)}")
+
+        self.graphs[key].launch()
+
+    def clear(self):
+        """
+        Discard the captured graphs.
+        """
+        self.graphs.clear()
+
+
 def log_system_info(mode):
     """
     Log relevant details of the system's compute capabilities.
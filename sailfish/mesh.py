@@ -179,6 +179,12 @@ class PlanarCartesian2DMesh(NamedTuple):
     The length of the domain is related to height by an aspect ratio. The
     minimum demarcations along an axis is also variable allowing for
     non-square meshing if need be.
+
+    The domain topology is set per axis by the `periodic_x` and `periodic_y`
+    flags. Solvers fill the guard zones on a periodic axis with the image
+    from the opposite side of the domain, and measure separations (e.g. from
+    a point mass) to the nearest periodic image; the boundary condition
+    applies only on the non-periodic axes.
     """
 
     x0: float = 0.0
@@ -187,12 +193,22 @@ class PlanarCartesian2DMesh(NamedTuple):
     y1: float = 1.0
     ni: int = 1000
     nj: int = 1000
+    periodic_x: bool = False
+    periodic_y: bool = False
 
     def __str__(self):
-        return f"<planar cartesian 2d: ({self.x0} -> {self.x1}) x ({self.y0} -> {self.y1}), shape {self.shape}>"
+        topology = "".join(
+            f", periodic in {axis}"
+            for axis, periodic in zip("xy", (self.periodic_x, self.periodic_y))
+            if periodic
+        )
+        return (
+            f"<planar cartesian 2d: ({self.x0} -> {self.x1}) x "
+            f"({self.y0} -> {self.y1}), shape {self.shape}{topology}>"
+        )
 
     @classmethod
-    def centered_square(cls, domain_radius, resolution):
+    def centered_square(cls, domain_radius, resolution, **topology):
         x0 = -domain_radius
         y0 = -domain_radius
         x1 = +domain_radius
@@ -201,10 +217,10 @@ class PlanarCartesian2DMesh(NamedTuple):
         nj = resolution
         dx = 2.0 * domain_radius / resolution
         dy = 2.0 * domain_radius / resolution
-        return PlanarCartesian2DMesh(x0, y0, x1, y1, ni, nj)
+        return PlanarCartesian2DMesh(x0, y0, x1, y1, ni, nj, **topology)
 
     @classmethod
-    def centered_rectangle(cls, height, resolution, aspect: int):
+    def centered_rectangle(cls, height, resolution, aspect: int, **topology):
         if type(aspect) is not int:
             raise ValueError("centered_rectangle requires aspect to be int")
         x0 = -0.5 * height * aspect
@@ -213,7 +229,7 @@ class PlanarCartesian2DMesh(NamedTuple):
         y1 = +0.5 * height
         ni = resolution * aspect
         nj = resolution
-        return PlanarCartesian2DMesh(x0, y0, x1, y1, ni, nj)
+        return PlanarCartesian2DMesh(x0, y0, x1, y1, ni, nj, **topology)
 
     @property
     def dx(self):
@@ -227,6 +243,36 @@ class PlanarCartesian2DMesh(NamedTuple):
     def shape(self):
         return self.ni, self.nj
 
+    @property
+    def period_x(self):
+        """
+        The period of the domain along the x-axis, or 0 if it is not periodic.
+        """
+        return self.x1 - self.x0 if self.periodic_x else 0.0
+
+    @property
+    def period_y(self):
+        """
+        The period of the domain along the y-axis, or 0 if it is not periodic.
+        """
+        return self.y1 - self.y0 if self.periodic_y else 0.0
+
+    def separation(self, dx, dy):
+        """
+        Return the separation (dx, dy) between two points, replaced by the
+        separation to the nearest periodic image along the periodic axes. The
+        separations may be floats or arrays.
+        """
+        lx = self.period_x
+        ly = self.period_y
+
+        if lx > 0.0:
+            dx = dx - lx * ((dx / lx + 0.5) // 1.0)
+        if ly > 0.0:
+            dy = dy - ly * ((dy / ly + 0.5) // 1.0)
+
+        return dx, dy
+
     def min_spacing(self, time=None):
         return min(self.dx, self.dy)
 
@@ -0,0 +1,103 @@
+"""
+Write downsampled frames of a primitive field to a single appendable file.
+
+Movie frames are written at a much higher cadence than checkpoints, so each
+frame is block-averaged onto a fixed, coarse grid (512 zones per axis by
+default) and stored in single precision. The frames of a run are appended to
+one file per field, `movie.<field>.pk`, as a sequence of pickled records,
+which :func:`load_movie` reads back into a single array.
+"""
+
+from logging import getLogger
+
+logger = getLogger(__name__)
+
+
+def coarsen(array, size):
+    """
+    Block-average an array onto a grid with at most `size` zones per axis.
+
+    Each axis of the array is divided into `size` nearly equal blocks of
+    zones, and the zones within each block are averaged. Axes that already
+    have `size` zones or fewer are not changed.
+    """
+    import numpy as np
+
+    for axis, n in enumerate(array.shape):
+        if n > size:
+            edges = np.linspace(0, n, size + 1).astype(int)
+            shape = [-1 if a == axis else 1 for a in range(array.ndim)]
+            counts = np.diff(edges).reshape(shape)
+            array = np.add.reduceat(array, edges[:-1], axis=axis) / counts
+
+    return array
+
+
+def write_movie_frame(outdir, state, field=None, size=None):
+    """
+    Append a downsampled frame of a primitive field to the movie file in the
+    output directory. The field and frame size default to the driver's movie
+    field and size, and otherwise to the first primitive field of the solver
+    and 512 zones per axis.
+    """
+    import os
+    import pathlib
+    import pickle
+    import numpy as np
+    from sailfish.fields import field_index, solver_fields
+
+    solver = state.solver
+    primitive = solver.primitive
+    num_fields = primitive.shape[-1]
+    physics = state.setup.physics
+    fields = solver_fields(state.setup.solver, num_fields, physics)
+    name = field or state.driver.movie_field or fields[0].name
+    size = size or state.driver.movie_size or 512
+    q = field_index(state.setup.solver, num_fields, name, physics)
+    frame = coarsen(primitive[..., q], size)
+    filename = f"movie.{name}.pk"
+
+    if outdir is not None:
+        pathlib.Path(outdir).mkdir(parents=True, exist_ok=True)
+        filename = os.path.join(outdir, filename)
+
+    record = dict(
+        iteration=state.iteration,
+        time=solver.time,
+        mesh=state.mesh,
+        frame=frame.astype(np.float32),
+    )
+
+    with open(filename, "ab") as f:
+        pickle.dump(record, f)
+
+    logger.info(f"append movie frame to {filename}")
+
+
+def load_movie(filename):
+    """
+    Load the frames appended to a movie file, and return a dictionary with
+    the mesh and arrays of the iterations, times, and frames. The frames
+    array has the time as its first axis.
+    """
+    import pickle
+    import numpy as np
+
+    records = []
+
+    with open(filename, "rb") as f:
+        while True:
+            try:
+                records.append(pickle.load(f))
+            except EOFError:
+                break
+
+    if not records:
+        raise ValueError(f"movie file {filename} has no frames")
+
+    return dict(
+        mesh=records[-1]["mesh"],
+        iteration=np.array([r["iteration"] for r in records]),
+        time=np.array([r["time"] for r in records]),
+        frames=np.array([r["frame"] for r in records]),
+    )
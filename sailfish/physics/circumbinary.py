@@ -1,5 +1,6 @@
 from typing import NamedTuple, List, Callable, Union
 from enum import Enum
+from math import log10, sin, pi
 
 
 class SinkModel(Enum):
@@ -21,9 +22,79 @@ class ViscosityModel(Enum):
     CONSTANT_ALPHA = 2
 
 
+class TemperatureProfile(Enum):
+    POINT_MASSES = 0
+    CYLINDRICAL = 1
+
+
+class SofteningModel(Enum):
+    PLUMMER = 0
+    SPLINE = 1
+
+
+class SofteningUnits(Enum):
+    ABSOLUTE = 0
+    CELL_SIZE = 1
+    SCALE_HEIGHT = 2
+
+
+FLOOR_FIELDS = ("density", "pressure", "velocity")
+
+ACTIVATION_MAPS = ("density_floor", "pressure_floor", "velocity_ceiling", "limiter")
+
+SURFACE_FLUXES = (
+    "mass_flux",
+    "momentum_flux_x",
+    "momentum_flux_y",
+    "angular_momentum_flux",
+)
+
+ENERGY_BUDGET_RATES = ("gravitational_work", "viscous_dissipation", "cooling_loss")
+"""
+Rates of the energy budget, averaged over each time series interval: the
+power delivered to the gas by the gravity of the point masses, and the power
+lost to viscous dissipation and radiative cooling.
+"""
+
+DIAGNOSTIC_DIMENSIONS = dict(
+    time=(0, 0, 0),
+    mdot=(1, 0, -1),
+    mass=(1, 0, 0),
+    fx=(1, 1, -2),
+    fy=(1, 1, -2),
+    torque=(1, 2, -2),
+    spin=(1, 2, -2),
+    power=(1, 2, -3),
+    angular_momentum=(1, 2, -1),
+    sigma_m1=(1, 0, 0),
+    eccentricity_vector=(1, 0, 0),
+    histogram=(0, 2, 0),
+    fourier_modes=(1, 0, 0),
+    floor_count=(0, 0, 0),
+    mass_flux=(1, 0, -1),
+    momentum_flux_x=(1, 1, -2),
+    momentum_flux_y=(1, 1, -2),
+    angular_momentum_flux=(1, 2, -2),
+    kinetic_energy=(1, 2, -2),
+    thermal_energy=(1, 2, -2),
+    gravitational_work=(1, 2, -3),
+    viscous_dissipation=(1, 2, -3),
+    cooling_loss=(1, 2, -3),
+)
+"""
+Exponents of mass, length, and time in the dimensions of each diagnostic
+quantity, as reported by the solvers. The time diagnostic is in user time
+(orbits), so it is not converted.
+"""
+
+
 class Diagnostic(NamedTuple):
     quantity: str
-    """ time, mdot, ldot, mass_moment, eccentricity_vector """
+    """ time, mdot, mass, torque, fx, fy, spin, power, angular_momentum,
+    sigma_m1, eccentricity_vector, histogram, fourier_modes, floor_count, one
+    of the surface fluxes: mass_flux, momentum_flux_x, momentum_flux_y,
+    angular_momentum_flux, or one of the energy budget terms: kinetic_energy,
+    thermal_energy, gravitational_work, viscous_dissipation, cooling_loss """
 
     gravity: bool = False
     """ Whether to include the gravity term (if applicable) """
@@ -32,11 +103,231 @@ class Diagnostic(NamedTuple):
     """ Whether to include the accretion term (if applicable) """
 
     which_mass: Union[int, str] = None
-    """ 1, 2, or 'both' """
+    """ 1, 2, or 'both'; for surface fluxes, the circle center: 1, 2, or None """
 
     radial_cut: tuple = None
     """ None is ok, or a radial annulus to include e.g. (1.0, 2.0) """
 
+    field: str = None
+    """ Field to histogram: a primitive field name (see sailfish.fields),
+    mach_number, or torque_density; or for a floor_count, the floor or ceiling
+    counted: density, pressure, or velocity """
+
+    bins: tuple = None
+    """ Histogram or radial bin range and count, e.g. (1e-4, 10.0, 64) """
+
+    log_bins: bool = False
+    """ Whether the histogram bins are spaced logarithmically """
+
+    num_modes: int = 8
+    """ Number of azimuthal Fourier modes (m = 1, 2, ...) per radial bin """
+
+    radius: Union[float, tuple] = None
+    """ Radius of the circle through which a surface flux is measured, or the
+    inner and outer radii of an annulus, e.g. (1.0, 2.0) """
+
+    @property
+    def is_surface_flux(self):
+        return self.quantity in SURFACE_FLUXES
+
+    @property
+    def is_energy_rate(self):
+        return self.quantity in ENERGY_BUDGET_RATES
+
+    @property
+    def dimensions(self):
+        return DIAGNOSTIC_DIMENSIONS.get(self.quantity, (0, 0, 0))
+
+    def surface_flux_radii(self, width):
+        """
+        Return the inner and outer radii of the shell over which a surface
+        flux is averaged. A circle is widened to a shell of the given width.
+        """
+        if self.radius is None:
+            raise ValueError(f"diagnostic {self.quantity} requires a radius")
+
+        if isinstance(self.radius, (tuple, list)):
+            r0, r1 = self.radius
+
+            if not 0.0 <= r0 < r1:
+                raise ValueError(f"annulus {self.radius} must have 0 <= r0 < r1")
+            return r0, r1
+        else:
+            return self.radius - 0.5 * width, self.radius + 0.5 * width
+
+    def bin_edges(self, xp):
+        """
+        Return an array of histogram bin edges, allocated with the given array
+        module.
+        """
+        if self.bins is None:
+            raise ValueError("histogram diagnostic requires bins=(lo, hi, num)")
+
+        lo, hi, num = self.bins
+
+        if self.log_bins:
+            return xp.logspace(log10(lo), log10(hi), int(num) + 1)
+        else:
+            return xp.linspace(lo, hi, int(num) + 1)
+
+
+def mass_ramp(time, growth_time, start_time=0.0):
+    r"""
+    Return a factor which grows smoothly from zero to one, for use in
+    gradually inserting a point mass into a simulation.
+
+    The factor is :math:`\sin^2(\pi t / 2 t_g)` over the growth time
+    :math:`t_g`, following `de Val-Borro et al. (2006)`_, and is one
+    afterwards.
+
+    .. _de Val-Borro et al. (2006): https://ui.adsabs.harvard.edu/abs/2006MNRAS.370..529D
+    """
+    if growth_time <= 0.0 or time - start_time >= growth_time:
+        return 1.0
+    elif time <= start_time:
+        return 0.0
+    else:
+        return sin(0.5 * pi * (time - start_time) / growth_time) ** 2
+
+
+def dead_zone(r, inner_radius, outer_radius, depth, width):
+    r"""
+    Return a viscosity multiplier which is one outside of an annulus, and
+    falls to `depth` inside of it, for modeling a dead zone.
+
+    The edges are smoothed with :math:`\tanh` profiles of the given width, so
+    the multiplier is
+
+    .. math::
+        1 - \frac{1 - d}{2} \left[\tanh\left(\frac{r - r_0}{w}\right) -
+        \tanh\left(\frac{r - r_1}{w}\right)\right] \, .
+
+    The radius `r` may be a float or an array.
+    """
+    from numpy import tanh
+
+    a = tanh((r - inner_radius) / width)
+    b = tanh((r - outer_radius) / width)
+    return 1.0 - 0.5 * (1.0 - depth) * (a - b)
+
+
+class EccentricCavity(NamedTuple):
+    r"""
+    Describes an eccentric, depleted cavity in a circumbinary disk, for
+    initial conditions that start close to the relaxed state
+
+    The cavity edge is an ellipse with one focus at the origin, and with its
+    periapse on the positive x-axis,
+
+    .. math::
+        r_e(\phi) = \frac{a (1 - e^2)}{1 + e \cos \phi} \, .
+
+    Inside the edge, the density is depleted by the factor
+    :math:`d + (1 - d) \exp[-(r_e / r)^s]`, where :math:`d` is the depth and
+    :math:`s` the steepness. The gas moves on Keplerian orbits which are
+    confocal with the edge and aligned with it, and whose eccentricity is
+    :math:`e` at the edge and falls as :math:`(r_e / r)^2` outside of it.
+    """
+
+    radius: float = 2.5
+    """ Semi-major axis of the cavity edge """
+
+    eccentricity: float = 0.0
+    """ Eccentricity of the cavity edge """
+
+    steepness: float = 12.0
+    """ Power law index controlling the sharpness of the edge """
+
+    depth: float = 1e-4
+    """ Density factor in the center of the cavity """
+
+    def edge_radius(self, x, y):
+        """
+        Return the distance from the origin to the cavity edge, in the
+        direction of the point (x, y).
+        """
+        from math import atan2, cos
+
+        a = self.radius
+        e = self.eccentricity
+        return a * (1.0 - e * e) / (1.0 + e * cos(atan2(y, x)))
+
+    def density_factor(self, x, y):
+        """
+        Return the factor multiplying the disk density at the point (x, y).
+        """
+        from math import exp, sqrt
+
+        r = max(sqrt(x * x + y * y), 1e-12)
+        re = self.edge_radius(x, y)
+        return self.depth + (1.0 - self.depth) * exp(-((re / r) ** self.steepness))
+
+    def orbital_velocity(self, x, y, gm, r_softened):
+        """
+        Return the velocity (vx, vy) of gas on an eccentric Keplerian orbit
+        passing through the point (x, y), around a central mass gm. The
+        softened radius is used in place of the distance from the origin
+        when computing the orbit's semi-latus rectum.
+        """
+        from math import atan2, cos, sin, sqrt
+
+        r = max(sqrt(x * x + y * y), 1e-12)
+        phi = atan2(y, x)
+        e = self.eccentricity * min(1.0, (self.edge_radius(x, y) / r) ** 2)
+        p = r_softened * (1.0 + e * cos(phi))
+        vr = sqrt(gm / p) * e * sin(phi)
+        vp = sqrt(gm / p) * (1.0 + e * cos(phi))
+        return vr * x / r - vp * y / r, vr * y / r + vp * x / r
+
+
+class DyeRegion(NamedTuple):
+    """
+    Describes a region in which a passive tracer (dye) concentration is reset
+    at every time step, while the region is active
+
+    The region is an annulus, a circle, or a half-plane. The half-plane
+    contains the points whose displacement from the center, projected onto
+    the normal vector, is at least the offset.
+    """
+
+    tracer: int = 0
+    """ Index of the tracer to be set (0, 1, ...) """
+
+    shape: str = "annulus"
+    """ 'annulus', 'circle', or 'half_plane' """
+
+    center: Union[int, str] = "origin"
+    """ 'origin', or 1 or 2 to center the region on a point mass """
+
+    inner_radius: float = 0.0
+    """ Inner radius of an annulus """
+
+    outer_radius: float = 1.0
+    """ Outer radius of an annulus, or the radius of a circle """
+
+    normal: tuple = (1.0, 0.0)
+    """ Normal vector of a half-plane, pointing into the region """
+
+    offset: float = 0.0
+    """ Distance of a half-plane's edge from the center, along the normal """
+
+    start_time: float = 0.0
+    """ Simulation time at which the region becomes active """
+
+    end_time: float = None
+    """ Simulation time at which the region becomes inactive, if any """
+
+    concentration: float = 1.0
+    """ Tracer concentration imposed inside the region """
+
+    def is_active(self, time):
+        """
+        Return whether the region is active at the given simulation time.
+        """
+        if self.end_time is not None and time >= self.end_time:
+            return False
+        return time >= self.start_time
+
 
 class PointMass(NamedTuple):
     r"""
@@ -65,6 +356,15 @@ class PointMass(NamedTuple):
 
     The acceleration-free sink model is there for logical completeness, but it
     should not be used in practice.
+
+    The Plummer form of the potential above is the default. The physics
+    configuration may instead select a cubic spline kernel (`Springel 2001`_),
+    for which the potential is exactly Keplerian beyond the softening length.
+    The softening length may also be interpreted in units of the grid spacing
+    or the local disk scale height, rather than as an absolute length (see
+    :obj:`Physics.softening_units`).
+
+    .. _Springel 2001: https://ui.adsabs.harvard.edu/abs/2001NewA....6...79S
     """
 
     mass: float = 0.0
@@ -96,7 +396,7 @@ class PointMass(NamedTuple):
 
 
 class Physics(NamedTuple):
-    """
+    r"""
     Physics configuration for the binary accretion solvers
 
     Configuration categories are:
@@ -113,6 +413,17 @@ class Physics(NamedTuple):
        supports the former two modes, and the cbdgam_2d solver only upports
        the last mode.
 
+       In locally isothermal mode, the temperature profile is prescribed
+       either from the distance to each point mass (the default), or from the
+       cylindrical radius about the origin. In both cases the disk aspect
+       ratio is the inverse of the Mach number at unit radius, and it scales
+       with radius as :math:`r^f`, where :math:`f` is the flaring index:
+
+       .. math::
+           c_s^2 = \frac{1}{\mathcal{M}^2} \sum_p \frac{G M_p}{r_p} r_p^{2f}
+           \quad {\rm or} \quad
+           c_s^2 = \frac{1}{\mathcal{M}^2} \frac{G M}{r} r^{2f} \, .
+
     2. Gravitating point masses
 
        Point masses can be optionally provided to model stars or black holes.
@@ -124,17 +435,78 @@ class Physics(NamedTuple):
        function, mapping the simulation time to a sequence of particles.
        Currently, solvers support either zero, one, or two particles.
 
+       An additional, time-dependent external acceleration field can also be
+       supplied through a callback function. This can be used to model, for
+       example, a turbulent stirring force. A common need is to grow the mass
+       of a point mass slowly from zero; see the :func:`mass_ramp` function.
+
+       The point masses can also grow by the mass their sinks accrete. If the
+       :obj:`accreted_mass` list is given, the cbdiso_2d and cbdgam_2d solvers
+       add the mass removed by each sink to it after every time step, and the
+       point mass callback is expected to add it to the masses it returns.
+       The gravity source terms then use the updated masses.
+
+       Most robustness problems originate in the few zones near the point
+       masses, where the flow is fast and steep. Zones within
+       :obj:`first_order_radius` of a gravitating point mass can be made to
+       use first-order (piecewise constant) reconstruction, rather than PLM,
+       which is more diffusive but does not overshoot. Currently only the
+       cbdiso_2d and cbdgam_2d solvers support this option.
+
     3. Viscosity model
 
        Two different viscosity models are nominally supported: constant-nu,
        and constant alpha. Currently the isothermal solver only supports
        constant-nu viscosity.
 
-    4. Thermal cooling
+       The viscosity coefficient (nu or alpha) can be made a function of the
+       distance from the origin, by supplying a callback which returns a
+       dimensionless multiplier of the coefficient, for example to model a
+       dead zone (see :func:`dead_zone`) or a transition disk. The solvers
+       tabulate the callback once, on a uniform grid of radii (see
+       :meth:`viscosity_profile_table`), and interpolate the table in the
+       viscous flux kernels.
+
+    4. Thermal cooling and energy reservoirs
+
+       The cbdgam_2d solver cools the gas at a rate
+       :math:`d\epsilon/dt = -C \epsilon^4 / \Sigma^2`, where :math:`C` is
+       the cooling coefficient, and the rate is integrated exactly over each
+       time step. The cooled internal energy is limited so that the Mach
+       number does not exceed the solver's Mach ceiling.
+
+       The cbdgam_2d solver can also evolve any number of internal energy
+       reservoirs (for example, a second temperature for a two-temperature
+       plasma) alongside the gas. Each reservoir is advected with the gas, and
+       exchanges heat with the gas thermal energy at a rate
+       :math:`\kappa \Sigma (\epsilon - \epsilon_r)`, where :math:`\kappa`
+       is the energy exchange rate, so that their temperatures relax toward
+       one another. The reservoirs do not currently add to the gas pressure.
+
+    5. Vertical structure
+
+       By default, the disk scale height is estimated from the local sound
+       speed and the point mass potential, assuming vertical hydrostatic
+       equilibrium. The cbdgam_2d solver can instead evolve the scale height
+       :math:`H` and its rate of change :math:`W = dH/dt` as additional fields
+       (a 2.5D model), so that the disk thickness lags the thermal state:
+
+       .. math::
+           \frac{dW}{dt} = \frac{P}{\Sigma H} - \tilde \Omega^2 H \, .
+
+       The work done by vertical expansion is taken from the thermal energy.
 
-       Todo.
+    6. Passive tracers
 
-    5. An outer buffer zone
+       Any number of passive tracers (dye concentrations) can be advected
+       with the gas, to follow mixing and the origin of accreted gas. Tracers
+       are removed by the sinks along with the gas, and are driven to zero in
+       the buffer zone. The tracer concentrations are reset to prescribed
+       values inside of dye regions (see :obj:`DyeRegion`) at every time step
+       while the regions are active.
+       Currently only the cbdiso_2d solver supports tracers.
+
+    7. An outer buffer zone
 
        For binary accretion problems in a square domain, it can be useful to
        impose a wave-damping zone (or sponge layer) to avoid artifacts fromt
@@ -148,6 +520,38 @@ class Physics(NamedTuple):
        between the domain radius (the half-width of a square domain), extending
        inwards by an amount specified by the :obj:`buffer_onset_width`
        parameter.
+
+       For quasi-steady accretion runs, the buffer can instead be used to
+       feed the disk at a controlled rate :math:`\dot M`, by setting
+       :obj:`buffer_accretion_rate`. The buffer then drives the solution
+       toward the steady, constant-nu viscous disk solution,
+
+       .. math::
+           \Sigma = \frac{\dot M}{3 \pi \nu} \, , \quad
+           v_r = -\frac{3 \nu}{2 r} \, ,
+
+       which has a flat surface density profile, in place of the sampled
+       initial condition with zero radial velocity. Currently only the
+       cbdiso_2d solver supports this option.
+
+    8. Shearing box
+
+       For local studies of disk instabilities, the equations can be solved
+       in a shearing box: a small patch of the disk, in a frame rotating at
+       the local orbital frequency :math:`\Omega`, where x points radially
+       outward and y in the direction of rotation. Setting
+       :obj:`shearing_box_frequency` adds the Coriolis and tidal
+       accelerations,
+
+       .. math::
+           a_x = 2 \Omega v_y + 2 q \Omega^2 x \, , \quad
+           a_y = -2 \Omega v_x \, ,
+
+       with the Keplerian shear rate :math:`q = 3/2`, so the background flow
+       is :math:`v_y = -q \Omega x`. The box must use shear-periodic
+       boundaries (the `shearing_periodic` boundary condition), and has no
+       point masses or buffer zone. Currently only the cbdiso_2d solver
+       supports the shearing box.
     """
 
     eos_type: EquationOfState = EquationOfState.GLOBALLY_ISOTHERMAL
@@ -159,6 +563,12 @@ class Physics(NamedTuple):
     mach_number: float = 10.0
     """ Square of the Mach number, if EOS type is locally isothermal """
 
+    temperature_profile: TemperatureProfile = TemperatureProfile.POINT_MASSES
+    """ Locally isothermal sound speed from point mass or cylindrical distance """
+
+    flaring_index: float = 0.0
+    """ Power law index of the aspect ratio, if EOS type is locally isothermal """
+
     gamma_law_index: float = 5.0 / 3.0
     """ Adiabatic index, if the EOS type is not isothermal """
 
@@ -171,6 +581,9 @@ class Physics(NamedTuple):
     alpha: float = 0.0
     """ Alpha parameter, if viscosity model is alpha """
 
+    viscosity_profile: Callable = None
+    """ Callback r -> f, a radial profile multiplying the viscosity coefficient """
+
     buffer_is_enabled: bool = False
     """ Whether the buffer zone is enabled """
 
@@ -180,15 +593,52 @@ class Physics(NamedTuple):
     buffer_onset_width: float = 0.1
     """ Distance over which the buffer ramps up """
 
+    buffer_accretion_rate: float = None
+    """ If given, the buffer feeds a steady viscous disk at this rate """
+
     point_mass_function: Callable[[float], List[PointMass]] = None
     """ Callback function to supply point masses as a function of time """
 
+    accreted_mass: List[float] = None
+    """ If given, a list of the mass accreted by each sink, which the solver
+    adds to each step; the point mass function should include it """
+
+    external_acceleration: Callable = None
+    """ Callback (t, x, y) -> (ax, ay) for an external acceleration field """
+
+    shearing_box_frequency: float = 0.0
+    """ Orbital frequency of a local shearing box frame, or zero if global """
+
     cooling_coefficient: float = 0.0
     """ Strength of the cooling term """
 
     constant_softening: bool = True
     """ If local disk height is ignored in gravitational softening """
 
+    softening_model: SofteningModel = SofteningModel.PLUMMER
+    """ Form of the softened point mass potential: Plummer or cubic spline """
+
+    softening_units: SofteningUnits = SofteningUnits.ABSOLUTE
+    """ Units of the softening length: absolute, cell size, or scale height """
+
+    first_order_radius: float = 0.0
+    """ Distance from each point mass within which reconstruction is first order """
+
+    vertical_structure: bool = False
+    """ Whether to evolve the disk scale height (gamma-law only) """
+
+    num_energy_reservoirs: int = 0
+    """ Number of separately evolved internal energy fields (gamma-law only) """
+
+    energy_exchange_rate: float = 0.0
+    """ Rate at which the energy reservoirs exchange heat with the gas """
+
+    num_tracers: int = 0
+    """ Number of passive tracers advected with the gas """
+
+    dye_regions: List[DyeRegion] = []
+    """ Regions where tracer concentrations are set at prescribed times """
+
     diagnostics: List[Diagnostic] = []
     """ Physics diagnostics to be returned when reductions are computed """
 
@@ -224,3 +674,49 @@ class Physics(NamedTuple):
         raise ValueError(
             "point_mass_function returned an unsupported description of point masses"
         )
+
+    def viscosity_profile_table(self, outer_radius, num_samples=1024):
+        """
+        Return a table of the viscosity profile, sampled uniformly from r=0 to
+        the given outer radius, and the spacing of the samples. If there is no
+        profile, the table has a single entry of 1.0.
+        """
+        import numpy as np
+
+        if self.viscosity_profile is None:
+            return np.ones(1), outer_radius
+
+        r = np.linspace(0.0, outer_radius, num_samples)
+        table = np.array(self.viscosity_profile(r), dtype=float) * np.ones_like(r)
+
+        if (table < 0.0).any():
+            raise ValueError("the viscosity profile must be non-negative")
+
+        return table, r[1] - r[0]
+
+    def with_softening_scale(self, scale):
+        """
+        Return a copy of this physics configuration, in which the softening
+        length of each point mass is multiplied by the given factor. This is
+        used by solvers to convert softening lengths given in units of the
+        grid spacing to absolute lengths.
+        """
+        point_mass_function = self.point_mass_function
+
+        if point_mass_function is None:
+            return self
+
+        def scaled(m):
+            return m._replace(softening_length=m.softening_length * scale)
+
+        def scaled_point_mass_function(time):
+            masses = point_mass_function(time)
+
+            if masses is None:
+                return None
+            elif isinstance(masses, PointMass):
+                return scaled(masses)
+            else:
+                return type(masses)(scaled(m) for m in masses)
+
+        return self._replace(point_mass_function=scaled_point_mass_function)
@@ -0,0 +1,73 @@
+"""
+Seeded Gaussian random fields, for perturbing the initial conditions of
+setups.
+"""
+
+
+class GaussianRandomField:
+    """
+    A 2D Gaussian random field with a power-law spectrum.
+
+    The field is generated on a periodic square grid covering the domain
+    `[-extent, extent]` in each direction, with power spectrum
+    :math:`P(k) \\propto k^n` where :math:`n` is the spectral index, and is
+    normalized to have zero mean and the given RMS amplitude. The field is
+    generated on its own grid, rather than the simulation mesh, so that a
+    given seed produces the same perturbation at any mesh resolution. Values
+    between the grid points are obtained by bilinear interpolation.
+    """
+
+    def __init__(
+        self,
+        extent=1.0,
+        num_zones=256,
+        spectral_index=-2.0,
+        amplitude=1.0,
+        seed=0,
+        min_wavelength=None,
+    ):
+        import numpy as np
+
+        rng = np.random.default_rng(seed)
+        noise = np.fft.rfft2(rng.standard_normal((num_zones, num_zones)))
+        kx = np.fft.fftfreq(num_zones)[:, None] * num_zones
+        ky = np.fft.rfftfreq(num_zones)[None, :] * num_zones
+        k = (kx**2 + ky**2) ** 0.5
+        k[0, 0] = 1.0
+
+        spectrum = k ** (0.5 * spectral_index)
+        spectrum[0, 0] = 0.0
+
+        if min_wavelength is not None:
+            spectrum[k > 2.0 * extent / min_wavelength] = 0.0
+
+        field = np.fft.irfft2(noise * spectrum, s=(num_zones, num_zones))
+        field *= amplitude / max(field.std(), 1e-300)
+
+        self.extent = extent
+        self.num_zones = num_zones
+        self.field = field
+
+    def __call__(self, x, y):
+        """
+        Return the field value at the point (x, y).
+        """
+        from math import floor
+
+        n = self.num_zones
+        u = (x + self.extent) / (2.0 * self.extent) * n - 0.5
+        v = (y + self.extent) / (2.0 * self.extent) * n - 0.5
+        i = floor(u)
+        j = floor(v)
+        a = u - i
+        b = v - j
+        f = self.field
+        i0, i1 = i % n, (i + 1) % n
+        j0, j1 = j % n, (j + 1) % n
+
+        return (
+            f[i0, j0] * (1.0 - a) * (1.0 - b)
+            + f[i1, j0] * a * (1.0 - b)
+            + f[i0, j1] * (1.0 - a) * b
+            + f[i1, j1] * a * b
+        )
@@ -0,0 +1,120 @@
+"""
+A stochastic driving force for driven-turbulence experiments.
+"""
+
+from math import exp, pi
+
+
+def array_module(a):
+    """
+    Return the numpy or cupy module, whichever allocated the given array.
+    """
+    import numpy
+
+    if isinstance(a, numpy.ndarray) or isinstance(a, float):
+        return numpy
+    else:
+        import cupy
+
+        return cupy
+
+
+class StochasticForcing:
+    """
+    A solenoidal, random acceleration field in 2D.
+
+    The field is a sum of Fourier modes with integer wave numbers between
+    `kmin` and `kmax` over the square domain `[-extent, extent]`. Each mode
+    has a complex amplitude which evolves as an Ornstein-Uhlenbeck process
+    with the given correlation time, following `Eswaran & Pope (1988)`_. The
+    acceleration of each mode is perpendicular to its wave vector, so the
+    forcing is divergence-free. The amplitude parameter is the RMS value of
+    the acceleration.
+
+    The random number generator and mode amplitudes are part of the
+    forcing's `state`, which should be written to checkpoints, so that
+    restarted runs are identical to continuous ones.
+
+    .. _Eswaran & Pope (1988): https://doi.org/10.1016/0045-7930(88)90013-8
+    """
+
+    def __init__(
+        self,
+        extent=1.0,
+        amplitude=1.0,
+        correlation_time=1.0,
+        kmin=1,
+        kmax=3,
+        seed=0,
+        time=0.0,
+    ):
+        import numpy as np
+
+        wavevectors = []
+
+        for nx in range(-kmax, kmax + 1):
+            for ny in range(0, kmax + 1):
+                if ny == 0 and nx <= 0:
+                    continue
+                if kmin**2 <= nx**2 + ny**2 <= kmax**2:
+                    wavevectors.append((nx * pi / extent, ny * pi / extent))
+
+        self.wavevectors = np.array(wavevectors)
+        self.correlation_time = correlation_time
+        self.sigma = amplitude * (2.0 / len(wavevectors)) ** 0.5
+        self.rng = np.random.default_rng(seed)
+        self.coefficients = self.random_coefficients(self.sigma)
+        self.time = time
+
+    def random_coefficients(self, sigma):
+        n = len(self.wavevectors)
+        re = self.rng.standard_normal(n)
+        im = self.rng.standard_normal(n)
+        return sigma * (re + 1j * im)
+
+    @property
+    def state(self):
+        """
+        The state of the random process, for writing to checkpoints.
+        """
+        return dict(rng=self.rng, coefficients=self.coefficients, time=self.time)
+
+    @state.setter
+    def state(self, state):
+        self.rng = state["rng"]
+        self.coefficients = state["coefficients"]
+        self.time = state["time"]
+
+    def advance(self, time):
+        """
+        Evolve the mode amplitudes forward to the given time.
+
+        The exact update for the Ornstein-Uhlenbeck process is used, so the
+        statistics of the forcing do not depend on the timestep. Requests
+        for earlier times (e.g. Runge-Kutta stages, or a retried step) do
+        not change the amplitudes.
+        """
+        if time > self.time:
+            f = exp(-(time - self.time) / self.correlation_time)
+            noise = self.random_coefficients(self.sigma * (1.0 - f * f) ** 0.5)
+            self.coefficients = self.coefficients * f + noise
+            self.time = time
+
+    def acceleration(self, time, x, y):
+        """
+        Return the acceleration components (ax, ay) at the given time, and at
+        the coordinates in the x and y arrays.
+        """
+        xp = array_module(x)
+        self.advance(time)
+        ax = 0.0
+        ay = 0.0
+
+        for (kx, ky), c in zip(self.wavevectors, self.coefficients):
+            phase = kx * x + ky * y
+            a = c.real * xp.cos(phase) - c.imag * xp.sin(phase)
+            k = (kx * kx + ky * ky) ** 0.5
+            ax = ax - a * ky / k
+            ay = ay + a * kx / k
+
+        return ax, ay
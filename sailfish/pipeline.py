@@ -0,0 +1,84 @@
+"""
+Compute time series diagnostics on a worker thread, off the main step loop.
+"""
+
+from logging import getLogger
+from queue import Queue
+from threading import Thread
+
+logger = getLogger(__name__)
+
+
+class DiagnosticsPipeline:
+    """
+    A queue of reduction snapshots, evaluated in order by a worker thread.
+
+    Each time series event submits a snapshot of the solver state, obtained
+    from :py:meth:`sailfish.solver_base.SolverBase.reductions_snapshot`, and
+    returns immediately. The worker thread evaluates the snapshots and
+    appends the results to the time series list. The pipeline must be
+    flushed before the time series is written to a checkpoint, so that no
+    pending results are missing from it. An exception raised on the worker
+    thread is raised again from the next call to `submit` or `flush`.
+    """
+
+    def __init__(self, timeseries, max_pending=8):
+        self.timeseries = timeseries
+        self.queue = Queue(maxsize=max_pending)
+        self.error = None
+        self.thread = Thread(target=self._work, daemon=True)
+        self.thread.start()
+
+    def _work(self):
+        while True:
+            compute = self.queue.get()
+            try:
+                if compute is None:
+                    return
+                if self.error is None:
+                    reductions = compute()
+
+                    if reductions:
+                        self.timeseries.append(reductions)
+                        logger.info(
+                            f"record timeseries event {len(self.timeseries)}"
+                        )
+                    else:
+                        logger.warning(
+                            "timeseries event ignored because solver does not "
+                            "provide reductions"
+                        )
+            except Exception as e:
+                self.error = e
+            finally:
+                self.queue.task_done()
+
+    def _raise_error(self):
+        if self.error is not None:
+            error, self.error = self.error, None
+            raise error
+
+    def submit(self, solver):
+        """
+        Snapshot the solver's reductions and queue them for evaluation.
+
+        If `max_pending` snapshots are already queued, this blocks until the
+        worker has caught up, which bounds the memory held by snapshots.
+        """
+        self._raise_error()
+        self.queue.put(solver.reductions_snapshot())
+
+    def flush(self):
+        """
+        Block until every queued snapshot has been appended to the time series.
+        """
+        self.queue.join()
+        self._raise_error()
+
+    def close(self):
+        """
+        Flush the pipeline and stop the worker thread.
+        """
+        self.flush()
+        self.queue.put(None)
+        self.thread.join()
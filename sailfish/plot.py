@@ -0,0 +1,162 @@
+"""
+Quick-look images of checkpoint files, for the `sailfish plot` subcommand.
+
+Images are rendered with matplotlib's non-interactive backend, so they can be
+made on cluster nodes with no display. The fields available for plotting are
+those written by the `convert` subcommand.
+"""
+
+from logging import getLogger
+from sailfish.export import checkpoint_fields, field_units
+
+logger = getLogger(__name__)
+
+
+def plot_checkpoint(chkpt_file, field=None, cmap="viridis", log=False, **kwargs):
+    """
+    Render an image of a checkpoint field to a PNG file, and return the
+    name of the file written.
+
+    If no field is given, the first primitive field (usually the density) is
+    plotted. Fields on 1D meshes are plotted as a line. Remaining keyword
+    arguments are `vmin`, `vmax`, `output` filename, and `dpi`. A
+    `ConfigurationError` is raised if the field is unknown, or if the
+    checkpoint's solver or mesh cannot be exported.
+    """
+    from os.path import splitext
+    import matplotlib
+
+    matplotlib.use("Agg")
+
+    import matplotlib.pyplot as plt
+    import numpy as np
+    from sailfish.driver import ConfigurationError, load_checkpoint
+
+    chkpt = load_checkpoint(chkpt_file)
+
+    try:
+        fields = checkpoint_fields(chkpt)
+    except ValueError as e:
+        raise ConfigurationError(f"cannot plot {chkpt_file}: {e}")
+
+    coordinates = [key for key in ("x", "y", "r", "theta") if key in fields]
+    field = field or next(key for key in fields if key not in coordinates)
+
+    if field not in fields or field in coordinates:
+        available = ", ".join(key for key in fields if key not in coordinates)
+        raise ConfigurationError(f"unknown field {field}, options are {available}")
+
+    data = fields[field]
+    units = field_units(field, chkpt["solver"])
+    label = f"{field} [{units}]" if units else field
+    label = f"log10({label})" if log else label
+    vmin, vmax = kwargs.get("vmin"), kwargs.get("vmax")
+    output = kwargs.get("output") or f"{splitext(chkpt_file)[0]}.{field}.png"
+
+    if log:
+        data = np.log10(np.abs(data) + 1e-300)
+
+    fig, ax = plt.subplots(figsize=(8, 8) if data.ndim == 2 else (8, 5))
+
+    if data.ndim == 1:
+        x = fields[coordinates[0]]
+        ax.plot(x, data)
+        ax.set_xlabel(coordinates[0])
+        ax.set_ylabel(label)
+        ax.set_ylim(vmin, vmax)
+        if coordinates[0] == "r":
+            ax.set_xscale("log")
+    else:
+        if "theta" in fields:
+            x = fields["r"] * np.sin(fields["theta"])
+            y = fields["r"] * np.cos(fields["theta"])
+        else:
+            x, y = fields["x"], fields["y"]
+        cm = ax.pcolormesh(x, y, data, cmap=cmap, vmin=vmin, vmax=vmax, shading="auto")
+        ax.set_aspect("equal")
+        fig.colorbar(cm, ax=ax, label=label)
+
+    ax.set_title(f"{chkpt['setup_name']} t={chkpt['time']:0.3f}")
+    fig.savefig(output, dpi=kwargs.get("dpi") or 150, bbox_inches="tight")
+    plt.close(fig)
+    logger.info(f"write {output}")
+    return output
+
+
+def argument_parser():
+    """
+    Return the argument parser for the `sailfish plot` subcommand.
+    """
+    import argparse
+
+    parser = argparse.ArgumentParser(
+        prog="sailfish plot",
+        description="render quick-look PNG images of checkpoint files",
+    )
+    parser.add_argument(
+        "checkpoints",
+        nargs="+",
+        metavar="CHKPT",
+        help="checkpoint files to plot",
+    )
+    parser.add_argument(
+        "--field",
+        "-f",
+        help="field to plot (default is the density)",
+    )
+    parser.add_argument(
+        "--cmap",
+        default="viridis",
+        help="matplotlib colormap name",
+    )
+    parser.add_argument(
+        "--log",
+        "-l",
+        action="store_true",
+        help="plot the base-10 logarithm of the field",
+    )
+    parser.add_argument(
+        "--vmin",
+        type=float,
+        help="lower limit of the color scale",
+    )
+    parser.add_argument(
+        "--vmax",
+        type=float,
+        help="upper limit of the color scale",
+    )
+    parser.add_argument(
+        "--dpi",
+        type=int,
+        help="image resolution in dots per inch",
+    )
+    parser.add_argument(
+        "--output",
+        "-o",
+        metavar="F",
+        help="output filename (only if a single checkpoint is given)",
+    )
+    return parser
+
+
+def main(argv):
+    """
+    Command line interface for the `sailfish plot` subcommand.
+    """
+    parser = argument_parser()
+    args = parser.parse_args(argv)
+
+    if args.output is not None and len(args.checkpoints) > 1:
+        parser.error("--output requires a single checkpoint")
+
+    for chkpt_file in args.checkpoints:
+        plot_checkpoint(
+            chkpt_file,
+            field=args.field,
+            cmap=args.cmap,
+            log=args.log,
+            vmin=args.vmin,
+            vmax=args.vmax,
+            dpi=args.dpi,
+            output=args.output,
+        )
@@ -0,0 +1,258 @@
+"""
+Named output products, each written at its own cadence and in its own format.
+
+An output product is a kind of data written periodically during a run:
+checkpoints, time series samples, radial profiles, movie frames, or torque
+maps. Products are configured in sections of the .sailfish file named
+`output.<name>`, with a `kind`, an `interval` (a recurrence rule, as for the
+`--checkpoint` option), an optional `format`, and any options of that kind.
+The kind may be omitted if the name is one of the kinds. For example:
+
+.. code-block:: ini
+
+    [output.checkpoint]
+    interval = 10.0
+
+    [output.snapshots]
+    kind = checkpoint
+    interval = log:0.5
+    format = hdf5
+
+    [output.profiles]
+    kind = profile
+    interval = 0.1
+    bins = 256
+
+    [output.sigma-movie]
+    kind = movie
+    interval = 0.01
+    field = sigma
+    size = 256
+
+Each product is a separate driver event, named after the product, so the
+products are numbered independently. A command line option of the same name,
+such as `--checkpoint`, overrides the interval of the configured product.
+Files are named `<name>.<number>.<ext>`, except for pickle checkpoints of the
+product named `checkpoint`, which keep the `chkpt.<number>.pk` names used to
+find restart files.
+"""
+
+from logging import getLogger
+from typing import NamedTuple, Dict
+from sailfish.event import Recurrence
+from sailfish.export import EXTENSIONS
+from sailfish.status import STATUS
+
+logger = getLogger(__name__)
+
+FORMAT_EXTENSIONS = dict(pickle=".pk", npz=".npz", txt=".txt", **EXTENSIONS)
+
+PRODUCT_FORMATS = dict(
+    checkpoint=["pickle", "hdf5", "vtk", "csv", "msgpack"],
+    lite_checkpoint=["pickle"],
+    timeseries=["pickle"],
+    profile=["npz", "txt"],
+    movie=["pickle"],
+    torque_map=["npz"],
+)
+"""
+The kinds of output products, and the formats supported by each. The first
+format of each kind is the default.
+"""
+
+
+class ProductConfigError(Exception):
+    """An output product was configured incorrectly"""
+
+
+class OutputProduct(NamedTuple):
+    """
+    A named output product, written at its own cadence and in its own format
+    """
+
+    name: str
+    kind: str
+    interval: Recurrence
+    format: str = None
+    options: Dict[str, str] = dict()
+
+    @property
+    def extension(self):
+        """
+        The filename extension for files of this product.
+        """
+        return FORMAT_EXTENSIONS[self.format]
+
+
+def product_from_config(name, items):
+    """
+    Return an :obj:`OutputProduct` from the items of an `output.<name>`
+    section of the user configuration file. Keys other than `kind`,
+    `interval`, and `format` are kept as options of the product.
+    """
+    from sailfish.event import ParseRecurrenceError
+
+    options = dict(items)
+    kind = options.pop("kind", name.replace("-", "_"))
+
+    if kind not in PRODUCT_FORMATS:
+        raise ProductConfigError(
+            f"output product {name} has unknown kind {kind}, "
+            f"options are {', '.join(PRODUCT_FORMATS)}"
+        )
+
+    if "interval" not in options:
+        raise ProductConfigError(f"output product {name} needs an interval")
+
+    try:
+        interval = Recurrence.from_str(options.pop("interval"))
+    except ParseRecurrenceError as e:
+        raise ProductConfigError(f"output product {name}: {e}")
+
+    format = options.pop("format", PRODUCT_FORMATS[kind][0])
+
+    if format not in PRODUCT_FORMATS[kind]:
+        raise ProductConfigError(
+            f"output product {name} has unknown format {format}, "
+            f"options are {', '.join(PRODUCT_FORMATS[kind])}"
+        )
+
+    return OutputProduct(name, kind, interval, format, options)
+
+
+def product_filename(product, number, outdir):
+    """
+    Return the name of the file to write for an output product, creating the
+    output directory if needed.
+    """
+    import os
+    import pathlib
+
+    stem = product.name
+
+    if product.name == "checkpoint" and product.format == "pickle":
+        stem = "chkpt"
+
+    if type(number) is int:
+        filename = f"{stem}.{number:04d}{product.extension}"
+    else:
+        filename = f"{stem}.{number}{product.extension}"
+
+    if outdir is not None:
+        pathlib.Path(outdir).mkdir(parents=True, exist_ok=True)
+        filename = os.path.join(outdir, filename)
+
+    return filename
+
+
+def radial_profiles(state, bins=None):
+    """
+    Return a dictionary of the radius and the primitive fields averaged in
+    radial bins, from the driver state.
+
+    On 1D meshes the fields are returned as they are. On polar meshes they
+    are averaged over the polar angle, and on 2D Cartesian meshes they are
+    averaged in `bins` annuli about the origin (by default half the number
+    of zones on the first axis).
+    """
+    import numpy as np
+    from sailfish.export import cell_coordinates
+    from sailfish.fields import solver_fields
+
+    primitive = state.solver.primitive
+    coordinates = cell_coordinates(state.mesh, state.solver.time)
+    fields = solver_fields(
+        state.setup.solver, primitive.shape[-1], state.setup.physics
+    )
+
+    if "y" in coordinates:
+        r = np.hypot(coordinates["x"], coordinates["y"]).flatten()
+        bins = int(bins or primitive.shape[0] // 2)
+        edges = np.linspace(0.0, r.max(), bins + 1)
+        index = np.clip(np.digitize(r, edges) - 1, 0, bins - 1)
+        counts = np.maximum(np.bincount(index, minlength=bins), 1)
+        profiles = dict(radius=0.5 * (edges[1:] + edges[:-1]))
+
+        for q, field in enumerate(fields):
+            values = primitive[..., q].flatten()
+            profiles[field.name] = np.bincount(index, values, bins) / counts
+
+        return profiles
+
+    if "theta" in coordinates:
+        profiles = dict(radius=coordinates["r"][:, 0])
+        primitive = primitive.mean(axis=1)
+    else:
+        profiles = dict(radius=next(iter(coordinates.values())))
+
+    for q, field in enumerate(fields):
+        profiles[field.name] = primitive[..., q]
+
+    return profiles
+
+
+def write_product(product, number, outdir, state):
+    """
+    Write an output product from the driver state.
+
+    Time series products are not written to a file; they are sampled by the
+    driver, and written with the checkpoints.
+    """
+    import pickle
+    import numpy as np
+    from sailfish.driver import (
+        append_timeseries,
+        checkpoint_dict,
+        write_lite_checkpoint,
+    )
+
+    if product.kind == "timeseries":
+        append_timeseries(state)
+
+    elif product.kind == "lite_checkpoint":
+        write_lite_checkpoint(number, outdir, state)
+
+    elif product.kind == "movie":
+        from sailfish.movie import write_movie_frame
+
+        size = product.options.get("size")
+        field = product.options.get("field")
+        write_movie_frame(outdir, state, field, size and int(size))
+
+    elif product.kind == "checkpoint":
+        from sailfish.export import WRITERS
+
+        filename = product_filename(product, number, outdir)
+        logger.info(f"write checkpoint {filename}")
+
+        if product.format == "pickle":
+            with open(filename, "wb") as f:
+                pickle.dump(checkpoint_dict(state), f)
+        else:
+            units = product.options.get("units", "code")
+            WRITERS[product.format](checkpoint_dict(state), filename, units=units)
+
+        STATUS.update(last_checkpoint=filename)
+
+    elif product.kind == "profile":
+        filename = product_filename(product, number, outdir)
+        profiles = radial_profiles(state, product.options.get("bins"))
+        logger.info(f"write profile {filename}")
+
+        if product.format == "npz":
+            np.savez(filename, time=state.solver.time, **profiles)
+        else:
+            header = f"time = {state.solver.time}\n" + " ".join(profiles)
+            columns = np.column_stack(list(profiles.values()))
+            np.savetxt(filename, columns, header=header)
+
+    elif product.kind == "torque_map":
+        maps = state.solver.torque_maps()
+
+        if not maps:
+            logger.warning(f"{product.name} ignored; the solver has no torque maps")
+            return
+
+        filename = product_filename(product, number, outdir)
+        logger.info(f"write torque maps {filename}")
+        np.savez(filename, time=state.solver.time, **maps)
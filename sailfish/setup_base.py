@@ -177,6 +177,17 @@ class SetupBase(ABC):
         """
         pass
 
+    def equilibrium_primitive(self, time, coordinate, primitive):
+        """
+        Set the primitive variables of the unperturbed equilibrium at a point.
+
+        Setups which seed perturbations on top of an equilibrium state should
+        override this method to omit them. It is used by solvers which need
+        the underlying equilibrium, e.g. to make the scheme well-balanced. The
+        default implementation is the same as `primitive`.
+        """
+        self.primitive(time, coordinate, primitive)
+
     @abstractmethod
     def mesh(self, resolution: int):
         """
@@ -276,6 +287,20 @@ class SetupBase(ABC):
         """
         pass
 
+    @property
+    def unit_system(self):
+        """
+        The physical scale of the code units, as a
+        :py:obj:`sailfish.units.UnitSystem`, or `None`.
+
+        Setups are free to define code units in which the problem is scale
+        free, and return `None` (the default). Setups which are given a
+        physical scale, for example through model parameters, return the
+        unit system, which is written to checkpoints and used to convert the
+        output to CGS or SI units.
+        """
+        return None
+
     def checkpoint_diagnostics(self, time):
         """
         Return a dict of post-processing data to include in checkpoint files.
@@ -284,3 +309,21 @@ class SetupBase(ABC):
         prescribed trajectory) in a gravitating hydrodynmics problem.
         """
         return dict()
+
+    def checkpoint_state(self):
+        """
+        Return any internal state of the setup needed for exact restarts.
+
+        Most setups are fully described by their model parameters, and should
+        return `None` (the default). Setups with evolving internal state, such
+        as the random number generator of a stochastic forcing term, return
+        an object to be written to checkpoints and passed back to
+        :py:meth:`restore_state` on restarts.
+        """
+        return None
+
+    def restore_state(self, state):
+        """
+        Restore the internal state returned by :py:meth:`checkpoint_state`.
+        """
+        pass
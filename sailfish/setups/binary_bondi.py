@@ -12,7 +12,7 @@ as Bondi-Hoyle-Lyttleton accretion. Some references below:
 
 from sailfish.mesh import PlanarCartesian2DMesh
 from sailfish.physics.circumbinary import SinkModel, PointMass, EquationOfState
-from sailfish.physics.kepler import OrbitalElements
+from sailfish.physics.orbits import OrbitalElements
 from sailfish.setup_base import SetupBase, param
 
 
@@ -2,16 +2,30 @@
 2D disk setups for binary problems.
 """
 
+from functools import cached_property
 from math import sqrt, exp, pi
 from sailfish.mesh import LogSphericalMesh, PlanarCartesian2DMesh
 from sailfish.physics.circumbinary import (
+    EccentricCavity,
     EquationOfState,
     PointMass,
     SinkModel,
+    SofteningModel,
+    SofteningUnits,
+    TemperatureProfile,
     ViscosityModel,
+    dead_zone,
+    mass_ramp,
 )
-from sailfish.physics.kepler import OrbitalElements
+from sailfish.physics.orbits import (
+    GravitationalWaveInspiral,
+    OrbitalElements,
+    circular_orbital_state,
+)
+from sailfish.physics.random_field import GaussianRandomField
+from sailfish.physics.stochastic_forcing import StochasticForcing
 from sailfish.setup_base import SetupBase, SetupError, param
+from sailfish.units import ASTRONOMICAL_UNIT, SOLAR_MASS, SPEED_OF_LIGHT, UnitSystem
 
 
 class CircumbinaryDisk(SetupBase):
@@ -38,6 +52,51 @@ class CircumbinaryDisk(SetupBase):
     .. math::
         \Sigma \propto r^{-3/5}, \, \mathcal{P} \propto r^{-3/2}
 
+    Alternatively, the disk can be warm-started from a tabulated radial
+    profile, such as a viscous disk solution relaxed in a previous run. The
+    profile is a text file whose columns are the radius, surface density,
+    radial velocity, azimuthal velocity, and (for gamma-law mode) the
+    vertically-integrated pressure. The profile is linearly interpolated onto
+    the mesh, and is held constant beyond the tabulated range of radii.
+
+    The disk can also be started with an eccentric, depleted cavity around
+    the binary, to save the thousands of orbits needed for the cavity to form
+    and become eccentric (see :obj:`EccentricCavity`). The cavity is enabled
+    by giving it a nonzero radius (its semi-major axis). The density (and
+    pressure) are depleted inside of the cavity, and the gas is placed on
+    eccentric orbits aligned with it.
+
+    The viscosity coefficient (nu or alpha) can vary with radius, for
+    dead-zone or transition-disk experiments. It is multiplied by a dead-zone
+    profile, which falls to a given depth inside of an annulus, and by a
+    tabulated profile: a text file whose columns are the radius and the
+    multiplier. The tabulated profile is linearly interpolated, and held
+    constant beyond its range of radii.
+
+    The component masses can change over time, with the binary orbit held
+    fixed. With `mass_growth=ramp`, the total mass and mass ratio change
+    linearly from their initial values to the final ones, over the ramp time.
+    With `mass_growth=accretion`, each component gains the mass accreted by
+    its sink. In both cases the gravity source terms use the current masses,
+    and the components are kept in the frame of their center of mass.
+
+    The binary separation can also shrink over time, on a circular orbit.
+    With `inspiral=gw`, it decays at the rate of gravitational-wave emission
+    (see :obj:`GravitationalWaveInspiral`), for the speed of light given in
+    code units, or implied by the unit system. With `inspiral=schedule`, the
+    separation is interpolated from a text file whose columns are the time
+    (in orbits) and the separation, and it is held constant beyond the
+    tabulated range of times. In both cases the orbital frequency is the
+    Keplerian one for the current separation and the initial masses.
+
+    For robustness near the sinks, the reconstruction can be dropped to first
+    order in the zones within `first_order_radius` of each point mass.
+
+    The initial surface density (and pressure, in gamma-law mode) can also be
+    multiplied by a factor :math:`1 + \delta`, where :math:`\delta` is a
+    seeded Gaussian random field with a power-law spectrum, to seed
+    instabilities reproducibly rather than from grid noise.
+
     .. _Shakura & Sunyaev (1973): https://ui.adsabs.harvard.edu/abs/1973A%26A....24..337S
     .. _Goodman (2003): https://ui.adsabs.harvard.edu/abs/2003MNRAS.339..937G
     """
@@ -45,12 +104,33 @@ class CircumbinaryDisk(SetupBase):
     eos = param("isothermal", "EOS type: either isothermal or gamma-law")
     domain_radius = param(12.0, "half side length of the square computational domain")
     mach_number = param(10.0, "orbital Mach number (isothermal)", mutable=True)
+    temperature_profile = param(
+        "point_masses", "sound speed profile [point_masses|cylindrical] (isothermal)"
+    )
+    flaring_index = param(0.0, "power law index of the aspect ratio (isothermal)")
     eccentricity = param(0.0, "orbital eccentricity of the binary", mutable=True)
     mass_ratio = param(1.0, "component mass ratio m2 / m1 <= 1", mutable=True)
+    secondary_growth_time = param(0.0, "time (in orbits) to grow the secondary mass")
+    mass_growth = param("none", "time-dependent masses [none|ramp|accretion]")
+    final_mass_ratio = param(1.0, "mass ratio at the end of the mass ramp")
+    final_total_mass = param(1.0, "total binary mass at the end of the mass ramp")
+    ramp_start = param(0.0, "time (in orbits) at which the mass ramp begins")
+    ramp_time = param(100.0, "duration (in orbits) of the mass ramp")
+    inspiral = param("none", "shrinking binary separation [none|gw|schedule]")
+    light_speed = param(0.0, "speed of light in code units (0 to use the units)")
+    inspiral_schedule = param("", "file with columns of time (in orbits) and a")
     sink_rate = param(10.0, "component sink rate", mutable=True)
     sink_radius = param(0.05, "component sink radius", mutable=True)
     softening_length = param(0.05, "gravitational softening length", mutable=True)
+    softening_model = param("plummer", "softening kernel [plummer|spline]")
+    softening_units = param(
+        "absolute", "softening length units [absolute|cells|scale_height]"
+    )
+    first_order_radius = param(
+        0.0, "radius around each point mass of first-order reconstruction", mutable=True
+    )
     buffer_is_enabled = param(True, "whether the buffer zone is enabled", mutable=True)
+    feeding_rate = param(0.0, "accretion rate fed through the buffer (isothermal)")
     sink_model = param(
         "torque_free", "sink [acceleration_free|force_free|torque_free]", mutable=True
     )
@@ -59,18 +139,326 @@ class CircumbinaryDisk(SetupBase):
     cooling_coefficient = param(0.0, "strength of the cooling term (gamma-law)")
     alpha = param(0.1, "alpha-viscosity parameter (gamma-law)")
     nu = param(0.001, "kinematic viscosity parameter (isothermal)")
+    viscosity_profile = param("", "file with a tabulated radial viscosity multiplier")
+    dead_zone_inner = param(0.0, "inner radius of a low-viscosity dead zone")
+    dead_zone_outer = param(0.0, "outer radius of the dead zone (0 to disable)")
+    dead_zone_depth = param(0.01, "viscosity multiplier inside the dead zone")
+    dead_zone_width = param(0.25, "width of the dead zone edges")
     constant_softening = param(True, "whether to use constant softening (gamma-law)")
     gamma_law_index = param(5.0 / 3.0, "adiabatic index (gamma-law)")
-    which_diagnostics = param("none", "diagnostics set to get from solver [none|mdots]")
+    vertical_structure = param(False, "whether to evolve the scale height (gamma-law)")
+    energy_reservoirs = param(0, "number of separate internal energies (gamma-law)")
+    energy_exchange_rate = param(0.0, "heat exchange rate of the energy reservoirs")
+    which_diagnostics = param(
+        "none",
+        "diagnostics set from the solver "
+        "[none|mdots|histograms|modes|fluxes|sinks|floors|energy]",
+    )
+    dye = param("none", "passive dye regions [none|sinks|cavity|halves] (isothermal)")
+    dye_time = param(0.0, "time (in orbits) from which the dye is injected")
+    dye_duration = param(0.0, "time (in orbits) for which dye is injected (0: no end)")
+    initial_profile = param("", "file with a tabulated radial profile to start from")
+    cavity_radius = param(0.0, "semi-major axis of an initial cavity (0 to disable)")
+    cavity_eccentricity = param(0.0, "eccentricity of the initial cavity")
+    cavity_steepness = param(12.0, "power law index of the cavity edge profile")
+    cavity_depth = param(1e-4, "density factor in the center of the cavity")
+    perturbation = param(0.0, "RMS amplitude of random density perturbations")
+    perturbation_slope = param(-2.0, "spectral index of the density perturbations")
+    perturbation_seed = param(0, "random seed for the density perturbations")
+    forcing = param(0.0, "RMS amplitude of stochastic turbulent driving", mutable=True)
+    forcing_time = param(1.0, "correlation time (in orbits) of the driving")
+    forcing_kmax = param(3, "largest wave number of the driving modes")
+    forcing_seed = param(0, "random seed for the stochastic driving")
+    unit_mass = param(0.0, "binary mass in solar masses (0 for code units)")
+    unit_length = param(0.0, "binary separation in AU (0 for code units)")
 
     def validate(self):
         if not self.is_isothermal and not self.is_gamma_law:
             raise SetupError(f"eos must be isothermal or gamma-law, got {self.eos}")
-        if self.which_diagnostics not in ["none", "mdots"]:
+        if self.temperature_profile not in ["point_masses", "cylindrical"]:
+            raise SetupError(
+                "temperature_profile must be point_masses or cylindrical, "
+                f"got {self.temperature_profile}"
+            )
+        if self.softening_model not in ["plummer", "spline"]:
+            raise SetupError(
+                f"softening_model must be plummer or spline, got {self.softening_model}"
+            )
+        if self.softening_units not in ["absolute", "cells", "scale_height"]:
+            raise SetupError(
+                "softening_units must be absolute, cells, or scale_height, "
+                f"got {self.softening_units}"
+            )
+        if (self.unit_mass > 0.0) != (self.unit_length > 0.0):
+            raise SetupError("unit_mass and unit_length must be given together")
+        if self.dye not in ["none", "sinks", "cavity", "halves"]:
+            raise SetupError(
+                f"dye must be none, sinks, cavity, or halves, got {self.dye}"
+            )
+        if self.dye_duration < 0.0:
+            raise SetupError("dye_duration must be non-negative")
+        if self.dye == "halves" and self.dye_duration == 0.0:
+            raise SetupError("dye=halves requires a positive dye_duration")
+        if self.dye != "none" and not self.is_isothermal:
+            raise SetupError("passive dye is only supported with isothermal eos")
+        if self.vertical_structure and not self.is_gamma_law:
+            raise SetupError("vertical structure is only supported with gamma-law eos")
+        if self.energy_reservoirs and not self.is_gamma_law:
+            raise SetupError("energy reservoirs are only supported with gamma-law eos")
+        if self.energy_reservoirs < 0:
+            raise SetupError("energy_reservoirs must be non-negative")
+        if self.feeding_rate < 0.0:
+            raise SetupError("feeding_rate must be non-negative")
+        if self.feeding_rate > 0.0 and not (self.is_isothermal and self.nu > 0.0):
+            raise SetupError("feeding_rate requires isothermal eos and nu > 0")
+        if self.feeding_rate > 0.0 and not self.buffer_is_enabled:
+            raise SetupError("feeding_rate requires the buffer to be enabled")
+        if self.which_diagnostics not in [
+            "none",
+            "mdots",
+            "histograms",
+            "modes",
+            "fluxes",
+            "sinks",
+            "floors",
+            "energy",
+        ]:
+            raise SetupError(
+                "which_diagnostics must be none, mdots, histograms, modes, "
+                f"fluxes, sinks, floors, or energy, got {self.which_diagnostics}"
+            )
+        if self.which_diagnostics == "energy" and not self.is_gamma_law:
+            raise SetupError("energy diagnostics are only supported with gamma-law eos")
+        if self.mass_growth not in ["none", "ramp", "accretion"]:
+            raise SetupError(
+                f"mass_growth must be none, ramp, or accretion, got {self.mass_growth}"
+            )
+        if self.mass_growth != "none" and self.secondary_growth_time > 0.0:
+            raise SetupError("mass_growth cannot be used with secondary_growth_time")
+        if self.mass_growth == "ramp":
+            if self.ramp_time <= 0.0:
+                raise SetupError("ramp_time must be positive")
+            if self.final_mass_ratio <= 0.0 or self.final_total_mass <= 0.0:
+                raise SetupError("final mass ratio and total mass must be positive")
+        if self.inspiral not in ["none", "gw", "schedule"]:
+            raise SetupError(
+                f"inspiral must be none, gw, or schedule, got {self.inspiral}"
+            )
+        if self.inspiral != "none" and self.eccentricity != 0.0:
+            raise SetupError("inspiral requires a circular orbit (eccentricity=0)")
+        if self.inspiral == "gw" and self.speed_of_light is None:
             raise SetupError(
-                f"which_diagnostics must be none or mdots, got {self.which_diagnostics}"
+                "inspiral=gw needs light_speed, or unit_mass and unit_length"
+            )
+        if self.inspiral == "schedule":
+            if not self.inspiral_schedule:
+                raise SetupError("inspiral=schedule needs an inspiral_schedule file")
+            self.inspiral_table  # load and check the file
+        if self.first_order_radius < 0.0:
+            raise SetupError("first_order_radius must be non-negative")
+        if self.cavity_radius < 0.0:
+            raise SetupError("cavity_radius must be non-negative")
+        if not 0.0 <= self.cavity_eccentricity < 1.0:
+            raise SetupError("cavity_eccentricity must be in [0, 1)")
+        if self.cavity_steepness <= 0.0:
+            raise SetupError("cavity_steepness must be positive")
+        if not 0.0 < self.cavity_depth <= 1.0:
+            raise SetupError("cavity_depth must be in (0, 1]")
+        if self.cavity_radius > 0.0 and self.initial_profile:
+            raise SetupError("an initial cavity cannot be used with initial_profile")
+        if self.perturbation < 0.0:
+            raise SetupError("perturbation amplitude must be non-negative")
+        if self.forcing < 0.0:
+            raise SetupError("forcing amplitude must be non-negative")
+        if self.forcing_time <= 0.0:
+            raise SetupError("forcing_time must be positive")
+        if self.forcing_kmax < 1:
+            raise SetupError("forcing_kmax must be at least 1")
+        if self.dead_zone_outer > 0.0:
+            if self.dead_zone_outer <= self.dead_zone_inner:
+                raise SetupError("dead_zone_outer must exceed dead_zone_inner")
+            if self.dead_zone_width <= 0.0:
+                raise SetupError("dead_zone_width must be positive")
+            if self.dead_zone_depth < 0.0:
+                raise SetupError("dead_zone_depth must be non-negative")
+        if self.viscosity_profile:
+            self.viscosity_table  # load and check the file
+
+    @cached_property
+    def radial_profile(self):
+        """
+        Load the tabulated radial profile from the initial profile file.
+
+        The columns are returned as a list of numpy arrays: radius, surface
+        density, radial velocity, azimuthal velocity, and optionally pressure.
+        Lines beginning with a '#' are ignored. The file is loaded when the
+        initial condition is first generated, so it is not needed to restart
+        from a checkpoint.
+        """
+        import numpy as np
+
+        try:
+            table = np.loadtxt(self.initial_profile, ndmin=2)
+        except OSError as e:
+            raise SetupError(f"could not load initial_profile: {e}")
+
+        if table.shape[1] not in (4, 5):
+            raise SetupError("initial_profile must have 4 or 5 columns")
+        if self.is_gamma_law and table.shape[1] != 5:
+            raise SetupError("initial_profile needs a pressure column (gamma-law)")
+        if (np.diff(table[:, 0]) <= 0.0).any():
+            raise SetupError("initial_profile radii must be increasing")
+
+        return list(table.T)
+
+    @cached_property
+    def viscosity_table(self):
+        """
+        Load the tabulated viscosity multiplier from the viscosity profile
+        file, and return the radius and multiplier columns.
+        """
+        import numpy as np
+
+        try:
+            table = np.loadtxt(self.viscosity_profile, ndmin=2)
+        except OSError as e:
+            raise SetupError(f"could not load viscosity_profile: {e}")
+
+        if table.shape[1] != 2:
+            raise SetupError("viscosity_profile must have 2 columns")
+        if (np.diff(table[:, 0]) <= 0.0).any():
+            raise SetupError("viscosity_profile radii must be increasing")
+        if (table[:, 1] < 0.0).any():
+            raise SetupError("viscosity_profile must be non-negative")
+
+        return table[:, 0], table[:, 1]
+
+    @cached_property
+    def inspiral_table(self):
+        """
+        Load the tabulated separation from the inspiral schedule file, and
+        return arrays of the time (in code units), the separation, its rate
+        of change, and the orbital phase. The phase is integrated from the
+        Keplerian frequency on a fine grid, and is zero at the first time.
+        """
+        import numpy as np
+
+        try:
+            table = np.loadtxt(self.inspiral_schedule, ndmin=2)
+        except OSError as e:
+            raise SetupError(f"could not load inspiral_schedule: {e}")
+
+        if table.shape[1] != 2 or table.shape[0] < 2:
+            raise SetupError("inspiral_schedule must have 2 columns and 2 rows")
+        if (np.diff(table[:, 0]) <= 0.0).any():
+            raise SetupError("inspiral_schedule times must be increasing")
+        if (table[:, 1] <= 0.0).any():
+            raise SetupError("inspiral_schedule separations must be positive")
+
+        tc = table[:, 0] * 2.0 * pi
+        ac = table[:, 1]
+        t = np.linspace(tc[0], tc[-1], max(8192, 64 * len(tc)))
+        a = np.interp(t, tc, ac)
+        w = a**-1.5
+        phase = np.zeros_like(t)
+        phase[1:] = np.cumsum(0.5 * (w[1:] + w[:-1]) * np.diff(t))
+        rate = np.interp(t, 0.5 * (tc[1:] + tc[:-1]), np.diff(ac) / np.diff(tc))
+
+        return t, a, rate, phase
+
+    def viscosity_multiplier(self, r):
+        """
+        Return the factor multiplying the viscosity coefficient at radius r,
+        which may be a float or an array.
+        """
+        import numpy as np
+
+        f = 1.0
+
+        if self.viscosity_profile:
+            rc, fc = self.viscosity_table
+            f = f * np.interp(r, rc, fc)
+
+        if self.dead_zone_outer > 0.0:
+            f = f * dead_zone(
+                r,
+                self.dead_zone_inner,
+                self.dead_zone_outer,
+                self.dead_zone_depth,
+                self.dead_zone_width,
+            )
+
+        return f
+
+    @property
+    def radial_viscosity_profile(self):
+        if self.viscosity_profile or self.dead_zone_outer > 0.0:
+            return self.viscosity_multiplier
+
+    @property
+    def cavity(self):
+        """
+        Return the initial eccentric cavity, if it is enabled.
+        """
+        if self.cavity_radius > 0.0:
+            return EccentricCavity(
+                radius=self.cavity_radius,
+                eccentricity=self.cavity_eccentricity,
+                steepness=self.cavity_steepness,
+                depth=self.cavity_depth,
+            )
+
+    @cached_property
+    def perturbation_field(self):
+        """
+        Return the random field used to perturb the initial density.
+        """
+        return GaussianRandomField(
+            extent=self.domain_radius,
+            spectral_index=self.perturbation_slope,
+            amplitude=self.perturbation,
+            seed=self.perturbation_seed,
+        )
+
+    @cached_property
+    def stochastic_forcing(self):
+        """
+        Return the stochastic driving force, if the forcing is enabled.
+        """
+        if self.forcing > 0.0:
+            return StochasticForcing(
+                extent=self.domain_radius,
+                amplitude=self.forcing,
+                correlation_time=self.forcing_time * 2.0 * pi,
+                kmax=self.forcing_kmax,
+                seed=self.forcing_seed,
             )
 
+    @property
+    def external_acceleration(self):
+        forcing = self.stochastic_forcing
+        return forcing.acceleration if forcing is not None else None
+
+    def profile_primitive(self, x, y, primitive):
+        """
+        Interpolate the tabulated radial profile to the given coordinates.
+        """
+        import numpy as np
+
+        r = sqrt(x * x + y * y)
+        r_hat_x = x / max(r, 1e-12)
+        r_hat_y = y / max(r, 1e-12)
+        rc, sigma, vr, vp, *pressure = self.radial_profile
+        vr = np.interp(r, rc, vr)
+        vp = np.interp(r, rc, vp)
+
+        primitive[0] = np.interp(r, rc, sigma)
+        primitive[1] = vr * r_hat_x - vp * r_hat_y
+        primitive[2] = vr * r_hat_y + vp * r_hat_x
+
+        if self.is_gamma_law:
+            primitive[3] = np.interp(r, rc, pressure[0])
+
     @property
     def is_isothermal(self):
         return self.eos == "isothermal"
@@ -80,6 +468,17 @@ class CircumbinaryDisk(SetupBase):
         return self.eos == "gamma-law"
 
     def primitive(self, t, coords, primitive):
+        self.equilibrium_primitive(t, coords, primitive)
+
+        if self.perturbation > 0.0:
+            x, y = coords
+            delta = max(self.perturbation_field(x, y), -0.9)
+            primitive[0] *= 1.0 + delta
+
+            if self.is_gamma_law:
+                primitive[3] *= 1.0 + delta
+
+    def equilibrium_primitive(self, t, coords, primitive):
         GM = 1.0
         x, y = coords
         r = sqrt(x * x + y * y)
@@ -87,7 +486,10 @@ class CircumbinaryDisk(SetupBase):
         phi_hat_x = -y / max(r, 1e-12)
         phi_hat_y = +x / max(r, 1e-12)
 
-        if self.is_isothermal:
+        if self.initial_profile:
+            self.profile_primitive(x, y, primitive)
+
+        elif self.is_isothermal:
             primitive[0] = self.initial_sigma
             primitive[1] = sqrt(GM / r_softened) * phi_hat_x
             primitive[2] = sqrt(GM / r_softened) * phi_hat_y
@@ -107,6 +509,15 @@ class CircumbinaryDisk(SetupBase):
                 * (0.0001 + 0.9999 * exp(-((1.0 / r_softened) ** 30)))
             )
 
+        if self.cavity is not None:
+            cavity = self.cavity
+            f = cavity.density_factor(x, y)
+            primitive[0] *= f
+            primitive[1:3] = cavity.orbital_velocity(x, y, GM, r_softened)
+
+            if self.is_gamma_law:
+                primitive[3] *= f
+
     def mesh(self, resolution):
         return PlanarCartesian2DMesh.centered_square(self.domain_radius, resolution)
 
@@ -120,17 +531,34 @@ class CircumbinaryDisk(SetupBase):
             return dict(
                 eos_type=EquationOfState.LOCALLY_ISOTHERMAL,
                 mach_number=self.mach_number,
+                temperature_profile=TemperatureProfile[
+                    self.temperature_profile.upper()
+                ],
+                flaring_index=self.flaring_index,
                 point_mass_function=self.point_masses,
+                accreted_mass=self.accreted_mass,
                 buffer_is_enabled=self.buffer_is_enabled,
                 buffer_driving_rate=100.0,
                 buffer_onset_width=1.0,
+                buffer_accretion_rate=self.feeding_rate or None,
                 cooling_coefficient=0.0,
                 constant_softening=self.constant_softening,
+                softening_model=SofteningModel[self.softening_model.upper()],
+                softening_units=dict(
+                    absolute=SofteningUnits.ABSOLUTE,
+                    cells=SofteningUnits.CELL_SIZE,
+                    scale_height=SofteningUnits.SCALE_HEIGHT,
+                )[self.softening_units],
                 viscosity_model=ViscosityModel.CONSTANT_NU
                 if self.nu > 0.0
                 else ViscosityModel.NONE,
                 viscosity_coefficient=self.nu,
                 alpha=0.0,
+                viscosity_profile=self.radial_viscosity_profile,
+                first_order_radius=self.first_order_radius,
+                num_tracers=len(self.dye_regions),
+                dye_regions=self.dye_regions,
+                external_acceleration=self.external_acceleration,
                 diagnostics=self.diagnostics,
             )
 
@@ -139,30 +567,163 @@ class CircumbinaryDisk(SetupBase):
                 eos_type=EquationOfState.GAMMA_LAW,
                 gamma_law_index=self.gamma_law_index,
                 point_mass_function=self.point_masses,
+                accreted_mass=self.accreted_mass,
                 buffer_is_enabled=self.buffer_is_enabled,
                 buffer_driving_rate=1000.0,  # default value in circumbinary.py
                 buffer_onset_width=0.1,  # default value in circumbinary.py
                 cooling_coefficient=self.cooling_coefficient,
                 constant_softening=self.constant_softening,
+                vertical_structure=self.vertical_structure,
+                num_energy_reservoirs=self.energy_reservoirs,
+                energy_exchange_rate=self.energy_exchange_rate,
+                softening_model=SofteningModel[self.softening_model.upper()],
+                softening_units=dict(
+                    absolute=SofteningUnits.ABSOLUTE,
+                    cells=SofteningUnits.CELL_SIZE,
+                    scale_height=SofteningUnits.SCALE_HEIGHT,
+                )[self.softening_units],
                 viscosity_model=ViscosityModel.CONSTANT_ALPHA
                 if self.alpha > 0.0
                 else ViscosityModel.NONE,
                 viscosity_coefficient=0.0,
                 alpha=self.alpha,
+                viscosity_profile=self.radial_viscosity_profile,
+                first_order_radius=self.first_order_radius,
+                external_acceleration=self.external_acceleration,
                 diagnostics=self.diagnostics,
             )
 
     @property
-    def diagnostics(self):
-        if self.which_diagnostics != "none":
+    def dye_regions(self):
+        start_time = self.dye_time * 2.0 * pi
+        end_time = start_time + self.dye_duration * 2.0 * pi
+
+        if self.dye_duration == 0.0:
+            end_time = None
+
+        if self.dye == "sinks":
             return [
-                dict(quantity="time"),
-                dict(quantity="mdot", which_mass=1, accretion=True),
-                dict(quantity="mdot", which_mass=2, accretion=True),
+                dict(
+                    tracer=n,
+                    shape="circle",
+                    center=n + 1,
+                    outer_radius=4.0 * self.sink_radius,
+                    start_time=start_time,
+                    end_time=end_time,
+                )
+                for n in range(2)
+            ]
+        elif self.dye == "cavity":
+            return [
+                dict(
+                    tracer=0,
+                    inner_radius=2.0,
+                    outer_radius=3.0,
+                    start_time=start_time,
+                    end_time=end_time,
+                )
+            ]
+        elif self.dye == "halves":
+            # Each half of the domain, on either side of the y-axis, is
+            # marked with its own tracer, to follow the mixing of the disk.
+            return [
+                dict(
+                    tracer=n,
+                    shape="half_plane",
+                    normal=(1.0 - 2.0 * n, 0.0),
+                    start_time=start_time,
+                    end_time=end_time,
+                )
+                for n in range(2)
             ]
         else:
             return []
 
+    @property
+    def diagnostics(self):
+        if self.which_diagnostics == "none":
+            return []
+
+        mdots = [
+            dict(quantity="time"),
+            dict(quantity="mdot", which_mass=1, accretion=True),
+            dict(quantity="mdot", which_mass=2, accretion=True),
+        ]
+
+        if self.which_diagnostics == "histograms":
+            return mdots + [
+                dict(
+                    quantity="histogram",
+                    field="sigma",
+                    bins=(1e-6, 1e2, 64),
+                    log_bins=True,
+                ),
+                dict(
+                    quantity="histogram",
+                    field="mach_number",
+                    bins=(1e-2, 1e2, 64),
+                    log_bins=True,
+                ),
+                dict(
+                    quantity="histogram",
+                    field="torque_density",
+                    bins=(-1.0, 1.0, 64),
+                ),
+            ]
+        elif self.which_diagnostics == "modes":
+            return mdots + [
+                dict(
+                    quantity="fourier_modes",
+                    bins=(0.0, self.domain_radius, 48),
+                    num_modes=8,
+                ),
+            ]
+        elif self.which_diagnostics == "floors":
+            # The number of times each floor or ceiling has been applied
+            # since the previous time series sample.
+            floors = ["density", "velocity"] + ["pressure"] * self.is_gamma_law
+            return mdots + [dict(quantity="floor_count", field=f) for f in floors]
+        elif self.which_diagnostics == "sinks":
+            # The rates of mass, linear momentum, and angular momentum (about
+            # the origin and about each point mass) accreted by each sink,
+            # and the gravitational torque on each point mass.
+            return mdots + [
+                dict(quantity=q, which_mass=m, **term)
+                for m in (1, 2)
+                for q, term in [
+                    ("fx", dict(accretion=True)),
+                    ("fy", dict(accretion=True)),
+                    ("torque", dict(accretion=True)),
+                    ("spin", dict(accretion=True)),
+                    ("torque", dict(gravity=True)),
+                ]
+            ]
+        elif self.which_diagnostics == "fluxes":
+            # Fluxes through circles around each point mass, and through the
+            # cavity edge at r = 2a.
+            return mdots + [
+                dict(quantity="mass_flux", which_mass=1, radius=4 * self.sink_radius),
+                dict(quantity="mass_flux", which_mass=2, radius=4 * self.sink_radius),
+                dict(quantity="mass_flux", radius=2.0),
+                dict(quantity="angular_momentum_flux", radius=2.0),
+            ]
+        elif self.which_diagnostics == "energy":
+            # The kinetic and thermal energy of the gas, and the rates of
+            # gravitational work, viscous dissipation, and cooling, averaged
+            # since the previous time series sample.
+            return mdots + [
+                dict(quantity=q)
+                for q in (
+                    "kinetic_energy",
+                    "thermal_energy",
+                    "gravitational_work",
+                    "viscous_dissipation",
+                    "cooling_loss",
+                )
+            ]
+        else:
+            return mdots
+
     @property
     def solver(self):
         if self.is_isothermal:
@@ -176,6 +737,8 @@ class CircumbinaryDisk(SetupBase):
 
     @property
     def default_end_time(self):
+        if self.inspiral == "gw":
+            return min(1000.0, 0.99 * self.gw_inspiral.merger_time / (2.0 * pi))
         return 1000.0
 
     @property
@@ -191,8 +754,96 @@ class CircumbinaryDisk(SetupBase):
             eccentricity=self.eccentricity,
         )
 
+    @property
+    def speed_of_light(self):
+        """
+        The speed of light in code units, for the gravitational-wave
+        inspiral, or `None` if it is not known.
+        """
+        if self.light_speed > 0.0:
+            return self.light_speed
+        if self.unit_system is not None:
+            u = self.unit_system
+            return SPEED_OF_LIGHT * u.time / u.length
+
+    @property
+    def gw_inspiral(self):
+        return GravitationalWaveInspiral(
+            initial_separation=1.0,
+            total_mass=1.0,
+            mass_ratio=self.mass_ratio,
+            speed_of_light=self.speed_of_light,
+        )
+
+    def orbital_state(self, time):
+        """
+        Return the orbital state of the binary at the given time, before any
+        change to the component masses.
+        """
+        if self.inspiral == "gw":
+            return self.gw_inspiral.orbital_state(time)
+
+        if self.inspiral == "schedule":
+            import numpy as np
+
+            tc, ac, rc, pc = self.inspiral_table
+
+            if time < tc[0]:
+                a, adot, phase = ac[0], 0.0, ac[0] ** -1.5 * (time - tc[0])
+            elif time > tc[-1]:
+                a, adot = ac[-1], 0.0
+                phase = pc[-1] + ac[-1] ** -1.5 * (time - tc[-1])
+            else:
+                a = np.interp(time, tc, ac)
+                adot = np.interp(time, tc, rc)
+                phase = np.interp(time, tc, pc)
+
+            return circular_orbital_state(
+                float(a), float(adot), float(phase), 1.0, self.mass_ratio
+            )
+
+        return self.orbital_elements.orbital_state(time)
+
+    @cached_property
+    def accreted_mass(self):
+        """
+        Return the list of the masses accreted by each component, which the
+        solver adds to, if the masses grow by accretion.
+        """
+        if self.mass_growth == "accretion":
+            return [0.0, 0.0]
+
+    def component_masses(self, time):
+        """
+        Return the masses of the two components at the given time.
+        """
+        m1 = 1.0 / (1.0 + self.mass_ratio)
+        m2 = 1.0 - m1
+
+        if self.mass_growth == "ramp":
+            t0 = self.ramp_start * 2.0 * pi
+            s = min(max((time - t0) / (self.ramp_time * 2.0 * pi), 0.0), 1.0)
+            m = 1.0 + s * (self.final_total_mass - 1.0)
+            q = self.mass_ratio + s * (self.final_mass_ratio - self.mass_ratio)
+            return m / (1.0 + q), m * q / (1.0 + q)
+
+        if self.mass_growth == "accretion":
+            dm1, dm2 = self.accreted_mass
+            return m1 + dm1, m2 + dm2
+
+        return m1, m2
+
     def point_masses(self, time):
-        m1, m2 = self.orbital_elements.orbital_state(time)
+        state = self.orbital_state(time)
+
+        if self.mass_growth != "none":
+            state = state.with_masses(*self.component_masses(time))
+
+        m1, m2 = state
+
+        if self.secondary_growth_time > 0.0:
+            ramp = mass_ramp(time, self.secondary_growth_time * 2.0 * pi)
+            m2 = m2._replace(mass=m2.mass * ramp)
 
         return (
             PointMass(
@@ -211,8 +862,34 @@ class CircumbinaryDisk(SetupBase):
             ),
         )
 
+    @property
+    def unit_system(self):
+        if self.unit_mass > 0.0:
+            return UnitSystem.gravitational(
+                mass=self.unit_mass * SOLAR_MASS,
+                length=self.unit_length * ASTRONOMICAL_UNIT,
+            )
+
     def checkpoint_diagnostics(self, time):
-        return dict(point_masses=self.point_masses(time))
+        return dict(point_masses=self.point_masses(time), diagnostics=self.diagnostics)
+
+    def checkpoint_state(self):
+        state = dict()
+        forcing = self.stochastic_forcing
+
+        if forcing is not None:
+            state["forcing"] = forcing.state
+        if self.accreted_mass is not None:
+            state["accreted_mass"] = list(self.accreted_mass)
+
+        return state or None
+
+    def restore_state(self, state):
+        forcing = self.stochastic_forcing
+        if forcing is not None and "forcing" in state:
+            forcing.state = state["forcing"]
+        if self.accreted_mass is not None and "accreted_mass" in state:
+            self.accreted_mass[:] = state["accreted_mass"]
 
 
 class KitpCodeComparison(SetupBase):
@@ -6,7 +6,14 @@ from math import pi, sin
 from sailfish.setup_base import SetupBase, SetupError, param
 from sailfish.mesh import PlanarCartesianMesh, LogSphericalMesh
 
-__all__ = ["Advection", "Burgers", "DensityWave", "Shocktube", "Wind"]
+__all__ = [
+    "Advection",
+    "Burgers",
+    "DensityWave",
+    "ManufacturedSolution",
+    "Shocktube",
+    "Wind",
+]
 
 
 class Advection(SetupBase):
@@ -168,3 +175,65 @@ class Wind(SetupBase):
     def validate(self):
         if self.velocity < 0.0:
             raise SetupError("velocity must be non-negative")
+
+
+class ManufacturedSolution(SetupBase):
+    """
+    A smooth, time-dependent flow made an exact solution of the srhd
+    equations by a forcing term, for verifying the convergence of the solver
+    and its boundary conditions.
+
+    The density, pressure, and scalar vary as cos(2 pi x), and the
+    four-velocity as sin^2(pi x), so the flow is periodic, its gradients
+    vanish at x=0 and x=1, and the velocity vanishes there. It is thus
+    compatible with periodic, outflow, inflow, and reflecting boundaries. See
+    :py:mod:`sailfish.physics.manufactured`.
+    """
+
+    amplitude = param(0.1, "amplitude of the variations")
+    boundary = param("periodic", "boundary condition [periodic|outflow|inflow|reflect]")
+
+    def exact_primitive(self, t, x):
+        """
+        Return the exact primitive variables (rho, u, p, x) at time t, where
+        x may be a float or an array.
+        """
+        from numpy import cos, sin, pi
+
+        a = self.amplitude
+        c = cos(2.0 * pi * x)
+        rho = 1.0 + a * c * cos(2.0 * pi * t)
+        u = a * sin(pi * x) ** 2 * sin(2.0 * pi * t)
+        pre = 1.0 + a * c * sin(2.0 * pi * t)
+        scalar = 0.5 + 0.5 * a * c * cos(2.0 * pi * t)
+        return rho, u, pre, scalar
+
+    def primitive(self, t, x, primitive):
+        primitive[:] = self.exact_primitive(t, x)
+
+    def mesh(self, num_zones):
+        return PlanarCartesianMesh(0.0, 1.0, num_zones)
+
+    @property
+    def solver(self):
+        return "srhd_1d"
+
+    @property
+    def physics(self):
+        from sailfish.physics.manufactured import srhd_1d_forcing
+
+        return dict(source_terms=srhd_1d_forcing(self.exact_primitive))
+
+    @property
+    def boundary_condition(self):
+        return self.boundary
+
+    @property
+    def default_end_time(self):
+        return 0.5
+
+    def validate(self):
+        if self.boundary not in ("periodic", "outflow", "inflow", "reflect"):
+            raise SetupError(f"unsupported boundary condition {self.boundary}")
+        if self.amplitude >= 1.0:
+            raise SetupError("amplitude must be less than 1.0")
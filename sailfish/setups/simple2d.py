@@ -3,11 +3,13 @@ Validation setups for various 2D solvers
 """
 
 from sailfish.mesh import LogSphericalMesh, PlanarCartesian2DMesh
+from functools import lru_cache
 from sailfish.physics.circumbinary import EquationOfState
-from sailfish.setup_base import SetupBase, param
+from sailfish.physics.random_field import GaussianRandomField
+from sailfish.setup_base import SetupBase, SetupError, param
 from math import exp
 
-__all__ = ["UniformPolar", "CylindricalExplosion"]
+__all__ = ["UniformPolar", "CylindricalExplosion", "ShearingBox"]
 
 
 class UniformPolar(SetupBase):
@@ -112,3 +114,80 @@ class CylindricalExplosion(SetupBase):
             raise ValueError(f"eos must be isothermal or gamma-law, got {self.eos}")
         if self.use_dg and not self.is_isothermal:
             raise ValueError("DG mode is only available for eos=isothermal")
+
+
+class ShearingBox(SetupBase):
+    """
+    A local shearing box, for studies of disk instabilities with the
+    cbdiso_2d solver.
+
+    The box is centered on a point orbiting at the frequency Omega = 1, with
+    x pointing radially outward and y in the direction of rotation. The gas
+    is isothermal, with uniform surface density, and moves with the
+    background shear flow vy = -3/2 Omega x. Seeded random perturbations are
+    added to both velocity components. The radial boundaries are
+    shear-periodic, and the azimuthal boundaries are periodic.
+    """
+
+    box_size = param(1.0, "side length of the square box")
+    sound_speed = param(0.1, "isothermal sound speed")
+    perturbation = param(0.01, "RMS amplitude of random velocity perturbations")
+    perturbation_seed = param(0, "random seed for the velocity perturbations")
+
+    @lru_cache(maxsize=None)
+    def perturbation_field(self, component):
+        """
+        Return the random field used to perturb one velocity component.
+        """
+        return GaussianRandomField(
+            extent=0.5 * self.box_size,
+            amplitude=self.perturbation,
+            seed=self.perturbation_seed + component,
+        )
+
+    def primitive(self, t, coords, primitive):
+        x, y = coords
+        primitive[0] = 1.0
+        primitive[1] = 0.0
+        primitive[2] = -1.5 * x
+
+        if self.perturbation > 0.0:
+            primitive[1] += self.perturbation_field(0)(x, y)
+            primitive[2] += self.perturbation_field(1)(x, y)
+
+    def mesh(self, resolution):
+        return PlanarCartesian2DMesh.centered_square(
+            0.5 * self.box_size, resolution, periodic_x=True, periodic_y=True
+        )
+
+    @property
+    def physics(self):
+        return dict(
+            eos_type=EquationOfState.GLOBALLY_ISOTHERMAL,
+            sound_speed=self.sound_speed,
+            shearing_box_frequency=1.0,
+        )
+
+    @property
+    def solver(self):
+        return "cbdiso_2d"
+
+    @property
+    def boundary_condition(self):
+        return "shearing_periodic"
+
+    @property
+    def default_resolution(self):
+        return 256
+
+    @property
+    def default_end_time(self):
+        return 10.0
+
+    def validate(self):
+        if self.box_size <= 0.0:
+            raise SetupError("box_size must be positive")
+        if self.sound_speed <= 0.0:
+            raise SetupError("sound_speed must be positive")
+        if self.perturbation < 0.0:
+            raise SetupError("perturbation amplitude must be non-negative")
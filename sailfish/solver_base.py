@@ -117,6 +117,44 @@ class SolverBase(ABC):
         """
         pass
 
+    def save_state(self):
+        """
+        Return an object from which the current solution state can be restored.
+
+        Solvers do not need to implement this. If they do, they must also
+        implement :py:meth:`restore_state`, so that the driver can discard a
+        time step and retry it with a smaller timestep. The default
+        implementation returns `None` to indicate that this is not supported.
+        """
+        return None
+
+    def restore_state(self, state):
+        """
+        Restore the solution state from an object returned by `save_state`.
+        """
+        raise NotImplementedError
+
+    def auxiliary_fields(self):
+        """
+        Return a dictionary of diagnostic arrays to be written to checkpoints.
+
+        Solvers do not need to implement this. If they do, the arrays should
+        have the shape of the mesh, without guard zones, so they can be
+        exported alongside the primitive fields.
+        """
+        return dict()
+
+    def torque_maps(self):
+        """
+        Return a dictionary of the gravitational torque density on each point
+        mass, keyed by field name.
+
+        Solvers which have point masses may implement this, to support the
+        `torque_map` output product. The arrays should have the shape of the
+        mesh, without guard zones.
+        """
+        return dict()
+
     def reductions(self):
         """
         Return a set of measurements derived from the solution state.
@@ -126,3 +164,17 @@ class SolverBase(ABC):
         to the solver by the setup when the setup is first constructed.
         """
         pass
+
+    def reductions_snapshot(self):
+        """
+        Return a function which computes the reductions for the current state.
+
+        This is used to compute time series diagnostics on a worker thread,
+        while the solver continues to advance. The function returned must not
+        depend on solver state that is modified by later iterations. The
+        default implementation computes the reductions immediately. Solvers
+        with expensive diagnostics should override it to copy the state they
+        need, and defer the computation.
+        """
+        reductions = self.reductions()
+        return lambda: reductions
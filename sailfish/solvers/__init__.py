@@ -15,9 +15,9 @@ def register_solver_extension(solver_name):
     __solver_extension_modules.append(solver_name)
 
 
-def make_solver(name, physics, options, **kwargs):
+def solver_module(name):
     """
-    Find a solver with the given name and construct it.
+    Return the module of the solver with the given name.
     """
     from importlib import import_module
     from . import srhd_1d
@@ -39,7 +39,40 @@ def make_solver(name, physics, options, **kwargs):
         solvers[ext_name] = import_module(ext_name)
 
     try:
-        return solvers[name].Solver(
+        return solvers[name]
+    except KeyError:
+        raise SolverInitializationError(f"unknown solver {name}")
+
+
+def solver_option_names(name):
+    """
+    Return the names of the options accepted by the solver with the given
+    name, or an empty list if the solver does not declare an `Options` class.
+    """
+    options = getattr(solver_module(name), "Options", None)
+    return list(getattr(options, "_fields", []))
+
+
+def solver_num_fields(name, physics):
+    """
+    Return the number of fields on the last axis of the solution array of the
+    solver with the given name, for a physics dictionary, or `None` if the
+    solver does not declare a `num_fields` function.
+    """
+    module = solver_module(name)
+
+    if not hasattr(module, "num_fields"):
+        return None
+
+    return module.num_fields(module.Physics(**(physics or dict())))
+
+
+def make_solver(name, physics, options, **kwargs):
+    """
+    Find a solver with the given name and construct it.
+    """
+    try:
+        return solver_module(name).Solver(
             physics=physics or dict(), options=options or dict(), **kwargs
         )
     except (TypeError, ValueError) as e:
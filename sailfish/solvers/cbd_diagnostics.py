@@ -0,0 +1,75 @@
+"""
+Diagnostics which are reduced the same way by the circumbinary disk solvers,
+`cbdiso_2d` and `cbdgam_2d`.
+
+The functions here operate on a single solver patch, and return the patch's
+contribution to the diagnostic, which the solver then combines over patches.
+"""
+
+
+def histogram(patch, diagnostic, primitive_fields, derived_fields):
+    """
+    Return a histogram counting the cells of a patch whose field value lies
+    in each of the diagnostic's bins; the counts are converted to areas when
+    the patch results are combined.
+
+    The field is either one of the names in `primitive_fields`, in the order
+    of the primitive array (see :py:mod:`sailfish.fields`), or a key of the
+    dictionary `derived_fields`, whose values are functions returning the
+    field on the patch interior, e.g. the Mach number.
+    """
+    ng = 2  # number of guard cells
+    d = diagnostic
+    xp = patch.xp
+    x, y = patch.cell_center_coordinate_arrays
+    r = (x**2 + y**2) ** 0.5
+    prim = patch.primitive[ng:-ng, ng:-ng]
+
+    if d.field in primitive_fields:
+        f = prim[..., primitive_fields.index(d.field)]
+    elif d.field in derived_fields:
+        f = derived_fields[d.field](patch)
+    else:
+        raise ValueError(f"unknown histogram field {d.field}")
+
+    if d.radial_cut is not None:
+        r0, r1 = d.radial_cut
+        weights = ((r0 < r) * (r < r1) * xp.ones(f.shape)).flatten()
+    else:
+        weights = None
+
+    counts, _ = xp.histogram(f.flatten(), d.bin_edges(xp), weights=weights)
+    return counts
+
+
+def fourier_modes(patch, diagnostic):
+    """
+    Return a complex array of shape (num_bins, num_modes), whose (n, m - 1)
+    element is the sum over cells of a patch in the radial bin n of
+    sigma * exp(-i m phi). Bins are linearly spaced in radius.
+    """
+    ng = 2  # number of guard cells
+    d = diagnostic
+    xp = patch.xp
+    x, y = patch.cell_center_coordinate_arrays
+    r = (x**2 + y**2) ** 0.5 * xp.ones(patch.shape)
+    phi = xp.arctan2(y, x) * xp.ones(patch.shape)
+    sigma = patch.primitive[ng:-ng, ng:-ng, 0]
+
+    if d.bins is None:
+        raise ValueError("fourier_modes diagnostic requires bins=(r0, r1, num)")
+
+    r0, r1, num_bins = d.bins
+    num_bins = int(num_bins)
+    index = xp.floor((r - r0) / (r1 - r0) * num_bins).astype(int)
+    valid = (index >= 0) * (index < num_bins)
+    index = index[valid]
+    modes = xp.zeros((num_bins, d.num_modes), dtype=complex)
+
+    for m in range(1, d.num_modes + 1):
+        w = sigma[valid] * xp.exp(-1.0j * m * phi[valid])
+        re = xp.bincount(index, weights=w.real, minlength=num_bins)
+        im = xp.bincount(index, weights=w.imag, minlength=num_bins)
+        modes[:, m - 1] = re + 1.0j * im
+
+    return modes
@@ -2,17 +2,44 @@
 MODULE: cbdgam_2d
 
 DESCRIPTION: Energy-conserving solver for a binary accretion problem in 2D
-  planar cartesian coordinates.
-
-TODO:
-    + add plm_theta as a solver option (currently it's hard-coded)
+  planar cartesian coordinates. If the VERTICAL_STRUCTURE macro is set to 1,
+  the disk scale height H and its rate of change W = dH/dt are evolved as two
+  additional fields (2.5D model), in place of the hydrostatic estimate. If
+  the NUM_RESERVOIRS macro is nonzero, that many separate internal energy
+  fields are evolved after those, exchanging heat with the gas. Separations
+  from the point masses are measured to their nearest periodic image along
+  the axes whose period is given by the PERIOD_X and PERIOD_Y macros. The
+  low-Mach correction of the face velocities is enabled by setting the
+  LOW_MACH_CORRECTION macro to 1.
 */
 
 
 // ============================ PHYSICS =======================================
 // ============================================================================
-#define NCONS 4
+#ifndef VERTICAL_STRUCTURE
+#define VERTICAL_STRUCTURE 0
+#endif
+#ifndef NUM_RESERVOIRS
+#define NUM_RESERVOIRS 0
+#endif
+#define RESERVOIR_INDEX (4 + 2 * VERTICAL_STRUCTURE)
+#define NCONS (4 + 2 * VERTICAL_STRUCTURE + NUM_RESERVOIRS)
+
+#ifndef PLM_THETA
 #define PLM_THETA 1.5
+#endif
+
+#ifndef LOW_MACH_CORRECTION
+#define LOW_MACH_CORRECTION 0
+#endif
+
+#ifndef PERIOD_X
+#define PERIOD_X 0.0
+#endif
+
+#ifndef PERIOD_Y
+#define PERIOD_Y 0.0
+#endif
 
 
 // ============================ MATH ==========================================
@@ -24,6 +51,13 @@ TODO:
 #define sign(x) copysign(1.0, x)
 #define minabs(a, b, c) min3(fabs(a), fabs(b), fabs(c))
 
+PRIVATE double periodic_separation(double d, double period)
+{
+    // The nearest periodic image along an axis with the given period, or the
+    // separation itself if the axis is not periodic (period = 0).
+    return period > 0.0 ? d - period * round(d / period) : d;
+}
+
 PRIVATE double plm_gradient_scalar(double yl, double y0, double yr)
 {
     double a = (y0 - yl) * PLM_THETA;
@@ -32,7 +66,7 @@ PRIVATE double plm_gradient_scalar(double yl, double y0, double yr)
     return 0.25 * fabs(sign(a) + sign(b)) * (sign(a) + sign(c)) * minabs(a, b, c);
 }
 
-PRIVATE void plm_gradient(double *yl, double *y0, double *yr, double *g)
+PRIVATE void plm_gradient(const double *yl, const double *y0, const double *yr, double *g)
 {
     for (int q = 0; q < NCONS; ++q)
     {
@@ -72,12 +106,65 @@ struct KeplerianBuffer {
 
 // ============================ GRAVITY =======================================
 // ============================================================================
+PRIVATE double softened_force_factor(
+    double r2,
+    double r_soft,
+    int softening_model)
+{
+    switch (softening_model)
+    {
+        case 1: // cubic spline (Springel 2001), Keplerian beyond r_soft
+        {
+            double r = sqrt(r2);
+
+            if (r >= r_soft)
+            {
+                return pow(r2, -1.5);
+            }
+            double u = r / r_soft;
+            double h3 = r_soft * r_soft * r_soft;
+
+            if (u < 0.5)
+            {
+                return (10.666666666666667 + u * u * (32.0 * u - 38.4)) / h3;
+            }
+            return (21.333333333333333 - 48.0 * u + 38.4 * u * u - 10.666666666666667 * u * u * u - 0.066666666666667 / (u * u * u)) / h3;
+        }
+        default: // Plummer
+            return pow(r2 + r_soft * r_soft, -1.5);
+    }
+}
+
+PRIVATE double keplerian_frequency_squared(
+    struct PointMassList *mass_list,
+    double x1,
+    double y1)
+{
+    double omegatilde2 = 0.0;
+
+    for (int p = 0; p < 2; ++p)
+    {
+        if (mass_list->masses[p].mass > 0.0)
+        {
+            double dx = periodic_separation(x1 - mass_list->masses[p].x, PERIOD_X);
+            double dy = periodic_separation(y1 - mass_list->masses[p].y, PERIOD_Y);
+            double r2 = dx * dx + dy * dy + 1e-12;
+            omegatilde2 += mass_list->masses[p].mass * pow(r2, -1.5);
+        }
+    }
+    return omegatilde2;
+}
+
 PRIVATE double disk_height(
     struct PointMassList *mass_list,
     double x1,
     double y1,
-    double *prim)
+    const double *prim)
 {
+    if (VERTICAL_STRUCTURE)
+    {
+        return prim[4]; // the evolved scale height
+    }
     if (mass_list->masses[0].mass == 0.0 && mass_list->masses[1].mass == 0.0)
     {
         return 1.0;
@@ -92,8 +179,8 @@ PRIVATE double disk_height(
             double y0 = mass_list->masses[p].y;
             double mp = mass_list->masses[p].mass;
 
-            double dx = x1 - x0;
-            double dy = y1 - y0;
+            double dx = periodic_separation(x1 - x0, PERIOD_X);
+            double dy = periodic_separation(y1 - y0, PERIOD_Y);
             double r2 = dx * dx + dy * dy + 1e-12;
             double r  = sqrt(r2);
             omegatilde2 += mp * pow(r, -3.0);
@@ -110,10 +197,12 @@ PRIVATE void point_mass_source_term(
     double x1,
     double y1,
     double dt,
-    double *prim,
+    const double *prim,
     double h,
     double *delta_cons,
     int constant_softening,
+    int softening_model,
+    int softening_units,
     double gamma_law_index)
 {
     double x0 = mass->x;
@@ -121,8 +210,8 @@ PRIVATE void point_mass_source_term(
     double sigma = prim[0];
     double pres  = prim[3];
     double eps = pres / sigma / (gamma_law_index - 1.0);
-    double dx = x1 - x0;
-    double dy = y1 - y0;
+    double dx = periodic_separation(x1 - x0, PERIOD_X);
+    double dy = periodic_separation(y1 - y0, PERIOD_Y);
     double r2 = dx * dx + dy * dy;
     double dr = sqrt(r2);
     double r_sink = mass->sink_radius;
@@ -130,7 +219,9 @@ PRIVATE void point_mass_source_term(
 
     if (constant_softening)
     {
-        r_soft = mass->softening_length;
+        // softening lengths given in units of the cell size are converted
+        // to absolute lengths by the caller
+        r_soft = softening_units == 2 ? mass->softening_length * h : mass->softening_length;
     }
     else if (dr > r_sink)
     {
@@ -147,7 +238,7 @@ PRIVATE void point_mass_source_term(
     // }
 
     double sink_rate = (dr < 4.0 * r_sink) ? mass->sink_rate * exp(-pow(dr / r_sink, 4.0)) : 0.0;
-    double fgrav_numerator = sigma * mass->mass * pow(r2 + r_soft * r_soft, -1.5);
+    double fgrav_numerator = sigma * mass->mass * softened_force_factor(r2, r_soft, softening_model);
     double fx = -fgrav_numerator * dx;
     double fy = -fgrav_numerator * dy;
     double mdot = sigma * sink_rate * -1.0;
@@ -200,6 +291,66 @@ PRIVATE void point_mass_source_term(
             break;
         }
     }
+
+    for (int q = 4; q < NCONS; ++q)
+    {
+        // auxiliary fields are removed by the sink along with the gas
+        delta_cons[q] = mass->sink_model != 0 ? dt * mdot * prim[q] : 0.0;
+    }
+}
+
+PRIVATE void vertical_structure_source_term(
+    struct PointMassList *mass_list,
+    double x1,
+    double y1,
+    double dt,
+    const double *prim,
+    double *cons)
+{
+#if VERTICAL_STRUCTURE
+    // The scale height H responds to the imbalance between the vertical
+    // pressure gradient and the vertical component of gravity, which vanishes
+    // when P / Sigma = Omega^2 H^2. The work done by vertical expansion is
+    // taken from the thermal energy.
+    double omegatilde2 = keplerian_frequency_squared(mass_list, x1, y1);
+    double sigma = prim[0];
+    double pres = prim[3];
+    double h = max2(prim[4], 1e-12);
+    double w = prim[5];
+    double vertical_acceleration = pres / (sigma * h) - omegatilde2 * h;
+
+    cons[3] -= pres * w / h * dt;
+    cons[4] += sigma * w * dt;
+    cons[5] += sigma * vertical_acceleration * dt;
+#endif
+}
+
+PRIVATE void energy_reservoir_source_term(
+    double exchange_rate,
+    double divergence,
+    double dt,
+    const double *prim,
+    double *cons,
+    double gamma_law_index)
+{
+    // Each reservoir holds an internal energy Sigma * e_r which is advected
+    // with the gas, does its own compression work, and exchanges heat with
+    // the gas at a rate proportional to the difference in specific internal
+    // energy. The exchange term conserves the total energy. The reservoirs
+    // do not contribute to the pressure seen by the Riemann solver.
+    double sigma = prim[0];
+    double eps = prim[3] / prim[0] / (gamma_law_index - 1.0);
+
+    for (int r = 0; r < NUM_RESERVOIRS; ++r)
+    {
+        int q = RESERVOIR_INDEX + r;
+        double eps_r = prim[q];
+        double pres_r = sigma * eps_r * (gamma_law_index - 1.0);
+        double heating = exchange_rate * sigma * (eps - eps_r);
+
+        cons[3] -= heating * dt;
+        cons[q] += (heating - pres_r * divergence) * dt;
+    }
 }
 
 PRIVATE void point_masses_source_term(
@@ -207,16 +358,18 @@ PRIVATE void point_masses_source_term(
     double x1,
     double y1,
     double dt,
-    double *prim,
+    const double *prim,
     double h,
     double *cons,
     int constant_softening,
+    int softening_model,
+    int softening_units,
     double gamma_law_index)
 {
     for (int p = 0; p < 2; ++p)
     {
         double delta_cons[NCONS];
-        point_mass_source_term(&mass_list->masses[p], x1, y1, dt, prim, h, delta_cons, constant_softening, gamma_law_index);
+        point_mass_source_term(&mass_list->masses[p], x1, y1, dt, prim, h, delta_cons, constant_softening, softening_model, softening_units, gamma_law_index);
 
         for (int q = 0; q < NCONS; ++q)
         {
@@ -230,7 +383,7 @@ PRIVATE void point_masses_source_term(
 // ============================================================================
 PRIVATE double sound_speed_squared(
     double gamma_law_index,
-    double *prim)
+    const double *prim)
 {
     return prim[3] / prim[0] * gamma_law_index;
 }
@@ -263,6 +416,17 @@ PRIVATE void buffer_source_term(
             double energy = surface_pressure / (gamma_law_index - 1.0) + kinetic_energy;
             double u0[NCONS] = {surface_density, px, py, energy};
 
+#if VERTICAL_STRUCTURE
+            double h0 = sqrt(surface_pressure / surface_density / (central_mass / (rc * rc * rc)));
+            u0[4] = surface_density * h0;
+            u0[5] = 0.0;
+#endif
+
+            for (int r = 0; r < NUM_RESERVOIRS; ++r)
+            {
+                u0[RESERVOIR_INDEX + r] = surface_pressure / (gamma_law_index - 1.0);
+            }
+
             double omega_outer = sqrt(central_mass * pow(onset_radius, -3.0));
             //double buffer_rate = driving_rate * omega_outer * max2(rc, 1.0);
             double buffer_rate = driving_rate * omega_outer * (rc - onset_radius) / (outer_radius - onset_radius);
@@ -292,14 +456,66 @@ PRIVATE void shear_strain(
     s[3] = syy;
 }
 
+/**
+ * Return the viscosity multiplier at a point, by linear interpolation of a
+ * radial profile sampled at r = k * dr. The profile is held constant beyond
+ * its outermost sample, so a single sample gives a uniform multiplier.
+ */
+PRIVATE double viscosity_multiplier(
+    const double *profile,
+    int num_samples,
+    double dr,
+    double x,
+    double y)
+{
+    double s = sqrt(x * x + y * y) / dr;
+    int k = (int)s;
+
+    if (k >= num_samples - 1)
+    {
+        return profile[num_samples - 1];
+    }
+    return profile[k] + (s - k) * (profile[k + 1] - profile[k]);
+}
+
+
+/**
+ * Return the weight of the PLM gradient in the face values reconstructed
+ * from a zone: one half, or zero (first order) if the zone center is within
+ * the given radius of a gravitating point mass.
+ */
+PRIVATE double reconstruction_weight(
+    const struct PointMassList *mass_list,
+    double radius,
+    double x,
+    double y)
+{
+    for (int p = 0; p < 2; ++p)
+    {
+        const struct PointMass *mass = &mass_list->masses[p];
+        double dx = periodic_separation(x - mass->x, PERIOD_X);
+        double dy = periodic_separation(y - mass->y, PERIOD_Y);
+
+        if (mass->mass > 0.0 && dx * dx + dy * dy < radius * radius)
+        {
+            return 0.0;
+        }
+    }
+    return 0.5;
+}
+
 
 // ============================ HYDRO =========================================
 // ============================================================================
-PRIVATE void cooling_term(
+/**
+ * Apply the cooling term to the conserved energy, and return the (negative)
+ * change in the energy density.
+ */
+PRIVATE double cooling_term(
     double cooling_coefficient,
     double mach_ceiling,
     double dt,
-    double *prim,
+    const double *prim,
     double *cons,
     double gamma_law_index)
 {
@@ -314,6 +530,7 @@ PRIVATE void cooling_term(
     eps_cooled = max2(eps_cooled, 2.0 * ek / gamma / (gamma - 1.0) * pow(mach_ceiling, -2.0));
 
     cons[3] += sigma * (eps_cooled - eps);
+    return sigma * (eps_cooled - eps);
 }
 
 PRIVATE void conserved_to_primitive(
@@ -322,14 +539,22 @@ PRIVATE void conserved_to_primitive(
     double velocity_ceiling,
     double density_floor,
     double pressure_floor,
-    double gamma_law_index)
+    double gamma_law_index,
+    double *floor_counts)
 {
     double gamma = gamma_law_index;
-    double pres  = max2(pressure_floor, (cons[3] - 0.5 * (cons[1] * cons[1] + cons[2] * cons[2]) / cons[0]) * (gamma - 1.0));
+    double pres_raw = (cons[3] - 0.5 * (cons[1] * cons[1] + cons[2] * cons[2]) / cons[0]) * (gamma - 1.0);
+    double pres  = max2(pressure_floor, pres_raw);
     double vx = sign(cons[1]) * min2(fabs(cons[1] / cons[0]), velocity_ceiling);
     double vy = sign(cons[2]) * min2(fabs(cons[2] / cons[0]), velocity_ceiling);
     double rho = cons[0];
 
+    // count activations of the density floor, pressure floor, and velocity
+    // ceiling
+    floor_counts[0] += cons[0] < density_floor;
+    floor_counts[1] += pres_raw < pressure_floor;
+    floor_counts[2] += max2(fabs(cons[1]), fabs(cons[2])) / fabs(cons[0]) > velocity_ceiling;
+
     if (cons[0] < density_floor)
     {
         rho = density_floor;
@@ -342,6 +567,11 @@ PRIVATE void conserved_to_primitive(
     prim[1] = vx;
     prim[2] = vy;
     prim[3] = pres;
+
+    for (int q = 4; q < NCONS; ++q)
+    {
+        prim[q] = cons[q] / max2(cons[0], density_floor);
+    }
 }
 
 PRIVATE void primitive_to_conserved(const double *prim, double *cons, double gamma_law_index)
@@ -359,6 +589,11 @@ PRIVATE void primitive_to_conserved(const double *prim, double *cons, double gam
     cons[1] = px;
     cons[2] = py;
     cons[3] = en;
+
+    for (int q = 4; q < NCONS; ++q)
+    {
+        cons[q] = rho * prim[q];
+    }
 }
 
 PRIVATE double primitive_to_velocity(const double *prim, int direction)
@@ -384,6 +619,11 @@ PRIVATE void primitive_to_flux(
     flux[1] = vn * cons[1] + pressure * (direction == 0);
     flux[2] = vn * cons[2] + pressure * (direction == 1);
     flux[3] = vn * (cons[3] + pressure);
+
+    for (int q = 4; q < NCONS; ++q)
+    {
+        flux[q] = vn * cons[q];
+    }
 }
 
 PRIVATE void primitive_to_outer_wavespeeds(
@@ -408,8 +648,44 @@ PRIVATE double primitive_max_wavespeed(const double *prim, double cs2)
     return max2(ax, ay);
 }
 
+/**
+ * The low-Mach correction of Thornber et al. (2008), JCP 227, 4873: the jump
+ * in the reconstructed velocity across a face is reduced by the face Mach
+ * number z = min(1, max(Ml, Mr)), keeping its mean. The dissipation of the
+ * Riemann solver scales with the velocity jump times the sound speed, so
+ * without this it is excessive in deeply subsonic flow.
+ */
+PRIVATE void low_mach_correction(double *pl, double *pr, double cs2)
+{
+    double ml = sqrt((pl[1] * pl[1] + pl[2] * pl[2]) / cs2);
+    double mr = sqrt((pr[1] * pr[1] + pr[2] * pr[2]) / cs2);
+    double z = min2(1.0, max2(ml, mr));
+
+    for (int q = 1; q < 3; ++q)
+    {
+        double mean = 0.5 * (pl[q] + pr[q]);
+        double jump = 0.5 * (pl[q] - pr[q]);
+        pl[q] = mean + z * jump;
+        pr[q] = mean - z * jump;
+    }
+}
+
 PRIVATE void riemann_hlle(const double *pl, const double *pr, double *flux, double cs2, int direction, double gamma_law_index)
 {
+#if (LOW_MACH_CORRECTION)
+    double plc[NCONS];
+    double prc[NCONS];
+
+    for (int q = 0; q < NCONS; ++q)
+    {
+        plc[q] = pl[q];
+        prc[q] = pr[q];
+    }
+    low_mach_correction(plc, prc, cs2);
+    pl = plc;
+    pr = prc;
+#endif
+
     double ul[NCONS];
     double ur[NCONS];
     double fl[NCONS];
@@ -436,6 +712,23 @@ PRIVATE void riemann_hlle(const double *pl, const double *pr, double *flux, doub
 
 // ============================ PUBLIC API ====================================
 // ============================================================================
+PRIVATE void external_acceleration_source_term(
+    const double *acceleration,
+    double dt,
+    const double *prim,
+    double *cons)
+{
+    double sigma = prim[0];
+    double vx = prim[1];
+    double vy = prim[2];
+    double ax = acceleration[0];
+    double ay = acceleration[1];
+
+    cons[1] += sigma * ax * dt;
+    cons[2] += sigma * ay * dt;
+    cons[3] += sigma * (vx * ax + vy * ay) * dt;
+}
+
 PUBLIC void cbdgam_2d_advance_rk(
     int ni,
     int nj,
@@ -443,9 +736,9 @@ PUBLIC void cbdgam_2d_advance_rk(
     double patch_xr,
     double patch_yl,
     double patch_yr,
-    double *conserved_rk, // :: $.shape == (ni + 4, nj + 4, 4)
-    double *primitive_rd, // :: $.shape == (ni + 4, nj + 4, 4)
-    double *primitive_wr, // :: $.shape == (ni + 4, nj + 4, 4)
+    const double *conserved_rk, // :: $.shape[:2] == (ni + 4, nj + 4)
+    const double *primitive_rd, // :: $.shape[:2] == (ni + 4, nj + 4)
+    double *primitive_wr, // :: $.shape[:2] == (ni + 4, nj + 4)
     double gamma_law_index,
     double buffer_surface_density,
     double buffer_surface_pressure,
@@ -472,7 +765,11 @@ PUBLIC void cbdgam_2d_advance_rk(
     double sink_rate2,
     double sink_radius2,
     int sink_model2,
+    double first_order_radius, // :: $ >= 0.0
     double alpha, // other
+    const double *viscosity_profile, // :: $.shape == (viscosity_profile_samples,)
+    int viscosity_profile_samples, // :: $ >= 1
+    double viscosity_profile_dr, // :: $ > 0.0
     double a,
     double dt,
     double velocity_ceiling,
@@ -480,7 +777,20 @@ PUBLIC void cbdgam_2d_advance_rk(
     double mach_ceiling,
     double density_floor,
     double pressure_floor,
-    int constant_softening)
+    int constant_softening,
+    int softening_model,
+    int softening_units,
+    double energy_exchange_rate,
+    const double *acceleration, // :: $.shape == (ni, nj, 2) or not acceleration_is_enabled
+    int acceleration_is_enabled,
+    double *residual, // :: $.shape[:2] == (ni, nj) or not well_balanced
+    int well_balanced, // 0: off, 1: subtract the residual, 2: record it
+    double *floor_counts, // :: $.shape == (ni, nj, 3)
+    double *activation_map, // :: $.shape == (ni, nj, 4) or not activation_maps_enabled
+    int activation_maps_enabled,
+    double *energy_budget, // :: $.shape == (ni, nj, 3) or not energy_budget_enabled
+    int energy_budget_enabled,
+    double budget_dt)
 {
     struct KeplerianBuffer buffer = {
         buffer_surface_density,
@@ -545,20 +855,20 @@ PUBLIC void cbdgam_2d_advance_rk(
         int nrl = (i + 1 + ng) * si + (j - 1 + ng) * sj;
         int nrr = (i + 1 + ng) * si + (j + 1 + ng) * sj;
 
-        double *un = &conserved_rk[ncc];
-        double *pcc = &primitive_rd[ncc];
-        double *pli = &primitive_rd[nli];
-        double *pri = &primitive_rd[nri];
-        double *plj = &primitive_rd[nlj];
-        double *prj = &primitive_rd[nrj];
-        double *pki = &primitive_rd[nki];
-        double *pti = &primitive_rd[nti];
-        double *pkj = &primitive_rd[nkj];
-        double *ptj = &primitive_rd[ntj];
-        double *pll = &primitive_rd[nll];
-        double *plr = &primitive_rd[nlr];
-        double *prl = &primitive_rd[nrl];
-        double *prr = &primitive_rd[nrr];
+        const double *un = &conserved_rk[ncc];
+        const double *pcc = &primitive_rd[ncc];
+        const double *pli = &primitive_rd[nli];
+        const double *pri = &primitive_rd[nri];
+        const double *plj = &primitive_rd[nlj];
+        const double *prj = &primitive_rd[nrj];
+        const double *pki = &primitive_rd[nki];
+        const double *pti = &primitive_rd[nti];
+        const double *pkj = &primitive_rd[nkj];
+        const double *ptj = &primitive_rd[ntj];
+        const double *pll = &primitive_rd[nll];
+        const double *plr = &primitive_rd[nlr];
+        const double *prl = &primitive_rd[nrl];
+        const double *prr = &primitive_rd[nrr];
 
         double plip[NCONS];
         double plim[NCONS];
@@ -591,17 +901,26 @@ PUBLIC void cbdgam_2d_advance_rk(
         plm_gradient(pll, plj, prl, gxlj);
         plm_gradient(plr, prj, prr, gxrj);
 
+        // Each zone's face values are reconstructed to first order if it is
+        // near a point mass. The weights are those of the zones the face
+        // values come from, so both zones sharing a face see the same flux.
+        double wcc = reconstruction_weight(&mass_list, first_order_radius, xc, yc);
+        double wli = reconstruction_weight(&mass_list, first_order_radius, xc - dx, yc);
+        double wri = reconstruction_weight(&mass_list, first_order_radius, xc + dx, yc);
+        double wlj = reconstruction_weight(&mass_list, first_order_radius, xc, yc - dy);
+        double wrj = reconstruction_weight(&mass_list, first_order_radius, xc, yc + dy);
+
         for (int q = 0; q < NCONS; ++q)
         {
-            plim[q] = pli[q] + 0.5 * gxli[q];
-            plip[q] = pcc[q] - 0.5 * gxcc[q];
-            prim[q] = pcc[q] + 0.5 * gxcc[q];
-            prip[q] = pri[q] - 0.5 * gxri[q];
-
-            pljm[q] = plj[q] + 0.5 * gylj[q];
-            pljp[q] = pcc[q] - 0.5 * gycc[q];
-            prjm[q] = pcc[q] + 0.5 * gycc[q];
-            prjp[q] = prj[q] - 0.5 * gyrj[q];
+            plim[q] = pli[q] + wli * gxli[q];
+            plip[q] = pcc[q] - wcc * gxcc[q];
+            prim[q] = pcc[q] + wcc * gxcc[q];
+            prip[q] = pri[q] - wri * gxri[q];
+
+            pljm[q] = plj[q] + wlj * gylj[q];
+            pljp[q] = pcc[q] - wcc * gycc[q];
+            prjm[q] = pcc[q] + wcc * gycc[q];
+            prjp[q] = prj[q] - wrj * gyrj[q];
         }
 
         double fli[NCONS];
@@ -641,11 +960,17 @@ PUBLIC void cbdgam_2d_advance_rk(
             double hlj = disk_height(&mass_list, xc, yl, plj);
             double hrj = disk_height(&mass_list, xc, yr, prj);
 
-            double nucc = alpha * hcc * sqrt(cs2cc);
-            double nuli = alpha * hli * sqrt(cs2li);
-            double nuri = alpha * hri * sqrt(cs2ri);
-            double nulj = alpha * hlj * sqrt(cs2lj);
-            double nurj = alpha * hrj * sqrt(cs2rj);
+            double mcc = viscosity_multiplier(viscosity_profile, viscosity_profile_samples, viscosity_profile_dr, xc, yc);
+            double mli = viscosity_multiplier(viscosity_profile, viscosity_profile_samples, viscosity_profile_dr, xc - dx, yc);
+            double mri = viscosity_multiplier(viscosity_profile, viscosity_profile_samples, viscosity_profile_dr, xc + dx, yc);
+            double mlj = viscosity_multiplier(viscosity_profile, viscosity_profile_samples, viscosity_profile_dr, xc, yc - dy);
+            double mrj = viscosity_multiplier(viscosity_profile, viscosity_profile_samples, viscosity_profile_dr, xc, yc + dy);
+
+            double nucc = alpha * mcc * hcc * sqrt(cs2cc);
+            double nuli = alpha * mli * hli * sqrt(cs2li);
+            double nuri = alpha * mri * hri * sqrt(cs2ri);
+            double nulj = alpha * mlj * hlj * sqrt(cs2lj);
+            double nurj = alpha * mrj * hrj * sqrt(cs2rj);
 
             fli[1] -= 0.5 * (nuli * pli[0] * sli[0] + nucc * pcc[0] * scc[0]); // x-x
             fli[2] -= 0.5 * (nuli * pli[0] * sli[1] + nucc * pcc[0] * scc[1]); // x-y
@@ -666,26 +991,127 @@ PUBLIC void cbdgam_2d_advance_rk(
             frj[3] -= 0.5 * (nucc * pcc[0] * scc[3] * pcc[2] + nurj * prj[0] * srj[3] * prj[2]);
         }
 
+        // The rate of viscous dissipation per unit area is tau_ij d_j v_i,
+        // with the stress and velocity gradients at the cell center.
+        double dissipation = 0.0;
+
+        if (alpha > 0.0 && energy_budget_enabled)
+        {
+            double scc[4];
+            shear_strain(gxcc, gycc, dx, dy, scc);
+            double mcc = viscosity_multiplier(viscosity_profile, viscosity_profile_samples, viscosity_profile_dr, xc, yc);
+            double nucc = alpha * mcc * hcc * sqrt(sound_speed_squared(gamma_law_index, pcc));
+            double dvdx[4] = {gxcc[1] / dx, gycc[1] / dy, gxcc[2] / dx, gycc[2] / dy};
+
+            for (int n = 0; n < 4; ++n)
+            {
+                dissipation += nucc * pcc[0] * scc[n] * dvdx[n];
+            }
+        }
+
         primitive_to_conserved(pcc, ucc, gamma_law_index);
+
+        double u0cc[NCONS];
+
+        for (int q = 0; q < NCONS; ++q)
+        {
+            u0cc[q] = ucc[q];
+        }
         buffer_source_term(&buffer, xc, yc, dt, ucc, gamma_law_index);
-        point_masses_source_term(&mass_list, xc, yc, dt, pcc, hcc, ucc, constant_softening, gamma_law_index);
-        cooling_term(cooling_coefficient, mach_ceiling, dt, pcc, ucc, gamma_law_index);
+        point_masses_source_term(&mass_list, xc, yc, dt, pcc, hcc, ucc, constant_softening, softening_model, softening_units, gamma_law_index);
+        vertical_structure_source_term(&mass_list, xc, yc, dt, pcc, ucc);
+        energy_reservoir_source_term(energy_exchange_rate, gxcc[1] / dx + gycc[2] / dy, dt, pcc, ucc, gamma_law_index);
+
+        if (acceleration_is_enabled)
+        {
+            external_acceleration_source_term(&acceleration[2 * (i * nj + j)], dt, pcc, ucc);
+        }
 
         for (int q = 0; q < NCONS; ++q)
         {
             ucc[q] -= ((fri[q] - fli[q]) / dx + (frj[q] - flj[q]) / dy) * dt;
+        }
+
+        // The residual of the scheme is the rate of change of the conserved
+        // quantities in an equilibrium state, due to discretization error.
+        // Cooling is excluded because it is not linear in the timestep.
+        double *rcc = &residual[(i * nj + j) * NCONS];
+
+        if (well_balanced == 1)
+        {
+            for (int q = 0; q < NCONS; ++q)
+            {
+                ucc[q] -= rcc[q] * dt;
+            }
+        }
+        else if (well_balanced == 2)
+        {
+            for (int q = 0; q < NCONS; ++q)
+            {
+                rcc[q] = (ucc[q] - u0cc[q]) / dt;
+            }
+        }
+        double cooling = cooling_term(cooling_coefficient, mach_ceiling, dt, pcc, ucc, gamma_law_index);
+
+        for (int q = 0; q < NCONS; ++q)
+        {
             ucc[q] = (1.0 - a) * ucc[q] + a * un[q];
         }
 
+        // Floor and ceiling activations are counted for the diagnostics, and
+        // optionally accumulated in per-zone maps along with activations of
+        // the slope limiter (on the density field).
+        double floor_flags[3] = {0.0, 0.0, 0.0};
+        double *fcc = &floor_counts[3 * (i * nj + j)];
         double *pout = &primitive_wr[ncc];
-        conserved_to_primitive(ucc, pout, velocity_ceiling, density_floor, pressure_floor, gamma_law_index);
+        conserved_to_primitive(ucc, pout, velocity_ceiling, density_floor, pressure_floor, gamma_law_index, floor_flags);
+
+        for (int f = 0; f < 3; ++f)
+        {
+            fcc[f] += floor_flags[f];
+        }
+
+        if (activation_maps_enabled)
+        {
+            double *mcc = &activation_map[4 * (i * nj + j)];
+            int limited_x = gxcc[0] != 0.5 * (pri[0] - pli[0]);
+            int limited_y = gycc[0] != 0.5 * (prj[0] - plj[0]);
+
+            for (int f = 0; f < 3; ++f)
+            {
+                mcc[f] += floor_flags[f];
+            }
+            mcc[3] += limited_x || limited_y;
+        }
+
+        // The energy budget accumulates the gravitational work done on the
+        // gas by the point masses, and the energy lost to viscous
+        // dissipation and cooling, each weighted by the stage's share of the
+        // time step. The gravitational work excludes the sink terms.
+        if (energy_budget_enabled)
+        {
+            double *ecc = &energy_budget[3 * (i * nj + j)];
+            double work = 0.0;
+
+            for (int p = 0; p < 2; ++p)
+            {
+                double delta_cons[NCONS];
+                struct PointMass mass = mass_list.masses[p];
+                mass.sink_rate = 0.0;
+                point_mass_source_term(&mass, xc, yc, 1.0, pcc, hcc, delta_cons, constant_softening, softening_model, softening_units, gamma_law_index);
+                work += delta_cons[3];
+            }
+            ecc[0] += work * budget_dt;
+            ecc[1] += dissipation * budget_dt;
+            ecc[2] -= cooling / dt * budget_dt;
+        }
     }
 }
 
 PUBLIC void cbdgam_2d_wavespeed(
     int ni,
     int nj,
-    double *primitive, // :: $.shape == (ni + 4, nj + 4, 4)
+    const double *primitive, // :: $.shape[:2] == (ni + 4, nj + 4)
     double *wavespeed, // :: $.shape == (ni + 4, nj + 4)
     double gamma_law_index)
 {
@@ -700,7 +1126,7 @@ PUBLIC void cbdgam_2d_wavespeed(
         int np = (i + ng) * si + (j + ng) * sj;
         int na = (i + ng) * ti + (j + ng) * tj;
 
-        double *pc = &primitive[np];
+        const double *pc = &primitive[np];
         double cs2 = sound_speed_squared(gamma_law_index, pc);
         double a = primitive_max_wavespeed(pc, cs2);
         wavespeed[na] = a;
@@ -710,8 +1136,8 @@ PUBLIC void cbdgam_2d_wavespeed(
 PUBLIC void cbdgam_2d_primitive_to_conserved(
     int ni,
     int nj,
-    double *primitive, // :: $.shape == (ni + 4, nj + 4, 4)
-    double *conserved, // :: $.shape == (ni + 4, nj + 4, 4)
+    const double *primitive, // :: $.shape[:2] == (ni + 4, nj + 4)
+    double *conserved, // :: $.shape[:2] == (ni + 4, nj + 4)
     double gamma_law_index)
 {
     int ng = 2; // number of guard zones
@@ -722,7 +1148,7 @@ PUBLIC void cbdgam_2d_primitive_to_conserved(
     {
         int n = (i + ng) * si + (j + ng) * sj;
 
-        double *pc = &primitive[n];
+        const double *pc = &primitive[n];
         double *uc = &conserved[n];
         primitive_to_conserved(pc, uc, gamma_law_index);
     }
@@ -754,9 +1180,11 @@ PUBLIC void cbdgam_2d_point_mass_source_term(
     double sink_radius2,
     int sink_model2,
     int which_mass, // :: $ in [1, 2]
-    double *primitive, // :: $.shape == (ni + 4, nj + 4, 4)
-    double *cons_rate, // :: $.shape == (ni + 4, nj + 4, 4)
+    const double *primitive, // :: $.shape[:2] == (ni + 4, nj + 4)
+    double *cons_rate, // :: $.shape[:2] == (ni + 4, nj + 4)
     int constant_softening,
+    int softening_model,
+    int softening_units,
     double gamma_law_index)
 {
     struct PointMass m1 = {x1, y1, vx1, vy1, mass1, softening_length1, sink_rate1, sink_radius1, sink_model1};
@@ -776,9 +1204,9 @@ PUBLIC void cbdgam_2d_point_mass_source_term(
 
         double xc = patch_xl + (i + 0.5) * dx;
         double yc = patch_yl + (j + 0.5) * dy;
-        double *pc = &primitive[ncc];
+        const double *pc = &primitive[ncc];
         double *uc = &cons_rate[ncc];
         double h = disk_height(&mass_list, xc, yc, pc);
-        point_mass_source_term(&mass_list.masses[which_mass - 1], xc, yc, 1.0, pc, h, uc, constant_softening, gamma_law_index);
+        point_mass_source_term(&mass_list.masses[which_mass - 1], xc, yc, 1.0, pc, h, uc, constant_softening, softening_model, softening_units, gamma_law_index);
     }
 }
@@ -2,19 +2,38 @@
 Energy-conserving solver for the binary accretion problem in 2D.
 """
 
+from math import hypot
 from typing import NamedTuple
 from logging import getLogger
 from sailfish.kernel.library import Library
-from sailfish.kernel.system import get_array_module, execution_context, num_devices
+from sailfish.kernel.system import (
+    KernelGraphCache,
+    copy_guard_zones,
+    execution_context,
+    get_array_module,
+    num_devices,
+)
 from sailfish.mesh import PlanarCartesian2DMesh
 from sailfish.physics.circumbinary import (
     Physics,
     EquationOfState,
     ViscosityModel,
+    SofteningUnits,
     Diagnostic,
+    FLOOR_FIELDS,
+    ACTIVATION_MAPS,
+    ENERGY_BUDGET_RATES,
 )
+from sailfish.fields import solver_fields
 from sailfish.solver_base import SolverBase
-from sailfish.subdivide import subdivide, to_host, concat_on_host, lazy_reduce
+from sailfish.solvers.cbd_diagnostics import fourier_modes, histogram
+from sailfish.subdivide import (
+    subdivide,
+    concat_on_host,
+    lazy_reduce,
+    pack_on_device,
+    unpack_on_host,
+)
 
 
 logger = getLogger(__name__)
@@ -25,24 +44,95 @@ class Options(NamedTuple):
     density_floor: float = 1e-10
     velocity_ceiling: float = 1e16
     mach_ceiling: float = 1e5
+    well_balanced: bool = False
+    out_of_core: bool = False
+    out_of_core_tile_size: int = 256
+    activation_maps: bool = False
+    torque_maps: bool = False
+    plm_theta: float = 1.5
+    cuda_graphs: bool = False
+    low_mach_correction: bool = False
 
 
-def initial_condition(setup, mesh, time):
+def initial_condition(setup, mesh, time, physics=None, equilibrium=False):
     """
     Generate a 2D array of primitive data from a mesh and a setup.
+
+    If `equilibrium` is True, the setup's unperturbed equilibrium state is
+    generated, rather than its initial condition.
+
+    If the physics configuration enables vertical structure, the scale height
+    is initialized to its hydrostatic value in the potential of the point
+    masses, and its rate of change is initialized to zero. Energy reservoirs
+    are initialized to the specific internal energy of the gas.
     """
     import numpy as np
 
+    vertical_structure = physics is not None and physics.vertical_structure
+    num_reservoirs = physics.num_energy_reservoirs if physics is not None else 0
     ni, nj = mesh.shape
-    primitive = np.zeros([ni, nj, 4])
+    primitive = np.zeros([ni, nj, num_fields(physics)])
+    setup_primitive = setup.equilibrium_primitive if equilibrium else setup.primitive
 
     for i in range(ni):
         for j in range(nj):
-            setup.primitive(time, mesh.cell_coordinates(i, j), primitive[i, j])
+            setup_primitive(time, mesh.cell_coordinates(i, j), primitive[i, j])
+
+    if vertical_structure:
+        x = mesh.x0 + (np.arange(ni)[:, None] + 0.5) * mesh.dx
+        y = mesh.y0 + (np.arange(nj)[None, :] + 0.5) * mesh.dy
+        omegatilde2 = np.zeros(mesh.shape)
+
+        for m in physics.point_masses(time):
+            if m.mass > 0.0:
+                dx, dy = mesh.separation(x - m.position_x, y - m.position_y)
+                r2 = dx**2 + dy**2 + 1e-12
+                omegatilde2 += m.mass * r2**-1.5
+
+        if not omegatilde2.any():
+            omegatilde2 += 1.0
+
+        cs2 = primitive[..., 3] / primitive[..., 0]
+        primitive[..., 4] = (cs2 / omegatilde2) ** 0.5
+        primitive[..., 5] = 0.0
+
+    for r in range(num_reservoirs):
+        eps = primitive[..., 3] / primitive[..., 0] / (physics.gamma_law_index - 1.0)
+        primitive[..., 4 + 2 * vertical_structure + r] = eps
 
     return primitive
 
 
+def potential_is_static(physics, time):
+    """
+    Determine whether the gravitational potential is independent of time.
+
+    This requires that there is no external acceleration, and that the point
+    masses are at rest, with positions and masses that are the same when
+    sampled at several later times (which catches mass ramps).
+    """
+    if physics.external_acceleration is not None:
+        return False
+
+    def placement(t):
+        return [(m.position_x, m.position_y, m.mass) for m in physics.point_masses(t)]
+
+    for m in physics.point_masses(time):
+        if m.velocity_x != 0.0 or m.velocity_y != 0.0:
+            return False
+
+    return all(placement(time + dt) == placement(time) for dt in (1e0, 1e1, 1e2, 1e3))
+
+
+def num_fields(physics=None):
+    """
+    Return the number of primitive fields for a physics configuration.
+    """
+    if physics is None:
+        return 4
+    return 4 + 2 * physics.vertical_structure + physics.num_energy_reservoirs
+
+
 class Patch:
     """
     Holds the array buffer state for the solution on a subset of the
@@ -91,6 +181,68 @@ class Patch:
             self.primitive1 = self.xp.array(primitive)
             self.primitive2 = self.xp.array(primitive)
             self.conserved0 = self.xp.zeros(primitive.shape)
+            self.surface_flux_sums = [0.0] * len(physics.diagnostics)
+            self.acceleration = self.xp.zeros(
+                self.shape + (2,) if physics.external_acceleration else (1, 1, 2)
+            )
+            outer_radius = hypot(max(-mesh.x0, mesh.x1), max(-mesh.y0, mesh.y1))
+            profile, dr = physics.viscosity_profile_table(outer_radius)
+            self.viscosity_profile = self.xp.array(profile)
+            self.viscosity_profile_dr = dr
+            self.floor_counts = self.xp.zeros(self.shape + (3,))
+            self.activation_map = self.xp.zeros(
+                self.shape + (4,) if options.activation_maps else (1, 1, 4)
+            )
+            self.energy_budget_enabled = any(
+                d.is_energy_rate for d in physics.diagnostics
+            )
+            self.energy_budget = self.xp.zeros(
+                self.shape + (3,) if self.energy_budget_enabled else (1, 1, 3)
+            )
+            self.residual = self.xp.zeros(
+                self.shape + primitive.shape[2:]
+                if options.well_balanced
+                else (1, 1) + primitive.shape[2:]
+            )
+
+    def surface_flux(self, diagnostic):
+        """
+        Return the outward flux of a conserved quantity through a circle or
+        annulus, centered on the origin or on one of the point masses, divided
+        by the cell area. The surface moves with the point mass if it's
+        centered on one, and the angular momentum is measured about its
+        center, in its rest frame. The flux is the advective part only, and
+        is averaged over the cells in the annulus, or within one grid spacing
+        of the circle. The result is a device scalar.
+        """
+        ng = 2  # number of guard cells
+        d = diagnostic
+        x, y = self.cell_center_coordinate_arrays
+        prim = self.primitive1[ng:-ng, ng:-ng]
+        sigma, vx, vy = prim[..., 0], prim[..., 1], prim[..., 2]
+
+        if d.which_mass in (1, 2):
+            m = self.physics.point_masses(self.time)[d.which_mass - 1]
+            x0, y0 = m.position_x, m.position_y
+            vx0, vy0 = m.velocity_x, m.velocity_y
+        else:
+            x0, y0, vx0, vy0 = 0.0, 0.0, 0.0, 0.0
+
+        r0, r1 = d.surface_flux_radii(2.0 * self.mesh.min_spacing())
+        r = self.xp.maximum(((x - x0) ** 2 + (y - y0) ** 2) ** 0.5, 1e-12)
+        vr = ((vx - vx0) * (x - x0) + (vy - vy0) * (y - y0)) / r
+        shell = (r >= r0) & (r < r1)
+
+        if d.quantity == "mass_flux":
+            f = sigma
+        elif d.quantity == "momentum_flux_x":
+            f = sigma * vx
+        elif d.quantity == "momentum_flux_y":
+            f = sigma * vy
+        elif d.quantity == "angular_momentum_flux":
+            f = sigma * ((x - x0) * (vy - vy0) - (y - y0) * (vx - vx0))
+
+        return (f * vr * shell).sum() / (r1 - r0)
 
     @property
     def cell_center_coordinate_arrays(self):
@@ -138,10 +290,24 @@ class Patch:
                 self.primitive1,
                 cons_rate,
                 int(self.physics.constant_softening),
+                self.physics.softening_model.value,
+                self.physics.softening_units.value,
                 self.physics.gamma_law_index,
             )
             return cons_rate[ng:-ng, ng:-ng]
 
+    def torque_density(self, which_mass):
+        """
+        Return the gravitational torque density about the origin, exerted by
+        the gas on one point mass, on the patch cells without guard zones.
+
+        This is the negative of the torque exerted by the point mass on the
+        gas in each cell, since the gravitational force is central.
+        """
+        x, y = self.cell_center_coordinate_arrays
+        udot = self.point_mass_source_term(which_mass, gravity=True)
+        return y * udot[..., 1] - x * udot[..., 2]
+
     def maximum_wavespeed(self):
         with self.execution_context:
             self.lib.cbdgam_2d_wavespeed[self.shape](
@@ -159,8 +325,49 @@ class Patch:
                 self.physics.gamma_law_index,
             )
 
-    def advance_rk(self, rk_param, dt):
+    def external_acceleration(self):
+        """
+        Return the array of external accelerations to be passed to the advance
+        kernel, after evaluating the physics callback (if there is one) at the
+        current patch time.
+        """
+        acceleration_function = self.physics.external_acceleration
+
+        if acceleration_function is not None:
+            x, y = self.cell_center_coordinate_arrays
+            ax, ay = acceleration_function(self.time, x, y)
+            self.acceleration[..., 0] = ax
+            self.acceleration[..., 1] = ay
+
+        return self.acceleration
+
+    def advance_rk(self, rk_param, dt, budget_dt=0.0, record_residual=False):
+        """
+        Pass required parameters for time evolution of the setup.
+
+        The energy budget rates, if enabled, are accumulated with the weight
+        `budget_dt`, which is this stage's share of the time step.
+
+        If `record_residual` is True, then the solution is not advanced, but
+        rather the rate of change of the conserved quantities is stored in
+        the residual array, to be subtracted in subsequent steps. The sinks
+        are disabled in that pass, and its other outputs are written to
+        scratch arrays, so the solution and floor counters are untouched.
+        """
         m1, m2 = self.physics.point_masses(self.time)
+        primitive2 = self.primitive2
+        floor_counts = self.floor_counts
+        activation_map = self.activation_map
+
+        if record_residual:
+            m1 = m1._replace(sink_rate=0.0)
+            m2 = m2._replace(sink_rate=0.0)
+
+            with self.execution_context:
+                primitive2 = self.xp.zeros_like(primitive2)
+                floor_counts = self.xp.zeros_like(floor_counts)
+                activation_map = self.xp.zeros_like(activation_map)
+
         buffer_central_mass = m1.mass + m2.mass
         buffer_surface_density = self.buffer_surface_density
         buffer_surface_pressure = self.buffer_surface_pressure
@@ -173,7 +380,7 @@ class Patch:
                 self.yr,
                 self.conserved0,
                 self.primitive1,
-                self.primitive2,
+                primitive2,
                 self.physics.gamma_law_index,
                 buffer_surface_density,
                 buffer_surface_pressure,
@@ -200,7 +407,11 @@ class Patch:
                 m2.sink_rate,
                 m2.sink_radius,
                 m2.sink_model.value,
+                self.physics.first_order_radius,
                 self.physics.alpha,
+                self.viscosity_profile,
+                len(self.viscosity_profile),
+                self.viscosity_profile_dr,
                 rk_param,
                 dt,
                 self.options.velocity_ceiling,
@@ -209,8 +420,24 @@ class Patch:
                 self.options.density_floor,
                 self.options.pressure_floor,
                 int(self.physics.constant_softening),
+                self.physics.softening_model.value,
+                self.physics.softening_units.value,
+                self.physics.energy_exchange_rate,
+                self.external_acceleration(),
+                int(self.physics.external_acceleration is not None),
+                self.residual,
+                2 if record_residual else int(self.options.well_balanced),
+                floor_counts,
+                activation_map,
+                int(self.options.activation_maps),
+                self.energy_budget,
+                int(self.energy_budget_enabled and not record_residual),
+                budget_dt,
             )
 
+        if record_residual:
+            return
+
         self.time = self.time0 * rk_param + (self.time + dt) * (1.0 - rk_param)
         self.primitive1, self.primitive2 = self.primitive2, self.primitive1
 
@@ -254,6 +481,9 @@ class Solver(SolverBase):
         if setup.boundary_condition != "outflow":
             raise ValueError("solver only supports outflow boundary condition")
 
+        if physics.buffer_is_enabled and (mesh.periodic_x or mesh.periodic_y):
+            raise ValueError("buffer zone is not supported on a periodic mesh")
+
         if physics.viscosity_model not in (
             ViscosityModel.NONE,
             ViscosityModel.CONSTANT_ALPHA,
@@ -263,12 +493,46 @@ class Solver(SolverBase):
         if physics.eos_type != EquationOfState.GAMMA_LAW:
             raise ValueError("solver only supports isothermal equation of states")
 
-        xp = get_array_module(mode)
+        for d in physics.diagnostics:
+            if d.is_surface_flux:
+                d.surface_flux_radii(mesh.min_spacing())
+            if d.quantity == "floor_count" and d.field not in FLOOR_FIELDS:
+                raise ValueError(f"floor_count field must be one of {FLOOR_FIELDS}")
+
+        if physics.num_tracers != 0:
+            raise ValueError("solver does not support passive tracers")
+
+        if physics.buffer_accretion_rate is not None:
+            raise ValueError("solver does not support a buffer accretion rate")
+
+        if options.well_balanced and not potential_is_static(physics, time):
+            raise ValueError("well_balanced requires a static gravitational potential")
+
+        if physics.softening_units == SofteningUnits.CELL_SIZE:
+            self._physics = physics = physics.with_softening_scale(mesh.min_spacing())
+
+        # In out-of-core mode, the patch data resides in host memory, and is
+        # streamed through the GPU in tiles of columns for each kernel launch.
+        xp = get_array_module("cpu" if options.out_of_core else mode)
         ng = 2  # number of guard zones
-        nq = 4  # number of conserved quantities
+        nq = num_fields(physics)  # number of conserved quantities
         with open(__file__.replace(".py", ".c")) as f:
             code = f.read()
-        lib = Library(code, mode=mode, debug=False)
+        lib = Library(
+            code,
+            mode=mode,
+            debug=False,
+            define_macros=dict(
+                VERTICAL_STRUCTURE=int(physics.vertical_structure),
+                NUM_RESERVOIRS=physics.num_energy_reservoirs,
+                PLM_THETA=options.plm_theta,
+                LOW_MACH_CORRECTION=int(options.low_mach_correction),
+                PERIOD_X=mesh.period_x,
+                PERIOD_Y=mesh.period_y,
+            ),
+            host_staged=options.out_of_core,
+            tile_size=options.out_of_core_tile_size,
+        )
 
         logger.info(f"initiate with time={time:0.4f}")
         logger.info(f"subdivide grid over {num_patches} patches")
@@ -281,15 +545,23 @@ class Solver(SolverBase):
         self.num_cons = nq
         self.xp = xp
         self.patches = []
+        self._surface_flux_time = 0.0
         ni, nj = mesh.shape
         self.domain_radius = self.mesh.x1
         self.buffer_onset_width = 0.1
 
         if solution is None:
-            primitive = initial_condition(setup, mesh, time)
+            primitive = initial_condition(setup, mesh, time, physics)
         else:
             primitive = solution
 
+        if primitive.shape[-1] != nq:
+            raise ValueError(
+                f"solution has {primitive.shape[-1]} fields, expected {nq} "
+                f"(vertical_structure={physics.vertical_structure}, "
+                f"num_energy_reservoirs={physics.num_energy_reservoirs})"
+            )
+
         if physics.buffer_is_enabled:
             # Here we sample the initial condition at the buffer onset radius
             # to determine the disk surface density at the radius where the
@@ -325,6 +597,26 @@ class Solver(SolverBase):
             )
             self.patches.append(patch)
 
+        self._graphs = KernelGraphCache(enabled=options.cuda_graphs)
+
+        if options.cuda_graphs:
+            if mode != "gpu" or options.out_of_core:
+                reason = "unless the patches are resident on a GPU"
+            elif num_patches > 1 and num_devices(mode) > 1:
+                reason = "with patches on multiple GPUs"
+            else:
+                reason = None
+
+            if reason is None:
+                logger.info("replay the guard zone fills as CUDA graphs")
+            else:
+                logger.warning(f"cuda_graphs is ignored {reason}")
+                self._graphs.enabled = False
+
+        if options.well_balanced:
+            equilibrium = initial_condition(setup, mesh, time, physics, True)
+            self.record_residual(equilibrium)
+
     @property
     def solution(self):
         return concat_on_host(
@@ -338,15 +630,62 @@ class Solver(SolverBase):
         """
         return None
 
+    def auxiliary_fields(self):
+        """
+        Return per-zone counts of the density floor, pressure floor, velocity
+        ceiling, and slope limiter activations since the previous call, and
+        the gravitational torque density on each point mass.
+
+        The activation maps are only recorded if the `activation_maps` solver
+        option is enabled. They are reset to zero after being returned, so
+        each checkpoint shows the activations since the one before it. The
+        torque maps are only computed if the `torque_maps` option is enabled.
+        """
+        fields = dict()
+
+        if self._options.activation_maps:
+            maps = concat_on_host([p.activation_map for p in self.patches], rank=2)
+
+            for patch in self.patches:
+                with patch.execution_context:
+                    patch.activation_map[...] = 0.0
+
+            for n, name in enumerate(ACTIVATION_MAPS):
+                fields[name] = maps[..., n]
+
+        if self._options.torque_maps:
+            fields.update(self.torque_maps())
+
+        return fields
+
+    def torque_maps(self):
+        """
+        Return the gravitational torque density on each point mass, as the
+        fields `torque_density1` and `torque_density2`.
+        """
+        return {
+            f"torque_density{m}": concat_on_host(
+                [p.torque_density(m) for p in self.patches], rank=2
+            )
+            for m in (1, 2)
+        }
+
     def reductions(self):
         """
         Generate runtime reductions on the solution data for time series.
+
+        Reductions are computed on the device that holds each patch. The
+        point mass source terms are only computed if a diagnostic requires
+        them, and the results for each patch are brought to the host in a
+        single transfer.
         """
         diagnostics = self._physics.diagnostics
-        udots1_acc = [p.point_mass_source_term(1, accretion=True) for p in self.patches]
-        udots2_acc = [p.point_mass_source_term(2, accretion=True) for p in self.patches]
-        udots1_grv = [p.point_mass_source_term(1, gravity=True) for p in self.patches]
-        udots2_grv = [p.point_mass_source_term(2, gravity=True) for p in self.patches]
+        udots = dict()
+        num_fields = self.patches[0].primitive.shape[2]
+        primitive_field_names = [
+            field.name
+            for field in solver_fields("cbdgam_2d", num_fields, self._physics)
+        ]
         da = self.mesh.dx * self.mesh.dy
         ng = self.num_guard
 
@@ -369,11 +708,29 @@ class Solver(SolverBase):
             if quantity == "mdot":
                 return get_field(patch, 0, cut, mass, gravity, accretion)
 
+            if quantity == "fx":
+                return get_field(patch, 1, cut, mass, gravity, accretion)
+
+            if quantity == "fy":
+                return get_field(patch, 2, cut, mass, gravity, accretion)
+
             if quantity == "torque":
                 fx = get_field(patch, 1, cut, mass, gravity, accretion)
                 fy = get_field(patch, 2, cut, mass, gravity, accretion)
                 return x * fy - y * fx
 
+            if quantity == "spin":
+                # The torque about the point mass itself, rather than the
+                # origin; with the accretion term, this is the rate at which
+                # the sink accretes spin angular momentum.
+                if mass not in (1, 2):
+                    raise ValueError("mass option for 'spin' must be 1 or 2")
+                m = self._physics.point_masses(patch.time)[mass - 1]
+                fx = get_field(patch, 1, cut, mass, gravity, accretion)
+                fy = get_field(patch, 2, cut, mass, gravity, accretion)
+                dx, dy = self.mesh.separation(x - m.position_x, y - m.position_y)
+                return dx * fy - dy * fx
+
             if quantity == "sigma_m1":
                 sigma = apply_radial_cut(patch.primitive[ng:-ng, ng:-ng, 0])
                 cos_phi = x / r
@@ -392,55 +749,158 @@ class Solver(SolverBase):
                 return sigma * (ex + 1.0j * ey)
 
             q = quantity
-            i = self.patches.index(patch)
-
-            if accretion:
-                udots1 = udots1_acc
-                udots2 = udots2_acc
-            elif gravity:
-                udots1 = udots1_grv
-                udots2 = udots2_grv
 
             if mass == "both":
-                f = udots1[i][..., q] + udots2[i][..., q]
+                f = get_udot(patch, 1, gravity, accretion)[..., q]
+                f = f + get_udot(patch, 2, gravity, accretion)[..., q]
             elif mass == 1:
-                f = udots1[i][..., q]
+                f = get_udot(patch, 1, gravity, accretion)[..., q]
             elif mass == 2:
-                f = udots2[i][..., q]
+                f = get_udot(patch, 2, gravity, accretion)[..., q]
 
             return apply_radial_cut(f)
 
-        def get_sum_fields(d):
-            result = []
+        def get_udot(patch, mass, gravity, accretion):
+            """
+            Return the point mass source term for a patch, computing it only
+            the first time it is needed. The accretion term takes precedence
+            if both terms are requested.
+            """
+            term = "acc" if accretion else "grv" if gravity else None
+            key = (self.patches.index(patch), mass, term)
+
+            if term is None:
+                raise ValueError("diagnostic requires either gravity or accretion")
+
+            if key not in udots:
+                udots[key] = patch.point_mass_source_term(
+                    mass, gravity=term == "grv", accretion=term == "acc"
+                )
+            return udots[key]
+
+        def get_mach_number(patch):
+            """
+            Return the Mach number of the gas on the patch interior.
+            """
+            prim = patch.primitive[ng:-ng, ng:-ng]
+            cs2 = self._physics.gamma_law_index * prim[..., 3] / prim[..., 0]
+            return ((prim[..., 1] ** 2 + prim[..., 2] ** 2) / cs2) ** 0.5
+
+        # Fields which can be histogrammed, besides the primitive fields
+        histogram_fields = dict(
+            mach_number=get_mach_number,
+            torque_density=lambda p: get_field(p, "torque", None, "both", gravity=True),
+        )
+
+        def get_sum_field(p, d):
+            if d.is_surface_flux:
+                n = diagnostics.index(d)
+                if self._surface_flux_time > 0.0:
+                    f = p.surface_flux_sums[n] / self._surface_flux_time
+                else:
+                    f = p.surface_flux(d)
+                p.surface_flux_sums[n] = 0.0
+                return f
+            if d.quantity == "floor_count":
+                return p.floor_counts[..., FLOOR_FIELDS.index(d.field)].sum()
+            if d.is_energy_rate:
+                # The surface flux time is the time since the previous sample.
+                f = p.energy_budget[..., ENERGY_BUDGET_RATES.index(d.quantity)].sum()
+                return f / max(self._surface_flux_time, 1e-300)
+            if d.quantity == "kinetic_energy":
+                sigma, vx, vy = (p.primitive[ng:-ng, ng:-ng, q] for q in range(3))
+                return (0.5 * sigma * (vx * vx + vy * vy)).sum()
+            if d.quantity == "thermal_energy":
+                gamma = self._physics.gamma_law_index
+                return (p.primitive[ng:-ng, ng:-ng, 3] / (gamma - 1.0)).sum()
+            if d.quantity == "histogram":
+                return histogram(p, d, primitive_field_names, histogram_fields)
+            if d.quantity == "fourier_modes":
+                return fourier_modes(p, d)
+            f = get_field(
+                p,
+                d.quantity,
+                d.radial_cut,
+                d.which_mass,
+                gravity=d.gravity,
+                accretion=d.accretion,
+            )
+            return f.sum()
+
+        fields = [d for d in diagnostics if d.quantity != "time"]
+        totals = [0.0] * len(fields)
+
+        if fields:
+            # Launch the reductions on every patch before blocking on any of
+            # the transfers, so that devices can work concurrently.
+            packed = []
             for p in self.patches:
                 with p.execution_context:
-                    f = get_field(
-                        p,
-                        d.quantity,
-                        d.radial_cut,
-                        d.which_mass,
-                        gravity=d.gravity,
-                        accretion=d.accretion,
-                    )
-                    result.append(f.sum())
-            return result
-
-        pass1 = []
-        pass2 = []
+                    values = [get_sum_field(p, d) for d in fields]
+                    packed.append(pack_on_device(values, p.xp))
+
+            for p, (buffer, layout) in zip(self.patches, packed):
+                with p.execution_context:
+                    for n, value in enumerate(unpack_on_host(buffer, layout)):
+                        totals[n] = totals[n] + value
+
+        result = []
+        sums = iter(totals)
 
         for d in diagnostics:
             if d.quantity == "time":
-                pass1.append(self.time / self.setup.reference_time_scale)
+                result.append(self.time / self.setup.reference_time_scale)
+            elif d.quantity == "floor_count":
+                result.append(next(sums))
             else:
-                pass1.append(get_sum_fields(d))
+                result.append(next(sums) * da)
 
-        for item in pass1:
-            if type(item) is not float:
-                pass2.append(sum(to_host(x) for x in item) * da)
-            else:
-                pass2.append(item)
+        if any(d.quantity == "floor_count" for d in diagnostics):
+            for p in self.patches:
+                with p.execution_context:
+                    p.floor_counts[...] = 0.0
+
+        for p in self.patches:
+            if p.energy_budget_enabled:
+                with p.execution_context:
+                    p.energy_budget[...] = 0.0
+
+        self._surface_flux_time = 0.0
+        return result
 
-        return pass2
+    def reductions_snapshot(self):
+        """
+        Return a function which computes the reductions for the current state.
+
+        The solver and its patches are shallow-copied, and the arrays the
+        reductions read are copied on their devices. The accumulated surface
+        fluxes, floor counts, and energy budget are moved to the snapshot, as
+        if the reductions had been computed now.
+        """
+        from copy import copy
+
+        snapshot = copy(self)
+        snapshot.patches = []
+
+        for patch in self.patches:
+            with patch.execution_context:
+                p = copy(patch)
+                p.primitive1 = patch.primitive1.copy()
+                p.floor_counts = patch.floor_counts.copy()
+                p.surface_flux_sums = list(patch.surface_flux_sums)
+                patch.surface_flux_sums = [0.0] * len(patch.surface_flux_sums)
+
+                if any(d.quantity == "floor_count" for d in self._physics.diagnostics):
+                    patch.floor_counts[...] = 0.0
+
+                if patch.energy_budget_enabled:
+                    p.energy_budget = patch.energy_budget.copy()
+                    patch.energy_budget[...] = 0.0
+
+                snapshot.patches.append(p)
+
+        self._surface_flux_time = 0.0
+        return snapshot.reductions
 
     @property
     def time(self):
@@ -474,11 +934,112 @@ class Solver(SolverBase):
         self.new_iteration()
         self.advance_rk(0.0, dt)
         self.advance_rk(0.5, dt)
+        self.accumulate_surface_fluxes(dt)
+        self.accrete_point_masses(dt)
+
+    def save_state(self):
+        """
+        Return a copy of the solution state, which can be passed to
+        `restore_state` to discard a time step.
+        """
+        patches = []
+        for patch in self.patches:
+            with patch.execution_context:
+                primitive = patch.primitive1.copy()
+                budget = patch.energy_budget.copy()
+                counters = (patch.floor_counts.copy(), patch.activation_map.copy())
+                flux_sums = list(patch.surface_flux_sums)
+                patches.append((patch.time, primitive, flux_sums, budget, counters))
+        accreted = list(self._physics.accreted_mass or [])
+        return patches, self._surface_flux_time, accreted
+
+    def restore_state(self, state):
+        """
+        Restore the solution state from a copy made by `save_state`.
+        """
+        patches, surface_flux_time, accreted = state
+        for patch, patch_state in zip(self.patches, patches):
+            time, primitive, flux_sums, budget, counters = patch_state
+            with patch.execution_context:
+                patch.time = patch.time0 = time
+                patch.primitive1[...] = primitive
+                patch.surface_flux_sums = list(flux_sums)
+                patch.energy_budget[...] = budget
+                patch.floor_counts[...], patch.activation_map[...] = counters
+        self._surface_flux_time = surface_flux_time
+
+        if self._physics.accreted_mass is not None:
+            self._physics.accreted_mass[:] = accreted
+
+    def record_residual(self, equilibrium):
+        """
+        Record the rate of change of the conserved quantities in an
+        equilibrium state, due to discretization error in the flux and source
+        terms, so that it can be subtracted in every subsequent step.
+
+        This makes the scheme well-balanced: the equilibrium (the setup's
+        unperturbed state, e.g. a Keplerian disk) is preserved to round-off,
+        while any seeded perturbations evolve freely. The residual is only
+        valid for a static potential, which the solver requires. Since the
+        equilibrium does not depend on time, recording it again on restart
+        gives the same residual as in a continuous run.
+        """
+        state = self.save_state()
+        ng = self.num_guard
+        ni, nj = self.mesh.shape
+
+        for patch, (a, b) in zip(self.patches, subdivide(ni, len(self.patches))):
+            with patch.execution_context:
+                patch.primitive1[ng:-ng, ng:-ng] = patch.xp.array(equilibrium[a:b])
+
+        self.set_bc("primitive1")
+
+        for patch in self.patches:
+            patch.recompute_conserved()
+            patch.advance_rk(0.0, 1.0, record_residual=True)
+
+        self.restore_state(state)
+
+    def accumulate_surface_fluxes(self, dt):
+        """
+        Add the surface fluxes at the end of a time step, weighted by the time
+        step size, to running sums which are kept on the device. The time
+        series records their averages since the previous sample.
+        """
+        for n, d in enumerate(self._physics.diagnostics):
+            if d.is_surface_flux:
+                for patch in self.patches:
+                    with patch.execution_context:
+                        f = patch.surface_flux(d) * dt
+                        patch.surface_flux_sums[n] = patch.surface_flux_sums[n] + f
+        self._surface_flux_time += dt
+
+    def accrete_point_masses(self, dt):
+        """
+        Add the mass removed by each sink over a time step to the physics'
+        accreted masses, if the point masses grow by accretion.
+        """
+        accreted = self._physics.accreted_mass
+
+        if accreted is None:
+            return
+
+        da = self.mesh.dx * self.mesh.dy
+
+        for m in (1, 2):
+            mdot = 0.0
+            for patch in self.patches:
+                with patch.execution_context:
+                    udot = patch.point_mass_source_term(m, accretion=True)
+                    mdot -= float(udot[..., 0].sum()) * da
+            accreted[m - 1] += mdot * dt
 
     def advance_rk(self, rk_param, dt):
+        # Each of the two RK2 stages contributes half of the update, so each
+        # adds half of its energy budget rates over the time step.
         self.set_bc("primitive1")
         for patch in self.patches:
-            patch.advance_rk(rk_param, dt)
+            patch.advance_rk(rk_param, dt, budget_dt=0.5 * dt)
 
     def set_bc(self, array):
         ng = self.num_guard
@@ -489,7 +1050,7 @@ class Solver(SolverBase):
             pl = getattr(self.patches[il], array)
             pc = getattr(self.patches[i0], array)
             pr = getattr(self.patches[ir], array)
-            self.set_bc_patch(pl, pc, pr, i0)
+            self._graphs(lambda: self.set_bc_patch(pl, pc, pr, i0), i0, pl, pc, pr)
 
     def set_bc_patch(self, pl, pc, pr, patch_index):
         ni, nj = self.mesh.shape
@@ -497,18 +1058,26 @@ class Solver(SolverBase):
 
         with self.patches[patch_index].execution_context:
             # 1. write to the guard zones of pc, the internal BC
-            pc[:+ng] = pl[-2 * ng : -ng]
-            pc[-ng:] = pr[+ng : +2 * ng]
-
-            # 2. Set outflow BC on the left/right patch edges
-            if patch_index == 0:
-                for i in range(ng):
-                    pc[i] = pc[ng]
-            if patch_index == len(self.patches) - 1:
-                for i in range(pc.shape[0] - ng, pc.shape[0]):
-                    pc[i] = pc[-ng - 1]
-
-            # 3. Set outflow BC on bottom and top edges
+            copy_guard_zones(pc[:+ng], pl[-2 * ng : -ng])
+            copy_guard_zones(pc[-ng:], pr[+ng : +2 * ng])
+
+            # 2. Set outflow BC on the left/right patch edges, unless the
+            # mesh is periodic in x; then the internal BC above has already
+            # copied in the image from the opposite edge of the domain
+            if not self.mesh.periodic_x:
+                if patch_index == 0:
+                    for i in range(ng):
+                        pc[i] = pc[ng]
+                if patch_index == len(self.patches) - 1:
+                    for i in range(pc.shape[0] - ng, pc.shape[0]):
+                        pc[i] = pc[-ng - 1]
+
+            # 3. Set periodic or outflow BC on bottom and top edges
+            if self.mesh.periodic_y:
+                pc[:, :+ng] = pc[:, -2 * ng : -ng]
+                pc[:, -ng:] = pc[:, +ng : +2 * ng]
+                return
+
             for i in range(ng):
                 pc[:, i] = pc[:, ng]
 
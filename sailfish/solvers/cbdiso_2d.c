@@ -2,13 +2,36 @@
 MODULE: cbdiso_2d
 
 DESCRIPTION: Isothermal solver for a binary accretion problem in 2D planar
-  cartesian coordinates.
+  cartesian coordinates. The number of passive tracers (dye concentrations
+  advected with the gas) is set at compile time with the NUM_TRACERS macro.
+  Separations from the point masses are measured to their nearest periodic
+  image along the axes whose period is given by the PERIOD_X and PERIOD_Y
+  macros (zero for a non-periodic axis). The low-Mach correction of the
+  face velocities is enabled by setting the LOW_MACH_CORRECTION macro to 1.
 */
 
 // ============================ PHYSICS =======================================
 // ============================================================================
-#define NCONS 3
+#ifndef NUM_TRACERS
+#define NUM_TRACERS 0
+#endif
+#define NCONS (3 + NUM_TRACERS)
+
+#ifndef PLM_THETA
 #define PLM_THETA 1.8
+#endif
+
+#ifndef LOW_MACH_CORRECTION
+#define LOW_MACH_CORRECTION 0
+#endif
+
+#ifndef PERIOD_X
+#define PERIOD_X 0.0
+#endif
+
+#ifndef PERIOD_Y
+#define PERIOD_Y 0.0
+#endif
 
 
 // ============================ MATH ==========================================
@@ -20,6 +43,13 @@ DESCRIPTION: Isothermal solver for a binary accretion problem in 2D planar
 #define sign(x) copysign(1.0, x)
 #define minabs(a, b, c) min3(fabs(a), fabs(b), fabs(c))
 
+PRIVATE double periodic_separation(double d, double period)
+{
+    // The nearest periodic image along an axis with the given period, or the
+    // separation itself if the axis is not periodic (period = 0).
+    return period > 0.0 ? d - period * round(d / period) : d;
+}
+
 PRIVATE double plm_gradient_scalar(double yl, double y0, double yr)
 {
     double a = (y0 - yl) * PLM_THETA;
@@ -28,7 +58,7 @@ PRIVATE double plm_gradient_scalar(double yl, double y0, double yr)
     return 0.25 * fabs(sign(a) + sign(b)) * (sign(a) + sign(c)) * minabs(a, b, c);
 }
 
-PRIVATE void plm_gradient(double *yl, double *y0, double *yr, double *g)
+PRIVATE void plm_gradient(const double *yl, const double *y0, const double *yr, double *g)
 {
     for (int q = 0; q < NCONS; ++q)
     {
@@ -53,6 +83,8 @@ struct PointMass {
 
 struct PointMassList {
     struct PointMass masses[2];
+    int softening_model;
+    int softening_units;
 };
 
 struct KeplerianBuffer {
@@ -62,15 +94,143 @@ struct KeplerianBuffer {
     double outer_radius;
     double onset_width;
     int is_enabled;
+    double inflow_viscosity;
 };
 
 
 // ============================ GRAVITY =======================================
 // ============================================================================
-PRIVATE double gravitational_potential(
+PRIVATE double softened_potential(
+    double r2,
+    double r_soft,
+    int softening_model)
+{
+    switch (softening_model)
+    {
+        case 1: // cubic spline (Springel 2001), Keplerian beyond r_soft
+        {
+            double r = sqrt(r2);
+
+            if (r >= r_soft)
+            {
+                return -1.0 / r;
+            }
+            double u = r / r_soft;
+
+            if (u < 0.5)
+            {
+                return (-2.8 + u * u * (5.333333333333333 + u * u * (6.4 * u - 9.6))) / r_soft;
+            }
+            return (-3.2 + 0.066666666666667 / u + u * u * (10.666666666666667 + u * (-16.0 + u * (9.6 - 2.133333333333333 * u)))) / r_soft;
+        }
+        default: // Plummer
+            return -1.0 / sqrt(r2 + r_soft * r_soft);
+    }
+}
+
+PRIVATE double softened_force_factor(
+    double r2,
+    double r_soft,
+    int softening_model)
+{
+    switch (softening_model)
+    {
+        case 1: // cubic spline (Springel 2001), Keplerian beyond r_soft
+        {
+            double r = sqrt(r2);
+
+            if (r >= r_soft)
+            {
+                return pow(r2, -1.5);
+            }
+            double u = r / r_soft;
+            double h3 = r_soft * r_soft * r_soft;
+
+            if (u < 0.5)
+            {
+                return (10.666666666666667 + u * u * (32.0 * u - 38.4)) / h3;
+            }
+            return (21.333333333333333 - 48.0 * u + 38.4 * u * u - 10.666666666666667 * u * u * u - 0.066666666666667 / (u * u * u)) / h3;
+        }
+        default: // Plummer
+            return pow(r2 + r_soft * r_soft, -1.5);
+    }
+}
+
+PRIVATE double cylindrical_sound_speed_squared(
+    struct PointMassList *mass_list,
+    double mach_squared,
+    double flaring_index,
+    double x1,
+    double y1)
+{
+    double m = mass_list->masses[0].mass + mass_list->masses[1].mass;
+    double rs = max2(mass_list->masses[0].softening_length, mass_list->masses[1].softening_length);
+    double r2 = x1 * x1 + y1 * y1 + rs * rs;
+    return m / sqrt(r2) * pow(r2, flaring_index) / mach_squared;
+}
+
+PRIVATE double disk_height(
     struct PointMassList *mass_list,
+    double cs2,
+    double mach_squared,
+    int eos_type,
+    int temperature_profile,
+    double flaring_index,
     double x1,
     double y1)
+{
+    double cs2_point_masses = 0.0;
+    double omegatilde2 = 0.0;
+
+    for (int p = 0; p < 2; ++p)
+    {
+        if (mass_list->masses[p].mass > 0.0)
+        {
+            double dx = periodic_separation(x1 - mass_list->masses[p].x, PERIOD_X);
+            double dy = periodic_separation(y1 - mass_list->masses[p].y, PERIOD_Y);
+            double mp = mass_list->masses[p].mass;
+            double r2 = dx * dx + dy * dy + 1e-12;
+            cs2_point_masses += mp / sqrt(r2) * pow(r2, flaring_index) / mach_squared;
+            omegatilde2 += mp * pow(r2, -1.5);
+        }
+    }
+    if (omegatilde2 == 0.0)
+    {
+        return 1.0;
+    }
+    double cs2_local = cs2;
+
+    if (eos_type == 2 && temperature_profile == 1)
+    {
+        cs2_local = cylindrical_sound_speed_squared(mass_list, mach_squared, flaring_index, x1, y1);
+    }
+    else if (eos_type == 2)
+    {
+        cs2_local = cs2_point_masses;
+    }
+    return sqrt(cs2_local / omegatilde2);
+}
+
+PRIVATE double softening_radius(
+    struct PointMassList *mass_list,
+    struct PointMass *mass,
+    double h)
+{
+    switch (mass_list->softening_units)
+    {
+        case 2: // in units of the local disk height
+            return mass->softening_length * h;
+        default: // absolute (lengths in cell units are converted by the caller)
+            return mass->softening_length;
+    }
+}
+
+PRIVATE double gravitational_potential(
+    struct PointMassList *mass_list,
+    double x1,
+    double y1,
+    double h)
 {
     double phi = 0.0;
 
@@ -81,14 +241,13 @@ PRIVATE double gravitational_potential(
             double x0 = mass_list->masses[p].x;
             double y0 = mass_list->masses[p].y;
             double mp = mass_list->masses[p].mass;
-            double rs = mass_list->masses[p].softening_length;
+            double rs = softening_radius(mass_list, &mass_list->masses[p], h);
 
-            double dx = x1 - x0;
-            double dy = y1 - y0;
+            double dx = periodic_separation(x1 - x0, PERIOD_X);
+            double dy = periodic_separation(y1 - y0, PERIOD_Y);
             double r2 = dx * dx + dy * dy;
-            double r2_softened = r2 + rs * rs;
 
-            phi -= mp / sqrt(r2_softened);
+            phi += mp * softened_potential(r2, rs, mass_list->softening_model);
         }
     }
     return phi;
@@ -99,20 +258,21 @@ PRIVATE void point_mass_source_term(
     double x1,
     double y1,
     double dt,
-    double *prim,
+    const double *prim,
+    double r_soft,
+    int softening_model,
     double *delta_cons)
 {
     double x0 = mass->x;
     double y0 = mass->y;
     double sigma = prim[0];
-    double dx = x1 - x0;
-    double dy = y1 - y0;
+    double dx = periodic_separation(x1 - x0, PERIOD_X);
+    double dy = periodic_separation(y1 - y0, PERIOD_Y);
     double r2 = dx * dx + dy * dy;
     double dr = sqrt(r2);
     double r_sink = mass->sink_radius;
-    double r_soft = mass->softening_length;
 
-    double fgrav_numerator = sigma * mass->mass * pow(r2 + r_soft * r_soft, -1.5);
+    double fgrav_numerator = sigma * mass->mass * softened_force_factor(r2, r_soft, softening_model);
     double fx = -fgrav_numerator * dx;
     double fy = -fgrav_numerator * dy;
     double sink_rate = (dr < 4.0 * r_sink) ? mass->sink_rate * exp(-pow(dr / r_sink, 4.0)) : 0.0;
@@ -172,6 +332,15 @@ PRIVATE void point_mass_source_term(
             break;
         }
     }
+
+    if (mass->sink_model != 0)
+    {
+        // passive tracers are removed by the sink along with the gas
+        for (int q = 3; q < NCONS; ++q)
+        {
+            delta_cons[q] += dt * mdot * prim[q];
+        }
+    }
 }
 
 PRIVATE void point_masses_source_term(
@@ -179,12 +348,15 @@ PRIVATE void point_masses_source_term(
     double x1,
     double y1,
     double dt,
-    double *prim,
+    const double *prim,
+    double h,
     double *delta_cons)
 {
     for (int p = 0; p < 2; ++p)
     {
-        point_mass_source_term(&mass_list->masses[p], x1, y1, dt, prim, delta_cons);
+        struct PointMass *mass = &mass_list->masses[p];
+        double r_soft = softening_radius(mass_list, mass, h);
+        point_mass_source_term(mass, x1, y1, dt, prim, r_soft, mass_list->softening_model, delta_cons);
     }
 }
 
@@ -195,6 +367,8 @@ PRIVATE double sound_speed_squared(
     double cs2,
     double mach_squared,
     int eos_type,
+    int temperature_profile,
+    double flaring_index,
     double x,
     double y,
     struct PointMassList *mass_list)
@@ -204,7 +378,39 @@ PRIVATE double sound_speed_squared(
         case 1: // globally isothermal
             return cs2;
         case 2: // locally Isothermal
-            return -gravitational_potential(mass_list, x, y) / mach_squared;
+        {
+            if (temperature_profile == 1)
+            {
+                return cylindrical_sound_speed_squared(mass_list, mach_squared, flaring_index, x, y);
+            }
+            double h = 0.0;
+
+            if (mass_list->softening_units == 2)
+            {
+                h = disk_height(mass_list, cs2, mach_squared, eos_type, temperature_profile, flaring_index, x, y);
+            }
+            if (flaring_index == 0.0)
+            {
+                return -gravitational_potential(mass_list, x, y, h) / mach_squared;
+            }
+            double cs2_local = 0.0;
+
+            for (int p = 0; p < 2; ++p)
+            {
+                struct PointMass *mass = &mass_list->masses[p];
+
+                if (mass->mass > 0.0)
+                {
+                    double dx = periodic_separation(x - mass->x, PERIOD_X);
+                    double dy = periodic_separation(y - mass->y, PERIOD_Y);
+                    double rs = softening_radius(mass_list, mass, h);
+                    double r2 = dx * dx + dy * dy;
+                    double phi = mass->mass * softened_potential(r2, rs, mass_list->softening_model);
+                    cs2_local -= phi * pow(r2 + rs * rs, flaring_index);
+                }
+            }
+            return cs2_local / mach_squared;
+        }
         default:
             return 1.0; // WARNING
     }
@@ -230,10 +436,14 @@ PRIVATE void buffer_source_term(
 
         if (rc > onset_radius)
         {
+            // If the inflow viscosity is nonzero, the target state is a
+            // steady viscous disk with radial velocity -3 nu / 2r, which
+            // feeds the domain at the rate 3 pi nu Sigma.
             double v_kep = sqrt(central_mass / rc);
-            double px = surface_density * (-yc / rc) * v_kep;
-            double py = surface_density * (+xc / rc) * v_kep;
-            double u0[NCONS] = {surface_density, px, py};
+            double v_rad = -1.5 * buffer->inflow_viscosity / rc;
+            double px = surface_density * ((-yc / rc) * v_kep + (xc / rc) * v_rad);
+            double py = surface_density * ((+xc / rc) * v_kep + (yc / rc) * v_rad);
+            double u0[NCONS] = {surface_density, px, py}; // tracers driven to zero
             double omega_outer = sqrt(central_mass * pow(onset_radius, -3.0));
             double buffer_rate = driving_rate * omega_outer * (rc - onset_radius) / (outer_radius - onset_radius);
 
@@ -262,6 +472,54 @@ PRIVATE void shear_strain(
     s[3] = syy;
 }
 
+/**
+ * Return the viscosity multiplier at a point, by linear interpolation of a
+ * radial profile sampled at r = k * dr. The profile is held constant beyond
+ * its outermost sample, so a single sample gives a uniform multiplier.
+ */
+PRIVATE double viscosity_multiplier(
+    const double *profile,
+    int num_samples,
+    double dr,
+    double x,
+    double y)
+{
+    double s = sqrt(x * x + y * y) / dr;
+    int k = (int)s;
+
+    if (k >= num_samples - 1)
+    {
+        return profile[num_samples - 1];
+    }
+    return profile[k] + (s - k) * (profile[k + 1] - profile[k]);
+}
+
+
+/**
+ * Return the weight of the PLM gradient in the face values reconstructed
+ * from a zone: one half, or zero (first order) if the zone center is within
+ * the given radius of a gravitating point mass.
+ */
+PRIVATE double reconstruction_weight(
+    const struct PointMassList *mass_list,
+    double radius,
+    double x,
+    double y)
+{
+    for (int p = 0; p < 2; ++p)
+    {
+        const struct PointMass *mass = &mass_list->masses[p];
+        double dx = periodic_separation(x - mass->x, PERIOD_X);
+        double dy = periodic_separation(y - mass->y, PERIOD_Y);
+
+        if (mass->mass > 0.0 && dx * dx + dy * dy < radius * radius)
+        {
+            return 0.0;
+        }
+    }
+    return 0.5;
+}
+
 
 // ============================ HYDRO =========================================
 // ============================================================================
@@ -269,7 +527,8 @@ PRIVATE void conserved_to_primitive(
     const double *cons,
     double *prim,
     double velocity_ceiling,
-    double density_floor)
+    double density_floor,
+    double *floor_counts)
 {
     double rho = max2(cons[0], density_floor);
     double px = cons[1];
@@ -277,9 +536,19 @@ PRIVATE void conserved_to_primitive(
     double vx = sign(px) * min2(fabs(px / rho), velocity_ceiling);
     double vy = sign(py) * min2(fabs(py / rho), velocity_ceiling);
 
+    // count activations of the density floor, pressure floor (not used by
+    // this solver), and velocity ceiling
+    floor_counts[0] += cons[0] < density_floor;
+    floor_counts[2] += max2(fabs(px), fabs(py)) / rho > velocity_ceiling;
+
     prim[0] = rho;
     prim[1] = vx;
     prim[2] = vy;
+
+    for (int q = 3; q < NCONS; ++q)
+    {
+        prim[q] = cons[q] / rho;
+    }
 }
 
 PRIVATE void primitive_to_conserved(
@@ -295,6 +564,11 @@ PRIVATE void primitive_to_conserved(
     cons[0] = rho;
     cons[1] = px;
     cons[2] = py;
+
+    for (int q = 3; q < NCONS; ++q)
+    {
+        cons[q] = rho * prim[q];
+    }
 }
 
 PRIVATE double primitive_to_velocity(
@@ -323,6 +597,11 @@ PRIVATE void primitive_to_flux(
     flux[0] = vn * cons[0];
     flux[1] = vn * cons[1] + pressure * (direction == 0);
     flux[2] = vn * cons[2] + pressure * (direction == 1);
+
+    for (int q = 3; q < NCONS; ++q)
+    {
+        flux[q] = vn * cons[q];
+    }
 }
 
 PRIVATE void primitive_to_outer_wavespeeds(
@@ -349,6 +628,28 @@ PRIVATE double primitive_max_wavespeed(
     return max2(ax, ay);
 }
 
+/**
+ * The low-Mach correction of Thornber et al. (2008), JCP 227, 4873: the jump
+ * in the reconstructed velocity across a face is reduced by the face Mach
+ * number z = min(1, max(Ml, Mr)), keeping its mean. The dissipation of the
+ * Riemann solver scales with the velocity jump times the sound speed, so
+ * without this it is excessive in deeply subsonic flow.
+ */
+PRIVATE void low_mach_correction(double *pl, double *pr, double cs2)
+{
+    double ml = sqrt((pl[1] * pl[1] + pl[2] * pl[2]) / cs2);
+    double mr = sqrt((pr[1] * pr[1] + pr[2] * pr[2]) / cs2);
+    double z = min2(1.0, max2(ml, mr));
+
+    for (int q = 1; q < 3; ++q)
+    {
+        double mean = 0.5 * (pl[q] + pr[q]);
+        double jump = 0.5 * (pl[q] - pr[q]);
+        pl[q] = mean + z * jump;
+        pr[q] = mean - z * jump;
+    }
+}
+
 PRIVATE void riemann_hlle(
     const double *pl,
     const double *pr,
@@ -356,6 +657,20 @@ PRIVATE void riemann_hlle(
     double cs2,
     int direction)
 {
+#if (LOW_MACH_CORRECTION)
+    double plc[NCONS];
+    double prc[NCONS];
+
+    for (int q = 0; q < NCONS; ++q)
+    {
+        plc[q] = pl[q];
+        prc[q] = pr[q];
+    }
+    low_mach_correction(plc, prc, cs2);
+    pl = plc;
+    pr = prc;
+#endif
+
     double ul[NCONS];
     double ur[NCONS];
     double fl[NCONS];
@@ -382,6 +697,75 @@ PRIVATE void riemann_hlle(
 
 // ============================ PUBLIC API ====================================
 // ============================================================================
+PRIVATE void external_acceleration_source_term(
+    const double *acceleration,
+    double dt,
+    const double *prim,
+    double *delta_cons)
+{
+    double sigma = prim[0];
+    delta_cons[1] += sigma * acceleration[0] * dt;
+    delta_cons[2] += sigma * acceleration[1] * dt;
+}
+
+/**
+ * Coriolis and tidal source terms in a local shearing box, rotating at the
+ * frequency omega, with a Keplerian shear rate.
+ */
+PRIVATE void shearing_box_source_term(
+    double omega,
+    double x,
+    double dt,
+    const double *prim,
+    double *delta_cons)
+{
+    double sigma = prim[0];
+    double vx = prim[1];
+    double vy = prim[2];
+    delta_cons[1] += sigma * (2.0 * omega * vy + 3.0 * omega * omega * x) * dt;
+    delta_cons[2] += sigma * (-2.0 * omega * vx) * dt;
+}
+
+/**
+ * Contribution of a zone to the outward flux of a conserved quantity through
+ * a shell, divided by the cell area; see `Patch.surface_flux` in cbdiso_2d.py.
+ * The shell is given as (quantity, r0, r1, x0, y0, vx0, vy0), where the
+ * quantity is the index of the flux in `SURFACE_FLUXES`, r0 and r1 are the
+ * inner and outer radii, and (x0, y0) and (vx0, vy0) are the position and
+ * velocity of its center.
+ */
+PRIVATE double surface_flux_density(
+    const double *shell,
+    double x,
+    double y,
+    const double *prim)
+{
+    double dx = x - shell[3];
+    double dy = y - shell[4];
+    double r = fmax(sqrt(dx * dx + dy * dy), 1e-12);
+
+    if (r < shell[1] || r >= shell[2])
+    {
+        return 0.0;
+    }
+    double sigma = prim[0];
+    double vx = prim[1];
+    double vy = prim[2];
+    double dvx = vx - shell[5];
+    double dvy = vy - shell[6];
+    double vr = (dvx * dx + dvy * dy) / r;
+    double f = 0.0;
+
+    switch ((int)shell[0])
+    {
+        case 0: f = sigma; break;
+        case 1: f = sigma * vx; break;
+        case 2: f = sigma * vy; break;
+        case 3: f = sigma * (dx * dvy - dy * dvx); break;
+    }
+    return f * vr / (shell[2] - shell[1]);
+}
+
 PUBLIC void cbdiso_2d_advance_rk(
     int ni,
     int nj,
@@ -389,15 +773,16 @@ PUBLIC void cbdiso_2d_advance_rk(
     double patch_xr,
     double patch_yl,
     double patch_yr,
-    double *conserved_rk, // :: $.shape == (ni + 4, nj + 4, 3)
-    double *primitive_rd, // :: $.shape == (ni + 4, nj + 4, 3)
-    double *primitive_wr, // :: $.shape == (ni + 4, nj + 4, 3)
+    const double *conserved_rk, // :: $.shape[:2] == (ni + 4, nj + 4)
+    const double *primitive_rd, // :: $.shape[:2] == (ni + 4, nj + 4)
+    double *primitive_wr, // :: $.shape[:2] == (ni + 4, nj + 4)
     double buffer_surface_density,
     double buffer_central_mass,
     double buffer_driving_rate,
     double buffer_outer_radius,
     double buffer_onset_width,
     int buffer_is_enabled,
+    double buffer_inflow_viscosity,
     double x1, // point mass 1
     double y1,
     double vx1,
@@ -416,14 +801,33 @@ PUBLIC void cbdiso_2d_advance_rk(
     double sink_rate2,
     double sink_radius2,
     int sink_model2,
+    double first_order_radius, // :: $ >= 0.0
+    int softening_model, // gravitational softening
+    int softening_units,
     double cs2, // equation of state
     double mach_squared,
     int eos_type,
+    int temperature_profile,
+    double flaring_index,
     double nu, // kinematic viscosity coefficient
+    const double *viscosity_profile, // :: $.shape == (viscosity_profile_samples,)
+    int viscosity_profile_samples, // :: $ >= 1
+    double viscosity_profile_dr, // :: $ > 0.0
+    const double *acceleration, // :: $.shape == (ni, nj, 2) or not acceleration_is_enabled
+    int acceleration_is_enabled,
+    double shearing_box_frequency, // :: $ >= 0.0
     double a, // RK parameter
     double dt, // timestep
     double velocity_ceiling,
-    double density_floor)
+    double density_floor,
+    double *floor_counts, // :: $.shape == (ni, nj, 3)
+    double *activation_map, // :: $.shape == (ni, nj, 4) or not activation_maps_enabled
+    int activation_maps_enabled,
+    double *reductions, // :: $.shape == (ni, 1 + num_surface_fluxes) or not reductions_enabled
+    const double *surface_fluxes, // :: $.shape[1:] == (7,)
+    int num_surface_fluxes,
+    const double *reduction_masses, // :: $.shape == (2, 4)
+    int reductions_enabled)
 {
     struct KeplerianBuffer buffer = {
         buffer_surface_density,
@@ -431,11 +835,25 @@ PUBLIC void cbdiso_2d_advance_rk(
         buffer_driving_rate,
         buffer_outer_radius,
         buffer_onset_width,
-        buffer_is_enabled
+        buffer_is_enabled,
+        buffer_inflow_viscosity
     };
     struct PointMass m1 = {x1, y1, vx1, vy1, mass1, softening_length1, sink_rate1, sink_radius1, sink_model1};
     struct PointMass m2 = {x2, y2, vx2, vy2, mass2, softening_length2, sink_rate2, sink_radius2, sink_model2};
-    struct PointMassList mass_list = {{m1, m2}};
+    struct PointMassList mass_list = {{m1, m2}, softening_model, softening_units};
+
+    // The point masses at the end of the stage, (x, y, mass, softening length)
+    // for each, which the sound speed of the reduced wavespeed is evaluated
+    // with, so it is identical to the wavespeed computed in a separate pass.
+    struct PointMassList reduction_mass_list = mass_list;
+
+    for (int p = 0; p < 2; ++p)
+    {
+        reduction_mass_list.masses[p].x = reduction_masses[4 * p + 0];
+        reduction_mass_list.masses[p].y = reduction_masses[4 * p + 1];
+        reduction_mass_list.masses[p].mass = reduction_masses[4 * p + 2];
+        reduction_mass_list.masses[p].softening_length = reduction_masses[4 * p + 3];
+    }
 
     double dx = (patch_xr - patch_xl) / ni;
     double dy = (patch_yr - patch_yl) / nj;
@@ -487,20 +905,20 @@ PUBLIC void cbdiso_2d_advance_rk(
         int nrl = (i + 1 + ng) * si + (j - 1 + ng) * sj;
         int nrr = (i + 1 + ng) * si + (j + 1 + ng) * sj;
 
-        double *un = &conserved_rk[ncc];
-        double *pcc = &primitive_rd[ncc];
-        double *pli = &primitive_rd[nli];
-        double *pri = &primitive_rd[nri];
-        double *plj = &primitive_rd[nlj];
-        double *prj = &primitive_rd[nrj];
-        double *pki = &primitive_rd[nki];
-        double *pti = &primitive_rd[nti];
-        double *pkj = &primitive_rd[nkj];
-        double *ptj = &primitive_rd[ntj];
-        double *pll = &primitive_rd[nll];
-        double *plr = &primitive_rd[nlr];
-        double *prl = &primitive_rd[nrl];
-        double *prr = &primitive_rd[nrr];
+        const double *un = &conserved_rk[ncc];
+        const double *pcc = &primitive_rd[ncc];
+        const double *pli = &primitive_rd[nli];
+        const double *pri = &primitive_rd[nri];
+        const double *plj = &primitive_rd[nlj];
+        const double *prj = &primitive_rd[nrj];
+        const double *pki = &primitive_rd[nki];
+        const double *pti = &primitive_rd[nti];
+        const double *pkj = &primitive_rd[nkj];
+        const double *ptj = &primitive_rd[ntj];
+        const double *pll = &primitive_rd[nll];
+        const double *plr = &primitive_rd[nlr];
+        const double *prl = &primitive_rd[nrl];
+        const double *prr = &primitive_rd[nrr];
 
         double plip[NCONS];
         double plim[NCONS];
@@ -533,17 +951,26 @@ PUBLIC void cbdiso_2d_advance_rk(
         plm_gradient(pll, plj, prl, gxlj);
         plm_gradient(plr, prj, prr, gxrj);
 
+        // Each zone's face values are reconstructed to first order if it is
+        // near a point mass. The weights are those of the zones the face
+        // values come from, so both zones sharing a face see the same flux.
+        double wcc = reconstruction_weight(&mass_list, first_order_radius, xc, yc);
+        double wli = reconstruction_weight(&mass_list, first_order_radius, xc - dx, yc);
+        double wri = reconstruction_weight(&mass_list, first_order_radius, xc + dx, yc);
+        double wlj = reconstruction_weight(&mass_list, first_order_radius, xc, yc - dy);
+        double wrj = reconstruction_weight(&mass_list, first_order_radius, xc, yc + dy);
+
         for (int q = 0; q < NCONS; ++q)
         {
-            plim[q] = pli[q] + 0.5 * gxli[q];
-            plip[q] = pcc[q] - 0.5 * gxcc[q];
-            prim[q] = pcc[q] + 0.5 * gxcc[q];
-            prip[q] = pri[q] - 0.5 * gxri[q];
-
-            pljm[q] = plj[q] + 0.5 * gylj[q];
-            pljp[q] = pcc[q] - 0.5 * gycc[q];
-            prjm[q] = pcc[q] + 0.5 * gycc[q];
-            prjp[q] = prj[q] - 0.5 * gyrj[q];
+            plim[q] = pli[q] + wli * gxli[q];
+            plip[q] = pcc[q] - wcc * gxcc[q];
+            prim[q] = pcc[q] + wcc * gxcc[q];
+            prip[q] = pri[q] - wri * gxri[q];
+
+            pljm[q] = plj[q] + wlj * gylj[q];
+            pljp[q] = pcc[q] - wcc * gycc[q];
+            prjm[q] = pcc[q] + wcc * gycc[q];
+            prjp[q] = prj[q] - wrj * gyrj[q];
         }
 
         double fli[NCONS];
@@ -552,10 +979,10 @@ PUBLIC void cbdiso_2d_advance_rk(
         double frj[NCONS];
         double ucc[NCONS];
 
-        double cs2li = sound_speed_squared(cs2, mach_squared, eos_type, xl, yc, &mass_list);
-        double cs2ri = sound_speed_squared(cs2, mach_squared, eos_type, xr, yc, &mass_list);
-        double cs2lj = sound_speed_squared(cs2, mach_squared, eos_type, xc, yl, &mass_list);
-        double cs2rj = sound_speed_squared(cs2, mach_squared, eos_type, xc, yr, &mass_list);
+        double cs2li = sound_speed_squared(cs2, mach_squared, eos_type, temperature_profile, flaring_index, xl, yc, &mass_list);
+        double cs2ri = sound_speed_squared(cs2, mach_squared, eos_type, temperature_profile, flaring_index, xr, yc, &mass_list);
+        double cs2lj = sound_speed_squared(cs2, mach_squared, eos_type, temperature_profile, flaring_index, xc, yl, &mass_list);
+        double cs2rj = sound_speed_squared(cs2, mach_squared, eos_type, temperature_profile, flaring_index, xc, yr, &mass_list);
 
         riemann_hlle(plim, plip, fli, cs2li, 0);
         riemann_hlle(prim, prip, fri, cs2ri, 0);
@@ -576,19 +1003,41 @@ PUBLIC void cbdiso_2d_advance_rk(
             shear_strain(gxrj, gyrj, dx, dy, srj);
             shear_strain(gxcc, gycc, dx, dy, scc);
 
-            fli[1] -= 0.5 * nu * (pli[0] * sli[0] + pcc[0] * scc[0]); // x-x
-            fli[2] -= 0.5 * nu * (pli[0] * sli[1] + pcc[0] * scc[1]); // x-y
-            fri[1] -= 0.5 * nu * (pcc[0] * scc[0] + pri[0] * sri[0]); // x-x
-            fri[2] -= 0.5 * nu * (pcc[0] * scc[1] + pri[0] * sri[1]); // x-y
-            flj[1] -= 0.5 * nu * (plj[0] * slj[2] + pcc[0] * scc[2]); // y-x
-            flj[2] -= 0.5 * nu * (plj[0] * slj[3] + pcc[0] * scc[3]); // y-y
-            frj[1] -= 0.5 * nu * (pcc[0] * scc[2] + prj[0] * srj[2]); // y-x
-            frj[2] -= 0.5 * nu * (pcc[0] * scc[3] + prj[0] * srj[3]); // y-y
+            double nucc = nu * viscosity_multiplier(viscosity_profile, viscosity_profile_samples, viscosity_profile_dr, xc, yc);
+            double nuli = nu * viscosity_multiplier(viscosity_profile, viscosity_profile_samples, viscosity_profile_dr, xc - dx, yc);
+            double nuri = nu * viscosity_multiplier(viscosity_profile, viscosity_profile_samples, viscosity_profile_dr, xc + dx, yc);
+            double nulj = nu * viscosity_multiplier(viscosity_profile, viscosity_profile_samples, viscosity_profile_dr, xc, yc - dy);
+            double nurj = nu * viscosity_multiplier(viscosity_profile, viscosity_profile_samples, viscosity_profile_dr, xc, yc + dy);
+
+            fli[1] -= 0.5 * (nuli * pli[0] * sli[0] + nucc * pcc[0] * scc[0]); // x-x
+            fli[2] -= 0.5 * (nuli * pli[0] * sli[1] + nucc * pcc[0] * scc[1]); // x-y
+            fri[1] -= 0.5 * (nucc * pcc[0] * scc[0] + nuri * pri[0] * sri[0]); // x-x
+            fri[2] -= 0.5 * (nucc * pcc[0] * scc[1] + nuri * pri[0] * sri[1]); // x-y
+            flj[1] -= 0.5 * (nulj * plj[0] * slj[2] + nucc * pcc[0] * scc[2]); // y-x
+            flj[2] -= 0.5 * (nulj * plj[0] * slj[3] + nucc * pcc[0] * scc[3]); // y-y
+            frj[1] -= 0.5 * (nucc * pcc[0] * scc[2] + nurj * prj[0] * srj[2]); // y-x
+            frj[2] -= 0.5 * (nucc * pcc[0] * scc[3] + nurj * prj[0] * srj[3]); // y-y
         }
-        double delta_cons[3] = {0.0, 0.0, 0.0};
+        double delta_cons[NCONS] = {0.0};
         primitive_to_conserved(pcc, ucc);
         buffer_source_term(&buffer, xc, yc, dt, ucc, delta_cons);
-        point_masses_source_term(&mass_list, xc, yc, dt, pcc, delta_cons);
+        double hcc = 0.0;
+
+        if (softening_units == 2)
+        {
+            hcc = disk_height(&mass_list, cs2, mach_squared, eos_type, temperature_profile, flaring_index, xc, yc);
+        }
+        point_masses_source_term(&mass_list, xc, yc, dt, pcc, hcc, delta_cons);
+
+        if (acceleration_is_enabled)
+        {
+            external_acceleration_source_term(&acceleration[2 * (i * nj + j)], dt, pcc, delta_cons);
+        }
+
+        if (shearing_box_frequency > 0.0)
+        {
+            shearing_box_source_term(shearing_box_frequency, xc, dt, pcc, delta_cons);
+        }
 
         for (int q = 0; q < NCONS; ++q)
         {
@@ -599,15 +1048,56 @@ PUBLIC void cbdiso_2d_advance_rk(
             ucc[q] += delta_cons[q];
             ucc[q] = (1.0 - a) * ucc[q] + a * un[q];
         }
-        conserved_to_primitive(ucc, &primitive_wr[ncc], velocity_ceiling, density_floor);
+        // Floor and ceiling activations are counted for the diagnostics, and
+        // optionally accumulated in per-zone maps along with activations of
+        // the slope limiter (on the density field).
+        double floor_flags[3] = {0.0, 0.0, 0.0};
+        double *fcc = &floor_counts[3 * (i * nj + j)];
+        conserved_to_primitive(ucc, &primitive_wr[ncc], velocity_ceiling, density_floor, floor_flags);
+
+        for (int f = 0; f < 3; ++f)
+        {
+            fcc[f] += floor_flags[f];
+        }
+
+        if (activation_maps_enabled)
+        {
+            double *mcc = &activation_map[4 * (i * nj + j)];
+            int limited_x = gxcc[0] != 0.5 * (pri[0] - pli[0]);
+            int limited_y = gycc[0] != 0.5 * (prj[0] - plj[0]);
+
+            for (int f = 0; f < 3; ++f)
+            {
+                mcc[f] += floor_flags[f];
+            }
+            mcc[3] += limited_x || limited_y;
+        }
+
+        // On the host, the maximum wavespeed and the surface fluxes of the
+        // updated solution are reduced here, in the same parallel loop as the
+        // update, rather than in separate passes over the grid. Each row of
+        // zones is updated by a single thread, so the partials are kept per
+        // row, and summed in the same order for any number of threads.
+        if (reductions_enabled)
+        {
+            double *pw = &primitive_wr[ncc];
+            double *rcc = &reductions[i * (1 + num_surface_fluxes)];
+            double cs2cc = sound_speed_squared(cs2, mach_squared, eos_type, temperature_profile, flaring_index, xc, yc, &reduction_mass_list);
+            rcc[0] = fmax(rcc[0], primitive_max_wavespeed(pw, cs2cc));
+
+            for (int n = 0; n < num_surface_fluxes; ++n)
+            {
+                rcc[1 + n] += surface_flux_density(&surface_fluxes[7 * n], xc, yc, pw);
+            }
+        }
     }
 }
 
 PUBLIC void cbdiso_2d_primitive_to_conserved(
     int ni,
     int nj,
-    double *primitive, // :: $.shape == (ni + 4, nj + 4, 3)
-    double *conserved) // :: $.shape == (ni + 4, nj + 4, 3)
+    const double *primitive, // :: $.shape[:2] == (ni + 4, nj + 4)
+    double *conserved) // :: $.shape[:2] == (ni + 4, nj + 4)
 {
     int ng = 2; // number of guard zones
     int si = NCONS * (nj + 2 * ng);
@@ -617,7 +1107,7 @@ PUBLIC void cbdiso_2d_primitive_to_conserved(
     {
         int n = (i + ng) * si + (j + ng) * sj;
 
-        double *pc = &primitive[n];
+        const double *pc = &primitive[n];
         double *uc = &conserved[n];
         primitive_to_conserved(pc, uc);
     }
@@ -630,7 +1120,7 @@ PUBLIC void cbdiso_2d_point_mass_source_term(
     double patch_xr,
     double patch_yl,
     double patch_yr,
-    double x1, // particle
+    double x1, // point mass 1
     double y1,
     double vx1,
     double vy1,
@@ -639,10 +1129,29 @@ PUBLIC void cbdiso_2d_point_mass_source_term(
     double sink_rate1,
     double sink_radius1,
     int sink_model1,
-    double *primitive, // :: $.shape == (ni + 4, nj + 4, 3)
-    double *cons_rate) // :: $.shape == (ni + 4, nj + 4, 3)
+    double x2, // point mass 2
+    double y2,
+    double vx2,
+    double vy2,
+    double mass2,
+    double softening_length2,
+    double sink_rate2,
+    double sink_radius2,
+    int sink_model2,
+    int which_mass, // :: $ in [1, 2]
+    int softening_model, // gravitational softening
+    int softening_units,
+    double soundspeed2, // equation of state
+    double mach_squared,
+    int eos_type,
+    int temperature_profile,
+    double flaring_index,
+    const double *primitive, // :: $.shape[:2] == (ni + 4, nj + 4)
+    double *cons_rate) // :: $.shape[:2] == (ni + 4, nj + 4)
 {
     struct PointMass m1 = {x1, y1, vx1, vy1, mass1, softening_length1, sink_rate1, sink_radius1, sink_model1};
+    struct PointMass m2 = {x2, y2, vx2, vy2, mass2, softening_length2, sink_rate2, sink_radius2, sink_model2};
+    struct PointMassList mass_list = {{m1, m2}, softening_model, softening_units};
 
     int ng = 2; // number of guard zones
     int si = NCONS * (nj + 2 * ng);
@@ -657,9 +1166,17 @@ PUBLIC void cbdiso_2d_point_mass_source_term(
 
         double xc = patch_xl + (i + 0.5) * dx;
         double yc = patch_yl + (j + 0.5) * dy;
-        double *pc = &primitive[ncc];
+        const double *pc = &primitive[ncc];
         double *uc = &cons_rate[ncc];
-        point_mass_source_term(&m1, xc, yc, 1.0, pc, uc);
+        double h = 0.0;
+
+        if (softening_units == 2)
+        {
+            h = disk_height(&mass_list, soundspeed2, mach_squared, eos_type, temperature_profile, flaring_index, xc, yc);
+        }
+        struct PointMass *mass = &mass_list.masses[which_mass - 1];
+        double r_soft = softening_radius(&mass_list, mass, h);
+        point_mass_source_term(mass, xc, yc, 1.0, pc, r_soft, softening_model, uc);
     }
 }
 
@@ -673,6 +1190,8 @@ PUBLIC void cbdiso_2d_wavespeed(
     double soundspeed2, // equation of state
     double mach_squared,
     int eos_type,
+    int temperature_profile,
+    double flaring_index,
     double x1, // point mass 1
     double y1,
     double vx1,
@@ -691,12 +1210,14 @@ PUBLIC void cbdiso_2d_wavespeed(
     double sink_rate2,
     double sink_radius2,
     int sink_model2,
-    double *primitive, // :: $.shape == (ni + 4, nj + 4, 3)
+    int softening_model, // gravitational softening
+    int softening_units,
+    const double *primitive, // :: $.shape[:2] == (ni + 4, nj + 4)
     double *wavespeed) // :: $.shape == (ni + 4, nj + 4)
 {
     struct PointMass m1 = {x1, y1, vx1, vy1, mass1, softening_length1, sink_rate1, sink_radius1, sink_model1};
     struct PointMass m2 = {x2, y2, vx2, vy2, mass2, softening_length2, sink_rate2, sink_radius2, sink_model2};
-    struct PointMassList mass_list = {{m1, m2}};
+    struct PointMassList mass_list = {{m1, m2}, softening_model, softening_units};
 
     int ng = 2; // number of guard zones
     int si = NCONS * (nj + 2 * ng);
@@ -714,9 +1235,58 @@ PUBLIC void cbdiso_2d_wavespeed(
         double x = patch_xl + (i + 0.5) * dx;
         double y = patch_yl + (j + 0.5) * dy;
 
-        double *pc = &primitive[np];
-        double cs2 = sound_speed_squared(soundspeed2, mach_squared, eos_type, x, y, &mass_list);
+        const double *pc = &primitive[np];
+        double cs2 = sound_speed_squared(soundspeed2, mach_squared, eos_type, temperature_profile, flaring_index, x, y, &mass_list);
         double a = primitive_max_wavespeed(pc, cs2);
         wavespeed[na] = a;
     }
 }
+
+PUBLIC void cbdiso_2d_sound_speed_squared(
+    int ni, // mesh
+    int nj,
+    double patch_xl,
+    double patch_xr,
+    double patch_yl,
+    double patch_yr,
+    double soundspeed2, // equation of state
+    double mach_squared,
+    int eos_type,
+    int temperature_profile,
+    double flaring_index,
+    double x1, // point mass 1
+    double y1,
+    double vx1,
+    double vy1,
+    double mass1,
+    double softening_length1,
+    double sink_rate1,
+    double sink_radius1,
+    int sink_model1,
+    double x2, // point mass 2
+    double y2,
+    double vx2,
+    double vy2,
+    double mass2,
+    double softening_length2,
+    double sink_rate2,
+    double sink_radius2,
+    int sink_model2,
+    int softening_model, // gravitational softening
+    int softening_units,
+    double *sound_speed_squared_out) // :: $.shape == (ni, nj)
+{
+    struct PointMass m1 = {x1, y1, vx1, vy1, mass1, softening_length1, sink_rate1, sink_radius1, sink_model1};
+    struct PointMass m2 = {x2, y2, vx2, vy2, mass2, softening_length2, sink_rate2, sink_radius2, sink_model2};
+    struct PointMassList mass_list = {{m1, m2}, softening_model, softening_units};
+
+    double dx = (patch_xr - patch_xl)/ni;
+    double dy = (patch_yr - patch_yl)/nj;
+
+    FOR_EACH_2D(ni, nj)
+    {
+        double x = patch_xl + (i + 0.5) * dx;
+        double y = patch_yl + (j + 0.5) * dy;
+        sound_speed_squared_out[i * nj + j] = sound_speed_squared(soundspeed2, mach_squared, eos_type, temperature_profile, flaring_index, x, y, &mass_list);
+    }
+}
@@ -2,24 +2,53 @@
 Isothermal solver for the binary accretion problem in 2D planar coordinates.
 """
 
+from math import hypot, pi
 from logging import getLogger
 from typing import NamedTuple, List
 from sailfish.kernel.library import Library
-from sailfish.kernel.system import get_array_module, execution_context, num_devices
+from sailfish.kernel.system import (
+    KernelGraphCache,
+    copy_guard_zones,
+    execution_context,
+    get_array_module,
+    num_devices,
+)
 from sailfish.mesh import PlanarCartesian2DMesh
 from sailfish.physics.circumbinary import (
     Physics,
     EquationOfState,
     ViscosityModel,
+    SofteningUnits,
     Diagnostic,
+    DyeRegion,
+    FLOOR_FIELDS,
+    ACTIVATION_MAPS,
+    SURFACE_FLUXES,
 )
+from sailfish.fields import solver_fields
 from sailfish.solver_base import SolverBase
-from sailfish.subdivide import subdivide, to_host, concat_on_host, lazy_reduce
+from sailfish.solvers.cbd_diagnostics import fourier_modes, histogram
+from sailfish.subdivide import (
+    subdivide,
+    concat_on_host,
+    lazy_reduce,
+    pack_on_device,
+    unpack_on_host,
+)
 
 
 logger = getLogger(__name__)
 
 
+def num_fields(physics=None):
+    """
+    Return the number of primitive fields for a physics configuration.
+    """
+    if physics is None:
+        return 3
+    return 3 + physics.num_tracers
+
+
 class Options(NamedTuple):
     """
     Contains parameters which are solver specific options.
@@ -28,16 +57,27 @@ class Options(NamedTuple):
     velocity_ceiling: float = 1e12
     density_floor: float = 1e-12
     rk_order: int = 2
+    out_of_core: bool = False
+    out_of_core_tile_size: int = 256
+    activation_maps: bool = False
+    torque_maps: bool = False
+    fused_reductions: bool = False
+    plm_theta: float = 1.8
+    cuda_graphs: bool = False
+    low_mach_correction: bool = False
 
 
-def initial_condition(setup, mesh, time):
+def initial_condition(setup, mesh, time, num_tracers=0):
     """
     Generate a 2D array of primitive data from a mesh and a setup.
+
+    Passive tracer concentrations are initialized to zero, unless they are
+    written by the setup.
     """
     import numpy as np
 
     ni, nj = mesh.shape
-    primitive = np.zeros([ni, nj, 3])
+    primitive = np.zeros([ni, nj, 3 + num_tracers])
 
     for i in range(ni):
         for j in range(nj):
@@ -62,9 +102,11 @@ class Patch:
         options,
         buffer_outer_radius,
         buffer_surface_density,
+        buffer_inflow_viscosity,
         lib,
         xp,
         execution_context,
+        fused_reductions=False,
     ):
         i0, i1 = index_range
         ni, nj = i1 - i0, mesh.shape[1]
@@ -80,6 +122,7 @@ class Patch:
         self.xr, self.yr = mesh.vertex_coordinates(i1, nj)
         self.buffer_outer_radius = buffer_outer_radius
         self.buffer_surface_density = buffer_surface_density
+        self.buffer_inflow_viscosity = buffer_inflow_viscosity
 
         with self.execution_context:
             x0 = self.xl + 0.5 * mesh.dx
@@ -92,6 +135,87 @@ class Patch:
             self.primitive1 = xp.array(primitive)
             self.primitive2 = xp.array(primitive)
             self.conserved0 = xp.zeros(primitive.shape)
+            self.surface_flux_sums = [0.0] * len(physics.diagnostics)
+            self.acceleration = xp.zeros(
+                self.shape + (2,) if physics.external_acceleration else (1, 1, 2)
+            )
+            outer_radius = hypot(max(-mesh.x0, mesh.x1), max(-mesh.y0, mesh.y1))
+            profile, dr = physics.viscosity_profile_table(outer_radius)
+            self.viscosity_profile = xp.array(profile)
+            self.viscosity_profile_dr = dr
+            self.floor_counts = xp.zeros(self.shape + (3,))
+            self.activation_map = xp.zeros(
+                self.shape + (4,) if options.activation_maps else (1, 1, 4)
+            )
+            self.fused_reductions = fused_reductions
+            self.surface_flux_diagnostics = [
+                d for d in physics.diagnostics if d.is_surface_flux
+            ]
+            self.reductions = xp.zeros(
+                (ni if fused_reductions else 1, 1 + len(self.surface_flux_diagnostics))
+            )
+            self.reductions_time = None
+
+    def surface_flux_shell(self, diagnostic, time):
+        """
+        Return the shell over which a surface flux is measured, as a tuple
+        (quantity, r0, r1, x0, y0, vx0, vy0), where the quantity is the index
+        of the flux in `SURFACE_FLUXES`. A circle is widened to a shell two
+        grid spacings wide.
+        """
+        d = diagnostic
+        r0, r1 = d.surface_flux_radii(2.0 * self.mesh.min_spacing())
+
+        if d.which_mass in (1, 2):
+            m = self.physics.point_masses(time)[d.which_mass - 1]
+            x0, y0 = m.position_x, m.position_y
+            vx0, vy0 = m.velocity_x, m.velocity_y
+        else:
+            x0, y0, vx0, vy0 = 0.0, 0.0, 0.0, 0.0
+
+        return (SURFACE_FLUXES.index(d.quantity), r0, r1, x0, y0, vx0, vy0)
+
+    def reduced_surface_flux(self, diagnostic):
+        """
+        Return the surface flux of a diagnostic from the partials reduced by
+        the advance kernel, or `None` if they are not current.
+        """
+        if self.reductions_time != self.time:
+            return None
+
+        n = self.surface_flux_diagnostics.index(diagnostic)
+        return self.reductions[:, 1 + n].sum()
+
+    def surface_flux(self, diagnostic):
+        """
+        Return the outward flux of a conserved quantity through a circle or
+        annulus, centered on the origin or on one of the point masses, divided
+        by the cell area. The surface moves with the point mass if it's
+        centered on one, and the angular momentum is measured about its
+        center, in its rest frame. The flux is the advective part only, and
+        is averaged over the cells in the annulus, or within one grid spacing
+        of the circle. The result is a device scalar.
+        """
+        ng = 2  # number of guard cells
+        d = diagnostic
+        x, y = self.cell_center_coordinate_arrays
+        prim = self.primitive1[ng:-ng, ng:-ng]
+        sigma, vx, vy = prim[..., 0], prim[..., 1], prim[..., 2]
+        _, r0, r1, x0, y0, vx0, vy0 = self.surface_flux_shell(d, self.time)
+        r = self.xp.maximum(((x - x0) ** 2 + (y - y0) ** 2) ** 0.5, 1e-12)
+        vr = ((vx - vx0) * (x - x0) + (vy - vy0) * (y - y0)) / r
+        shell = (r >= r0) & (r < r1)
+
+        if d.quantity == "mass_flux":
+            f = sigma
+        elif d.quantity == "momentum_flux_x":
+            f = sigma * vx
+        elif d.quantity == "momentum_flux_y":
+            f = sigma * vy
+        elif d.quantity == "angular_momentum_flux":
+            f = sigma * ((x - x0) * (vy - vy0) - (y - y0) * (vx - vx0))
+
+        return (f * vr * shell).sum() / (r1 - r0)
 
     @property
     def cell_center_coordinate_arrays(self):
@@ -112,7 +236,7 @@ class Patch:
         if which_mass not in (1, 2):
             raise ValueError("which_mass must be either 1 or 2")
 
-        m = self.physics.point_masses(self.time)[which_mass - 1]
+        m1, m2 = self.physics.point_masses(self.time)
 
         with self.execution_context:
             cons_rate = self.xp.zeros_like(self.conserved0)
@@ -122,24 +246,89 @@ class Patch:
                 self.xr,
                 self.yl,
                 self.yr,
-                m.position_x,
-                m.position_y,
-                m.velocity_x,
-                m.velocity_y,
-                m.mass * gravity,
-                m.softening_length,
-                m.sink_rate * accretion,
-                m.sink_radius,
-                m.sink_model.value,
+                m1.position_x,
+                m1.position_y,
+                m1.velocity_x,
+                m1.velocity_y,
+                m1.mass * gravity,
+                m1.softening_length,
+                m1.sink_rate * accretion,
+                m1.sink_radius,
+                m1.sink_model.value,
+                m2.position_x,
+                m2.position_y,
+                m2.velocity_x,
+                m2.velocity_y,
+                m2.mass * gravity,
+                m2.softening_length,
+                m2.sink_rate * accretion,
+                m2.sink_radius,
+                m2.sink_model.value,
+                which_mass,
+                self.physics.softening_model.value,
+                self.physics.softening_units.value,
+                self.physics.sound_speed**2,
+                self.physics.mach_number**2,
+                self.physics.eos_type.value,
+                self.physics.temperature_profile.value,
+                self.physics.flaring_index,
                 self.primitive1,
                 cons_rate,
             )
         return cons_rate[ng:-ng, ng:-ng]
 
+    def torque_density(self, which_mass):
+        """
+        Return the gravitational torque density about the origin, exerted by
+        the gas on one point mass, on the patch cells without guard zones.
+
+        This is the negative of the torque exerted by the point mass on the
+        gas in each cell, since the gravitational force is central.
+        """
+        x, y = self.cell_center_coordinate_arrays
+        udot = self.point_mass_source_term(which_mass, gravity=True)
+        return y * udot[..., 1] - x * udot[..., 2]
+
+    def inject_dye(self, region):
+        """
+        Set the concentration of a passive tracer inside a dye region.
+        """
+        ng = 2  # number of guard cells
+
+        if region.center == "origin":
+            x0, y0 = 0.0, 0.0
+        else:
+            m = self.physics.point_masses(self.time)[region.center - 1]
+            x0, y0 = m.position_x, m.position_y
+
+        with self.execution_context:
+            x, y = self.cell_center_coordinate_arrays
+            dx, dy = x - x0, y - y0
+            r = (dx**2 + dy**2) ** 0.5
+
+            if region.shape == "annulus":
+                inside = (region.inner_radius <= r) * (r < region.outer_radius)
+            elif region.shape == "circle":
+                inside = r < region.outer_radius
+            else:
+                nx, ny = region.normal
+                inside = dx * nx + dy * ny >= region.offset * (nx**2 + ny**2) ** 0.5
+            tracer = self.primitive1[ng:-ng, ng:-ng, 3 + region.tracer]
+            tracer[...] = self.xp.where(inside, region.concentration, tracer)
+
     def maximum_wavespeed(self):
         """
         Return the maximum wavespeed over a given patch.
+
+        If the advance kernel reduced the wavespeed at the end of the most
+        recent time step, that value is used, and otherwise the wavespeed is
+        computed here. The reduced wavespeed evaluates the sound speed with
+        the point masses at the end of the step, so the two are identical.
         """
+        if self.reductions_time == self.time:
+            with self.execution_context:
+                return self.reductions[:, 0].max()
+
         m1, m2 = self.physics.point_masses(self.time)
         with self.execution_context:
             self.lib.cbdiso_2d_wavespeed[self.shape](
@@ -150,6 +339,8 @@ class Patch:
                 self.physics.sound_speed**2,
                 self.physics.mach_number**2,
                 self.physics.eos_type.value,
+                self.physics.temperature_profile.value,
+                self.physics.flaring_index,
                 m1.position_x,
                 m1.position_y,
                 m1.velocity_x,
@@ -168,11 +359,55 @@ class Patch:
                 m2.sink_rate,
                 m2.sink_radius,
                 m2.sink_model.value,
+                self.physics.softening_model.value,
+                self.physics.softening_units.value,
                 self.primitive1,
                 self.wavespeeds,
             )
             return self.wavespeeds.max()
 
+    def sound_speed_squared(self):
+        """
+        Return an array of the squared sound speed on the patch interior, as
+        it is evaluated by the advance kernel, with the configured softening.
+        """
+        m1, m2 = self.physics.point_masses(self.time)
+        with self.execution_context:
+            cs2 = self.xp.zeros(self.shape)
+            self.lib.cbdiso_2d_sound_speed_squared[self.shape](
+                self.xl,
+                self.xr,
+                self.yl,
+                self.yr,
+                self.physics.sound_speed**2,
+                self.physics.mach_number**2,
+                self.physics.eos_type.value,
+                self.physics.temperature_profile.value,
+                self.physics.flaring_index,
+                m1.position_x,
+                m1.position_y,
+                m1.velocity_x,
+                m1.velocity_y,
+                m1.mass,
+                m1.softening_length,
+                m1.sink_rate,
+                m1.sink_radius,
+                m1.sink_model.value,
+                m2.position_x,
+                m2.position_y,
+                m2.velocity_x,
+                m2.velocity_y,
+                m2.mass,
+                m2.softening_length,
+                m2.sink_rate,
+                m2.sink_radius,
+                m2.sink_model.value,
+                self.physics.softening_model.value,
+                self.physics.softening_units.value,
+                cs2,
+            )
+            return cs2
+
     def recompute_conserved(self):
         """
         Convert the most recent primitive array to conserved.
@@ -183,18 +418,52 @@ class Patch:
                 self.conserved0,
             )
 
-    def advance_rk(self, rk_param, dt):
+    def external_acceleration(self):
+        """
+        Return the array of external accelerations to be passed to the advance
+        kernel, after evaluating the physics callback (if there is one) at the
+        current patch time.
+        """
+        acceleration_function = self.physics.external_acceleration
+
+        if acceleration_function is not None:
+            x, y = self.cell_center_coordinate_arrays
+            ax, ay = acceleration_function(self.time, x, y)
+            self.acceleration[..., 0] = ax
+            self.acceleration[..., 1] = ay
+
+        return self.acceleration
+
+    def advance_rk(self, rk_param, dt, final_stage=False):
         """
         Pass required parameters for time evolution of the setup.
 
         This function calls the C-module function responsible for performing time evolution using a
-        RK algorithm to update the parameters of the setup.
+        RK algorithm to update the parameters of the setup. On the final stage
+        of a time step, the kernel also reduces the maximum wavespeed and the
+        surface fluxes of the updated solution, if fused reductions are
+        enabled.
         """
         m1, m2 = self.physics.point_masses(self.time)
         buffer_central_mass = m1.mass + m2.mass
         buffer_surface_density = self.buffer_surface_density
+        next_time = self.time0 * rk_param + (self.time + dt) * (1.0 - rk_param)
+        reduce = self.fused_reductions and final_stage
+        shells = [
+            self.surface_flux_shell(d, next_time)
+            for d in self.surface_flux_diagnostics
+        ]
+        next_masses = [
+            (m.position_x, m.position_y, m.mass, m.softening_length)
+            for m in self.physics.point_masses(next_time)
+        ]
 
         with self.execution_context:
+            if reduce:
+                self.reductions[...] = 0.0
+
+            surface_fluxes = self.xp.array(shells or [[0.0] * 7]).reshape(-1, 7)
+            reduction_masses = self.xp.array(next_masses)
             self.lib.cbdiso_2d_advance_rk[self.shape](
                 self.xl,
                 self.xr,
@@ -209,6 +478,7 @@ class Patch:
                 self.buffer_outer_radius,
                 self.physics.buffer_onset_width,
                 int(self.physics.buffer_is_enabled),
+                self.buffer_inflow_viscosity,
                 m1.position_x,
                 m1.position_y,
                 m1.velocity_x,
@@ -227,17 +497,37 @@ class Patch:
                 m2.sink_rate,
                 m2.sink_radius,
                 m2.sink_model.value,
+                self.physics.first_order_radius,
+                self.physics.softening_model.value,
+                self.physics.softening_units.value,
                 self.physics.sound_speed**2,
                 self.physics.mach_number**2,
                 self.physics.eos_type.value,
+                self.physics.temperature_profile.value,
+                self.physics.flaring_index,
                 self.physics.viscosity_coefficient,
+                self.viscosity_profile,
+                len(self.viscosity_profile),
+                self.viscosity_profile_dr,
+                self.external_acceleration(),
+                int(self.physics.external_acceleration is not None),
+                self.physics.shearing_box_frequency,
                 rk_param,
                 dt,
                 self.options.velocity_ceiling,
                 self.options.density_floor,
+                self.floor_counts,
+                self.activation_map,
+                int(self.options.activation_maps),
+                self.reductions,
+                surface_fluxes,
+                len(shells),
+                reduction_masses,
+                int(reduce),
             )
-        self.time = self.time0 * rk_param + (self.time + dt) * (1.0 - rk_param)
+        self.time = next_time
         self.primitive1, self.primitive2 = self.primitive2, self.primitive1
+        self.reductions_time = next_time if reduce else None
 
     def new_iteration(self):
         self.time0 = self.time
@@ -269,6 +559,9 @@ class Solver(SolverBase):
         physics["diagnostics"] = [
             Diagnostic(**v) for v in physics.get("diagnostics", [])
         ]
+        physics["dye_regions"] = [
+            DyeRegion(**v) for v in physics.get("dye_regions", [])
+        ]
 
         self._physics = physics = Physics(**physics)
         self._options = options = Options(**options)
@@ -276,8 +569,26 @@ class Solver(SolverBase):
         if type(mesh) is not PlanarCartesian2DMesh:
             raise ValueError("solver only supports 2D cartesian mesh")
 
-        if setup.boundary_condition != "outflow":
-            raise ValueError("solver only supports outflow boundary condition")
+        if setup.boundary_condition not in ("outflow", "shearing_periodic"):
+            raise ValueError("solver only supports outflow or shearing_periodic BC")
+
+        if (setup.boundary_condition == "shearing_periodic") != (
+            physics.shearing_box_frequency > 0.0
+        ):
+            raise ValueError("shearing_periodic BC requires a shearing box, and v.v.")
+
+        if physics.shearing_box_frequency > 0.0 and (
+            physics.point_mass_function is not None or physics.buffer_is_enabled
+        ):
+            raise ValueError("shearing box does not support point masses or buffer")
+
+        if setup.boundary_condition == "shearing_periodic" and not (
+            mesh.periodic_x and mesh.periodic_y
+        ):
+            raise ValueError("shearing_periodic BC requires a mesh periodic in x and y")
+
+        if physics.buffer_is_enabled and (mesh.periodic_x or mesh.periodic_y):
+            raise ValueError("buffer zone is not supported on a periodic mesh")
 
         if physics.viscosity_model not in (
             ViscosityModel.NONE,
@@ -285,6 +596,12 @@ class Solver(SolverBase):
         ):
             raise ValueError("solver only supports constant-nu viscosity")
 
+        if physics.buffer_accretion_rate is not None and (
+            physics.viscosity_model != ViscosityModel.CONSTANT_NU
+            or not physics.buffer_is_enabled
+        ):
+            raise ValueError("buffer accretion rate requires the buffer and viscosity")
+
         if physics.eos_type not in (
             EquationOfState.GLOBALLY_ISOTHERMAL,
             EquationOfState.LOCALLY_ISOTHERMAL,
@@ -297,31 +614,81 @@ class Solver(SolverBase):
         if not physics.constant_softening:
             raise ValueError("solver only supports constant gravitational softening")
 
-        xp = get_array_module(mode)
+        if physics.vertical_structure:
+            raise ValueError("solver does not support vertical structure")
+
+        if physics.num_energy_reservoirs != 0:
+            raise ValueError("solver does not support energy reservoirs")
+
+        if physics.softening_units == SofteningUnits.CELL_SIZE:
+            self._physics = physics = physics.with_softening_scale(mesh.min_spacing())
+
+        for d in physics.diagnostics:
+            if d.is_surface_flux:
+                d.surface_flux_radii(mesh.min_spacing())
+            if d.quantity == "floor_count" and d.field not in FLOOR_FIELDS:
+                raise ValueError(f"floor_count field must be one of {FLOOR_FIELDS}")
+
+        for region in physics.dye_regions:
+            if region.shape not in ("annulus", "circle", "half_plane"):
+                raise ValueError(
+                    f"dye region shape must be annulus, circle, or half_plane, "
+                    f"got {region.shape}"
+                )
+            if not 0 <= region.tracer < physics.num_tracers:
+                raise ValueError(
+                    f"dye region refers to tracer {region.tracer}, but "
+                    f"there are {physics.num_tracers} tracers"
+                )
+
+        # In out-of-core mode, the patch data resides in host memory, and is
+        # streamed through the GPU in tiles of columns for each kernel launch.
+        xp = get_array_module("cpu" if options.out_of_core else mode)
         ng = 2  # number of guard zones
-        nq = 3  # number of conserved quantities
+        nq = num_fields(physics)  # number of conserved quantities
         with open(__file__.replace(".py", ".c")) as f:
             code = f.read()
-        lib = Library(code, mode=mode, debug=False)
+        lib = Library(
+            code,
+            mode=mode,
+            debug=False,
+            define_macros=dict(
+                NUM_TRACERS=physics.num_tracers,
+                PLM_THETA=options.plm_theta,
+                LOW_MACH_CORRECTION=int(options.low_mach_correction),
+                PERIOD_X=mesh.period_x,
+                PERIOD_Y=mesh.period_y,
+            ),
+            host_staged=options.out_of_core,
+            tile_size=options.out_of_core_tile_size,
+        )
 
         logger.info(f"initiate with time={time:0.4f}")
         logger.info(f"subdivide grid over {num_patches} patches")
         logger.info(f"mesh is {mesh}")
-        logger.info(f"boundary condition is outflow")
+        logger.info(f"boundary condition is {setup.boundary_condition}")
 
         self.mesh = mesh
         self.setup = setup
+        self.boundary_condition = setup.boundary_condition
         self.num_guard = ng
         self.num_cons = nq
         self.xp = xp
         self.patches = []
+        self._surface_flux_time = 0.0
         ni, nj = mesh.shape
 
         if solution is None:
-            primitive = initial_condition(setup, mesh, time)
+            primitive = initial_condition(setup, mesh, time, physics.num_tracers)
         else:
             primitive = solution
 
+        if primitive.shape[-1] != nq:
+            raise ValueError(
+                f"solution has {primitive.shape[-1] - 3} tracers, "
+                f"expected {physics.num_tracers}"
+            )
+
         if physics.buffer_is_enabled:
             # Here we sample the initial condition at the buffer onset radius
             # to determine the disk surface density at the radius where the
@@ -336,6 +703,20 @@ class Solver(SolverBase):
             buffer_outer_radius = 0.0
             buffer_surface_density = 0.0
 
+        if physics.buffer_accretion_rate is not None:
+            # The buffer drives the solution toward a steady viscous disk,
+            # which has surface density Mdot / (3 pi nu) and radial velocity
+            # -3 nu / 2r, independent of the initial condition.
+            nu = physics.viscosity_coefficient
+
+            if physics.viscosity_profile is not None:
+                nu *= float(physics.viscosity_profile(buffer_outer_radius))
+
+            buffer_surface_density = physics.buffer_accretion_rate / (3.0 * pi * nu)
+            buffer_inflow_viscosity = nu
+        else:
+            buffer_inflow_viscosity = 0.0
+
         for n, (a, b) in enumerate(subdivide(ni, num_patches)):
             prim = np.zeros([b - a + 2 * ng, nj + 2 * ng, nq])
             prim[ng:-ng, ng:-ng] = primitive[a:b]
@@ -348,12 +729,32 @@ class Solver(SolverBase):
                 options,
                 buffer_outer_radius,
                 buffer_surface_density,
+                buffer_inflow_viscosity,
                 lib,
                 xp,
                 execution_context(mode, device_id=n % num_devices(mode)),
+                fused_reductions=options.fused_reductions and mode != "gpu",
             )
             self.patches.append(patch)
 
+        self._graphs = KernelGraphCache(enabled=options.cuda_graphs)
+
+        if options.cuda_graphs:
+            if mode != "gpu" or options.out_of_core:
+                reason = "unless the patches are resident on a GPU"
+            elif num_patches > 1 and num_devices(mode) > 1:
+                reason = "with patches on multiple GPUs"
+            elif self.boundary_condition == "shearing_periodic":
+                reason = "with the shearing_periodic BC"
+            else:
+                reason = None
+
+            if reason is None:
+                logger.info("replay the guard zone fills as CUDA graphs")
+            else:
+                logger.warning(f"cuda_graphs is ignored {reason}")
+                self._graphs.enabled = False
+
     @property
     def solution(self):
         return concat_on_host(
@@ -367,16 +768,62 @@ class Solver(SolverBase):
         """
         return None
 
+    def auxiliary_fields(self):
+        """
+        Return per-zone counts of the density floor, pressure floor, velocity
+        ceiling, and slope limiter activations since the previous call, and
+        the gravitational torque density on each point mass.
+
+        The activation maps are only recorded if the `activation_maps` solver
+        option is enabled. They are reset to zero after being returned, so
+        each checkpoint shows the activations since the one before it. The
+        torque maps are only computed if the `torque_maps` option is enabled.
+        """
+        fields = dict()
+
+        if self._options.activation_maps:
+            maps = concat_on_host([p.activation_map for p in self.patches], rank=2)
+
+            for patch in self.patches:
+                with patch.execution_context:
+                    patch.activation_map[...] = 0.0
+
+            for n, name in enumerate(ACTIVATION_MAPS):
+                fields[name] = maps[..., n]
+
+        if self._options.torque_maps:
+            fields.update(self.torque_maps())
+
+        return fields
+
+    def torque_maps(self):
+        """
+        Return the gravitational torque density on each point mass, as the
+        fields `torque_density1` and `torque_density2`.
+        """
+        return {
+            f"torque_density{m}": concat_on_host(
+                [p.torque_density(m) for p in self.patches], rank=2
+            )
+            for m in (1, 2)
+        }
+
     def reductions(self):
         """
         Generate runtime reductions on the solution data for time series.
+
+        Reductions are computed on the device that holds each patch. The
+        point mass source terms are only computed if a diagnostic requires
+        them, and the results for each patch are brought to the host in a
+        single transfer.
         """
 
         diagnostics = self._physics.diagnostics
-        udots1_acc = [p.point_mass_source_term(1, accretion=True) for p in self.patches]
-        udots2_acc = [p.point_mass_source_term(2, accretion=True) for p in self.patches]
-        udots1_grv = [p.point_mass_source_term(1, gravity=True) for p in self.patches]
-        udots2_grv = [p.point_mass_source_term(2, gravity=True) for p in self.patches]
+        udots = dict()
+        num_fields = self.patches[0].primitive.shape[2]
+        primitive_field_names = [
+            field.name for field in solver_fields("cbdiso_2d", num_fields)
+        ]
         da = self.mesh.dx * self.mesh.dy
         ng = self.num_guard
 
@@ -410,6 +857,18 @@ class Solver(SolverBase):
                 fy = get_field(patch, 2, cut, mass, gravity, accretion)
                 return x * fy - y * fx
 
+            if quantity == "spin":
+                # The torque about the point mass itself, rather than the
+                # origin; with the accretion term, this is the rate at which
+                # the sink accretes spin angular momentum.
+                if mass not in (1, 2):
+                    raise ValueError("mass option for 'spin' must be 1 or 2")
+                m = self._physics.point_masses(patch.time)[mass - 1]
+                fx = get_field(patch, 1, cut, mass, gravity, accretion)
+                fy = get_field(patch, 2, cut, mass, gravity, accretion)
+                dx, dy = self.mesh.separation(x - m.position_x, y - m.position_y)
+                return dx * fy - dy * fx
+
             if quantity == "sigma_m1":
                 sigma = apply_radial_cut(patch.primitive[ng:-ng, ng:-ng, 0])
                 cos_phi = x / r
@@ -452,55 +911,141 @@ class Solver(SolverBase):
                     raise ValueError("Mass option for 'power' must be 1 or 2.")
 
             q = quantity
-            i = self.patches.index(patch)
-
-            if accretion:
-                udots1 = udots1_acc
-                udots2 = udots2_acc
-            elif gravity:
-                udots1 = udots1_grv
-                udots2 = udots2_grv
 
             if mass == "both":
-                f = udots1[i][..., q] + udots2[i][..., q]
+                f = get_udot(patch, 1, gravity, accretion)[..., q]
+                f = f + get_udot(patch, 2, gravity, accretion)[..., q]
             elif mass == 1:
-                f = udots1[i][..., q]
+                f = get_udot(patch, 1, gravity, accretion)[..., q]
             elif mass == 2:
-                f = udots2[i][..., q]
+                f = get_udot(patch, 2, gravity, accretion)[..., q]
 
             return apply_radial_cut(f)
 
-        def get_sum_fields(d):
-            result = []
+        def get_udot(patch, mass, gravity, accretion):
+            """
+            Return the point mass source term for a patch, computing it only
+            the first time it is needed. The accretion term takes precedence
+            if both terms are requested.
+            """
+            term = "acc" if accretion else "grv" if gravity else None
+            key = (self.patches.index(patch), mass, term)
+
+            if term is None:
+                raise ValueError("diagnostic requires either gravity or accretion")
+
+            if key not in udots:
+                udots[key] = patch.point_mass_source_term(
+                    mass, gravity=term == "grv", accretion=term == "acc"
+                )
+            return udots[key]
+
+        def get_mach_number(patch):
+            """
+            Return the Mach number of the gas on the patch interior. The sound
+            speed is computed by the kernel, so it uses the same softening
+            model and units as the solver.
+            """
+            prim = patch.primitive[ng:-ng, ng:-ng]
+            cs2 = patch.sound_speed_squared()
+            return ((prim[..., 1] ** 2 + prim[..., 2] ** 2) / cs2) ** 0.5
+
+        # Fields which can be histogrammed, besides the primitive fields
+        histogram_fields = dict(
+            mach_number=get_mach_number,
+            torque_density=lambda p: get_field(p, "torque", None, "both", gravity=True),
+        )
+
+        def get_sum_field(p, d):
+            if d.is_surface_flux:
+                n = diagnostics.index(d)
+                if self._surface_flux_time > 0.0:
+                    f = p.surface_flux_sums[n] / self._surface_flux_time
+                else:
+                    f = p.surface_flux(d)
+                p.surface_flux_sums[n] = 0.0
+                return f
+            if d.quantity == "floor_count":
+                return p.floor_counts[..., FLOOR_FIELDS.index(d.field)].sum()
+            if d.quantity == "histogram":
+                return histogram(p, d, primitive_field_names, histogram_fields)
+            if d.quantity == "fourier_modes":
+                return fourier_modes(p, d)
+            f = get_field(
+                p,
+                d.quantity,
+                d.radial_cut,
+                d.which_mass,
+                gravity=d.gravity,
+                accretion=d.accretion,
+            )
+            return f.sum()
+
+        fields = [d for d in diagnostics if d.quantity != "time"]
+        totals = [0.0] * len(fields)
+
+        if fields:
+            # Launch the reductions on every patch before blocking on any of
+            # the transfers, so that devices can work concurrently.
+            packed = []
             for p in self.patches:
                 with p.execution_context:
-                    f = get_field(
-                        p,
-                        d.quantity,
-                        d.radial_cut,
-                        d.which_mass,
-                        gravity=d.gravity,
-                        accretion=d.accretion,
-                    )
-                    result.append(f.sum())
-            return result
-
-        pass1 = []
-        pass2 = []
+                    values = [get_sum_field(p, d) for d in fields]
+                    packed.append(pack_on_device(values, p.xp))
+
+            for p, (buffer, layout) in zip(self.patches, packed):
+                with p.execution_context:
+                    for n, value in enumerate(unpack_on_host(buffer, layout)):
+                        totals[n] = totals[n] + value
+
+        result = []
+        sums = iter(totals)
 
         for d in diagnostics:
             if d.quantity == "time":
-                pass1.append(self.time / self.setup.reference_time_scale)
+                result.append(self.time / self.setup.reference_time_scale)
+            elif d.quantity == "floor_count":
+                result.append(next(sums))
             else:
-                pass1.append(get_sum_fields(d))
+                result.append(next(sums) * da)
 
-        for item in pass1:
-            if type(item) is not float:
-                pass2.append(sum(to_host(x) for x in item) * da)
-            else:
-                pass2.append(item)
+        if any(d.quantity == "floor_count" for d in diagnostics):
+            for p in self.patches:
+                with p.execution_context:
+                    p.floor_counts[...] = 0.0
+
+        self._surface_flux_time = 0.0
+        return result
+
+    def reductions_snapshot(self):
+        """
+        Return a function which computes the reductions for the current state.
+
+        The solver and its patches are shallow-copied, and the arrays the
+        reductions read are copied on their devices. The accumulated surface
+        fluxes and floor counts are moved to the snapshot, as if the
+        reductions had been computed now.
+        """
+        from copy import copy
+
+        snapshot = copy(self)
+        snapshot.patches = []
+
+        for patch in self.patches:
+            with patch.execution_context:
+                p = copy(patch)
+                p.primitive1 = patch.primitive1.copy()
+                p.floor_counts = patch.floor_counts.copy()
+                p.surface_flux_sums = list(patch.surface_flux_sums)
+                patch.surface_flux_sums = [0.0] * len(patch.surface_flux_sums)
+
+                if any(d.quantity == "floor_count" for d in self._physics.diagnostics):
+                    patch.floor_counts[...] = 0.0
+
+                snapshot.patches.append(p)
 
-        return pass2
+        self._surface_flux_time = 0.0
+        return snapshot.reductions
 
     @property
     def time(self):
@@ -533,22 +1078,106 @@ class Solver(SolverBase):
             (patch.execution_context for patch in self.patches),
         )
 
+    def save_state(self):
+        """
+        Return a copy of the solution state, which can be passed to
+        `restore_state` to discard a time step.
+        """
+        patches = []
+        for patch in self.patches:
+            with patch.execution_context:
+                primitive = patch.primitive1.copy()
+                reductions = (patch.reductions.copy(), patch.reductions_time)
+                counters = (patch.floor_counts.copy(), patch.activation_map.copy())
+                flux_sums = list(patch.surface_flux_sums)
+                patches.append(
+                    (patch.time, primitive, flux_sums, reductions, counters)
+                )
+        accreted = list(self._physics.accreted_mass or [])
+        return patches, self._surface_flux_time, accreted
+
+    def restore_state(self, state):
+        """
+        Restore the solution state from a copy made by `save_state`.
+        """
+        patches, surface_flux_time, accreted = state
+        for patch, patch_state in zip(self.patches, patches):
+            time, primitive, flux_sums, reductions, counters = patch_state
+            with patch.execution_context:
+                patch.time = patch.time0 = time
+                patch.primitive1[...] = primitive
+                patch.surface_flux_sums = list(flux_sums)
+                patch.reductions[...], patch.reductions_time = reductions
+                patch.floor_counts[...], patch.activation_map[...] = counters
+        self._surface_flux_time = surface_flux_time
+
+        if self._physics.accreted_mass is not None:
+            self._physics.accreted_mass[:] = accreted
+
+    def inject_dye(self):
+        """
+        Reset the tracer concentrations inside the dye regions which are
+        active at the current time.
+        """
+        for region in self._physics.dye_regions:
+            if region.is_active(self.time):
+                for patch in self.patches:
+                    patch.inject_dye(region)
+
     def advance(self, dt):
+        self.inject_dye()
         self.new_iteration()
         if self._options.rk_order == 1:
-            self.advance_rk(0.0, dt)
+            self.advance_rk(0.0, dt, final_stage=True)
         elif self._options.rk_order == 2:
             self.advance_rk(0.0, dt)
-            self.advance_rk(0.5, dt)
+            self.advance_rk(0.5, dt, final_stage=True)
         elif self._options.rk_order == 3:
             self.advance_rk(0.0, dt)
             self.advance_rk(0.75, dt)
-            self.advance_rk(1.0 / 3.0, dt)
+            self.advance_rk(1.0 / 3.0, dt, final_stage=True)
+        self.accumulate_surface_fluxes(dt)
+        self.accrete_point_masses(dt)
 
-    def advance_rk(self, rk_param, dt):
+    def accumulate_surface_fluxes(self, dt):
+        """
+        Add the surface fluxes at the end of a time step, weighted by the time
+        step size, to running sums which are kept on the device. The time
+        series records their averages since the previous sample.
+        """
+        for n, d in enumerate(self._physics.diagnostics):
+            if d.is_surface_flux:
+                for patch in self.patches:
+                    with patch.execution_context:
+                        f = patch.reduced_surface_flux(d)
+                        f = (patch.surface_flux(d) if f is None else f) * dt
+                        patch.surface_flux_sums[n] = patch.surface_flux_sums[n] + f
+        self._surface_flux_time += dt
+
+    def accrete_point_masses(self, dt):
+        """
+        Add the mass removed by each sink over a time step to the physics'
+        accreted masses, if the point masses grow by accretion.
+        """
+        accreted = self._physics.accreted_mass
+
+        if accreted is None:
+            return
+
+        da = self.mesh.dx * self.mesh.dy
+
+        for m in (1, 2):
+            mdot = 0.0
+            for patch in self.patches:
+                with patch.execution_context:
+                    udot = patch.point_mass_source_term(m, accretion=True)
+                    mdot -= float(udot[..., 0].sum()) * da
+            accreted[m - 1] += mdot * dt
+
+    def advance_rk(self, rk_param, dt, final_stage=False):
         self.set_bc("primitive1")
         for patch in self.patches:
-            patch.advance_rk(rk_param, dt)
+            patch.advance_rk(rk_param, dt, final_stage)
 
     def set_bc(self, array):
         ng = self.num_guard
@@ -559,7 +1188,7 @@ class Solver(SolverBase):
             pl = getattr(self.patches[il], array)
             pc = getattr(self.patches[i0], array)
             pr = getattr(self.patches[ir], array)
-            self.set_bc_patch(pl, pc, pr, i0)
+            self._graphs(lambda: self.set_bc_patch(pl, pc, pr, i0), i0, pl, pc, pr)
 
     def set_bc_patch(self, pl, pc, pr, patch_index):
         ni, nj = self.mesh.shape
@@ -567,24 +1196,68 @@ class Solver(SolverBase):
 
         with self.patches[patch_index].execution_context:
             # 1. write to the guard zones of pc, the internal BC
-            pc[:+ng] = pl[-2 * ng : -ng]
-            pc[-ng:] = pr[+ng : +2 * ng]
-
-            # 2. Set outflow BC on the left/right patch edges
-            if patch_index == 0:
-                for i in range(ng):
-                    pc[i] = pc[ng]
-            if patch_index == len(self.patches) - 1:
-                for i in range(pc.shape[0] - ng, pc.shape[0]):
-                    pc[i] = pc[-ng - 1]
-
-            # 3. Set outflow BC on bottom and top edges
+            copy_guard_zones(pc[:+ng], pl[-2 * ng : -ng])
+            copy_guard_zones(pc[-ng:], pr[+ng : +2 * ng])
+
+            if self.boundary_condition == "shearing_periodic":
+                # 2. Shift the periodic images on the left/right patch edges
+                if patch_index == 0:
+                    self.shear_guard_zones(pc[:+ng, ng:-ng], +1.0)
+                if patch_index == len(self.patches) - 1:
+                    self.shear_guard_zones(pc[-ng:, ng:-ng], -1.0)
+
+                # 3. Set periodic BC on bottom and top edges
+                pc[:, :+ng] = pc[:, -2 * ng : -ng]
+                pc[:, -ng:] = pc[:, +ng : +2 * ng]
+                return
+
+            # 2. Set outflow BC on the left/right patch edges, unless the
+            # mesh is periodic in x; then the internal BC above has already
+            # copied in the image from the opposite edge of the domain
+            if not self.mesh.periodic_x:
+                if patch_index == 0:
+                    for i in range(ng):
+                        pc[i] = pc[ng]
+                if patch_index == len(self.patches) - 1:
+                    for i in range(pc.shape[0] - ng, pc.shape[0]):
+                        pc[i] = pc[-ng - 1]
+
+            # 3. Set periodic or outflow BC on bottom and top edges
+            if self.mesh.periodic_y:
+                pc[:, :+ng] = pc[:, -2 * ng : -ng]
+                pc[:, -ng:] = pc[:, +ng : +2 * ng]
+                return
+
             for i in range(ng):
                 pc[:, i] = pc[:, ng]
 
             for i in range(pc.shape[1] - ng, pc.shape[1]):
                 pc[:, i] = pc[:, -ng - 1]
 
+    def shear_guard_zones(self, guard, side):
+        """
+        Apply the shear-periodic boundary condition to the x guard zones on
+        one side of the box (side = +1 on the left, -1 on the right), which
+        already hold the periodic image from the other side.
+
+        The image is displaced in y by the shear across the box, q Omega Lx t
+        with q = 3/2, and interpolated linearly between zones. Its y-velocity
+        is offset by the background shear velocity difference, q Omega Lx.
+        """
+        from math import floor
+
+        mesh = self.mesh
+        omega = self._physics.shearing_box_frequency
+        lx = mesh.x1 - mesh.x0
+        shift = side * 1.5 * omega * lx * self.time / mesh.dy
+        n = int(floor(shift))
+        w = shift - n
+        xp = self.xp
+        image = guard.copy()
+        guard[...] = (1.0 - w) * xp.roll(image, n, axis=1)
+        guard[...] += w * xp.roll(image, n + 1, axis=1)
+        guard[..., 2] += side * 1.5 * omega * lx
+
     def new_iteration(self):
         for patch in self.patches:
             patch.new_iteration()
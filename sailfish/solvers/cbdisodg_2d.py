@@ -5,9 +5,21 @@ Isothermal solver for the binary accretion problem in 2D planar coordinates.
 from logging import getLogger
 from typing import NamedTuple
 from sailfish.kernel.library import Library
-from sailfish.kernel.system import get_array_module, execution_context, num_devices
+from sailfish.kernel.system import (
+    copy_guard_zones,
+    execution_context,
+    get_array_module,
+    num_devices,
+)
 from sailfish.mesh import PlanarCartesian2DMesh
-from sailfish.physics.circumbinary import Physics, EquationOfState, ViscosityModel
+from sailfish.physics.circumbinary import (
+    Physics,
+    EquationOfState,
+    ViscosityModel,
+    SofteningModel,
+    SofteningUnits,
+    TemperatureProfile,
+)
 from sailfish.solver_base import SolverBase
 from sailfish.subdivide import subdivide, concat_on_host, lazy_reduce
 
@@ -323,6 +335,9 @@ class Solver(SolverBase):
         if type(mesh) is not PlanarCartesian2DMesh:
             raise ValueError("solver only supports 2D Cartesian mesh")
 
+        if mesh.periodic_x or mesh.periodic_y:
+            raise ValueError("solver does not support periodic meshes")
+
         if setup.boundary_condition != "outflow":
             raise ValueError("solver only supports outflow boundary condition")
 
@@ -332,6 +347,15 @@ class Solver(SolverBase):
         ):
             raise ValueError("solver only supports constant-nu viscosity")
 
+        if physics.viscosity_profile is not None:
+            raise ValueError("solver does not support a viscosity profile")
+
+        if physics.accreted_mass is not None:
+            raise ValueError("solver does not support point mass growth by accretion")
+
+        if physics.first_order_radius > 0.0:
+            raise ValueError("solver does not support a first-order radius")
+
         if physics.eos_type not in (
             EquationOfState.GLOBALLY_ISOTHERMAL,
             EquationOfState.LOCALLY_ISOTHERMAL,
@@ -344,6 +368,33 @@ class Solver(SolverBase):
         if not physics.constant_softening:
             raise ValueError("solver only supports constant gravitational softening")
 
+        if (
+            physics.softening_model != SofteningModel.PLUMMER
+            or physics.softening_units != SofteningUnits.ABSOLUTE
+        ):
+            raise ValueError("solver only supports Plummer softening of fixed length")
+
+        if (
+            physics.temperature_profile != TemperatureProfile.POINT_MASSES
+            or physics.flaring_index != 0.0
+        ):
+            raise ValueError("solver only supports the point mass temperature profile")
+
+        if physics.num_tracers != 0:
+            raise ValueError("solver does not support passive tracers")
+
+        if physics.vertical_structure:
+            raise ValueError("solver does not support vertical structure")
+
+        if physics.num_energy_reservoirs != 0:
+            raise ValueError("solver does not support energy reservoirs")
+
+        if physics.external_acceleration is not None:
+            raise ValueError("solver does not support external acceleration")
+
+        if physics.buffer_accretion_rate is not None:
+            raise ValueError("solver does not support a buffer accretion rate")
+
         xp = get_array_module(mode)
         ng = GUARD  # number of guard zones
         nq = NCONS  # number of conserved quantities
@@ -525,8 +576,8 @@ class Solver(SolverBase):
 
         with self.patches[patch_index].execution_context:
             # 1. write to the guard zones of pc, the internal BC
-            pc[:+ng] = pl[-2 * ng : -ng]
-            pc[-ng:] = pr[+ng : +2 * ng]
+            copy_guard_zones(pc[:+ng], pl[-2 * ng : -ng])
+            copy_guard_zones(pc[-ng:], pr[+ng : +2 * ng])
 
             # 2. Set outflow BC on the left/right patch edges
             if patch_index == 0:
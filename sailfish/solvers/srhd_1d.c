@@ -14,10 +14,13 @@ DESCRIPTION:
 #define COORDS_CARTESIAN 0
 #define COORDS_SPHERICAL 1
 #define NCONS 4
-#define PLM_THETA 2.0
 #define ADIABATIC_GAMMA (4.0 / 3.0)
 #define NOMINAL_FOUR_PI 1.0
 
+#ifndef PLM_THETA
+#define PLM_THETA 2.0
+#endif
+
 
 // ============================ MATH ==========================================
 // ============================================================================
@@ -477,7 +480,9 @@ PUBLIC void srhd_1d_max_wavespeeds(
 
 /**
  * Updates an array of primitive data by advancing it a single Runge-Kutta
- * step.
+ * step. If source terms are enabled, the source_terms array gives the rate of
+ * change of the conserved densities (per unit volume) in each zone, which is
+ * added to the geometric source terms.
  */
 PUBLIC void srhd_1d_advance_rk(
     int num_zones,          // number of zones, not including guard zones
@@ -493,7 +498,9 @@ PUBLIC void srhd_1d_advance_rk(
     double dt,              // timestep size
     int fix_i0,             // don't evolve the first zone in the patch
     int fix_i1,             // don't evolve the final zone in the patch
-    int coords)             // :: $ in [0, 1]
+    int coords,             // :: $ in [0, 1]
+    double *source_terms,   // :: $.shape == (num_zones, 4) or not source_terms_enabled
+    int source_terms_enabled)
 {
     int ng = 2; // number of guard zones
 
@@ -547,6 +554,16 @@ PUBLIC void srhd_1d_advance_rk(
             riemann_hllc(prim, prip, yr * adot, fri);
             geometric_source_terms(coords, xl, xr, prd, sources);
 
+            if (source_terms_enabled)
+            {
+                double dv = cell_volume(coords, xl, xr);
+
+                for (int q = 0; q < NCONS; ++q)
+                {
+                    sources[q] += source_terms[NCONS * i + q] * dv;
+                }
+            }
+
             for (int q = 0; q < NCONS; ++q)
             {
                 uwr[q] = urd[q] + (fli[q] * dal - fri[q] * dar + sources[q]) * dt;
@@ -15,9 +15,14 @@ value of 2.0.
 """
 
 from logging import getLogger
-from typing import NamedTuple
+from typing import Callable, NamedTuple
 from sailfish.kernel.library import Library
-from sailfish.kernel.system import get_array_module, execution_context, num_devices
+from sailfish.kernel.system import (
+    copy_guard_zones,
+    execution_context,
+    get_array_module,
+    num_devices,
+)
 from sailfish.subdivide import subdivide, concat_on_host, lazy_reduce
 from sailfish.mesh import PlanarCartesianMesh, LogSphericalMesh
 from sailfish.solver_base import SolverBase
@@ -58,10 +63,12 @@ def initial_condition(setup, mesh, i0, i1, time, xp):
 class Options(NamedTuple):
     compute_wavespeed: bool = False
     rk_order: int = 2
+    plm_theta: float = 2.0
 
 
 class Physics(NamedTuple):
-    pass
+    source_terms: Callable = None
+    """ Callback (t, x) -> (s0, s1, s2, s3) for conserved density source terms """
 
 
 class Patch:
@@ -81,6 +88,7 @@ class Patch:
         index_range,
         fix_i0,
         fix_i1,
+        physics,
         lib,
         xp,
         execution_context,
@@ -95,6 +103,7 @@ class Patch:
         self.index_range = index_range
         self.fix_i0 = fix_i0
         self.fix_i1 = fix_i1
+        self.physics = physics
         self.num_zones = num_zones = index_range[1] - index_range[0]
         self.coordinates = coordinates = COORDINATES_DICT[type(mesh)]
         self.time = self.time0 = time
@@ -133,6 +142,9 @@ class Patch:
             self.conserved0 = conserved_with_guard.copy()
             self.conserved1 = conserved_with_guard.copy()
             self.conserved2 = conserved_with_guard.copy()
+            self.sources = xp.zeros(
+                (num_zones, nq) if physics.source_terms else (1, nq)
+            )
 
     def recompute_primitive(self):
         with self.execution_context:
@@ -144,7 +156,25 @@ class Patch:
                 self.coordinates,
             )
 
+    def source_terms(self):
+        """
+        Return the array of source terms to be passed to the advance kernel,
+        after evaluating the physics callback (if there is one) at the zone
+        centers and the current patch time.
+        """
+        source_function = self.physics.source_terms
+
+        if source_function is not None:
+            with self.execution_context:
+                x = 0.5 * (self.faces[:-1] + self.faces[1:]) * self.scale_factor
+                for q, s in enumerate(source_function(self.time, x)):
+                    self.sources[:, q] = s
+
+        return self.sources
+
     def advance_rk(self, rk_param, dt):
+        sources = self.source_terms()
+
         with self.execution_context:
             self.lib.srhd_1d_advance_rk[self.num_zones](
                 self.faces,
@@ -160,6 +190,8 @@ class Patch:
                 int(self.fix_i0),
                 int(self.fix_i1),
                 self.coordinates,
+                sources,
+                int(self.physics.source_terms is not None),
             )
         self.time = self.time0 * rk_param + (self.time + dt) * (1.0 - rk_param)
         self.conserved1, self.conserved2 = self.conserved2, self.conserved1
@@ -213,10 +245,14 @@ class Solver(SolverBase):
             code = f.read()
 
         xp = get_array_module(mode)
-        lib = Library(code, mode=mode, debug=False)
-
         self._physics = physics = Physics(**physics)
         self._options = options = Options(**options)
+        lib = Library(
+            code,
+            mode=mode,
+            debug=False,
+            define_macros=dict(PLM_THETA=options.plm_theta),
+        )
 
         try:
             bcl, bcr = setup.boundary_condition
@@ -248,6 +284,7 @@ class Solver(SolverBase):
                 (a, b),
                 fix_i0,
                 fix_i1,
+                physics,
                 lib,
                 xp,
                 execution_context(mode, device_id=n % num_devices(mode)),
@@ -338,8 +375,8 @@ class Solver(SolverBase):
         bcl, bcr = self.boundary_condition
 
         with self.patches[patch_index].execution_context:
-            pc[:+ng] = pl[-2 * ng : -ng]
-            pc[-ng:] = pr[+ng : +2 * ng]
+            copy_guard_zones(pc[:+ng], pl[-2 * ng : -ng])
+            copy_guard_zones(pc[-ng:], pr[+ng : +2 * ng])
 
             def negative_vel(p):
                 return self.xp.asarray([p[0], -p[1], p[2], p[3]])
@@ -17,7 +17,12 @@ value of 2.0.
 from logging import getLogger
 from typing import NamedTuple
 from sailfish.kernel.library import Library
-from sailfish.kernel.system import get_array_module, execution_context, num_devices
+from sailfish.kernel.system import (
+    copy_guard_zones,
+    execution_context,
+    get_array_module,
+    num_devices,
+)
 from sailfish.subdivide import subdivide, concat_on_host, lazy_reduce
 from sailfish.mesh import PlanarCartesianMesh, LogSphericalMesh
 from sailfish.solver_base import SolverBase
@@ -369,8 +374,8 @@ class Solver(SolverBase):
         bcl, bcr = self.boundary_condition
 
         with self.patches[patch_index].execution_context:
-            pc[:+ng] = pl[-2 * ng : -ng]
-            pc[-ng:] = pr[+ng : +2 * ng]
+            copy_guard_zones(pc[:+ng], pl[-2 * ng : -ng])
+            copy_guard_zones(pc[-ng:], pr[+ng : +2 * ng])
 
             def negative_vel(p):
                 return self.xp.asarray([p[0], -p[1], p[2], p[3]])
@@ -0,0 +1,105 @@
+"""
+A thread-safe summary of the running simulation, for embedding applications.
+
+The driver publishes its progress to the global :obj:`STATUS` handle: the
+setup name, iteration, simulation time, timestep, zone update rate, and the
+most recent checkpoint file. Frontends such as GUIs or a status server can
+poll it from another thread with :func:`current_status`, rather than parsing
+the log output:
+
+.. code-block:: python
+
+    from threading import Thread
+    from sailfish.driver import run
+    from sailfish.status import current_status
+
+    Thread(target=run, args=("kitp-code-comparison",), daemon=True).start()
+    print(current_status().iteration)
+
+Each poll returns an immutable :obj:`StatusSnapshot`, so its fields are
+always mutually consistent, and it may be kept or passed to other threads.
+"""
+
+from threading import Lock
+from time import time as wall_clock
+from typing import NamedTuple
+
+
+class StatusSnapshot(NamedTuple):
+    """
+    The state of the simulation at the most recent update
+    """
+
+    setup_name: str = None
+    """ the name of the setup being run, or `None` before the first run """
+
+    running: bool = False
+    """ whether a run is in progress """
+
+    iteration: int = 0
+    """ the iteration number """
+
+    time: float = None
+    """ the simulation time, in the setup's reference time units """
+
+    dt: float = None
+    """ the most recent timestep, in code units """
+
+    mzps: float = None
+    """ million zones updated per second over the most recent fold """
+
+    last_checkpoint: str = None
+    """ the file name of the most recent checkpoint written """
+
+    error: str = None
+    """ a description of the exception which ended the run, if any """
+
+    updated: float = None
+    """ the wall clock time of the update, in seconds since the epoch """
+
+
+class Status:
+    """
+    A handle to the simulation status, which may be polled from any thread
+
+    Updates replace the snapshot as a whole under a lock, so a reader never
+    sees a partially updated status.
+    """
+
+    def __init__(self):
+        self._lock = Lock()
+        self._snapshot = StatusSnapshot()
+
+    def snapshot(self):
+        """
+        Return the current :obj:`StatusSnapshot`.
+        """
+        with self._lock:
+            return self._snapshot
+
+    def update(self, **fields):
+        """
+        Replace the given fields of the status. This is called by the driver.
+        """
+        with self._lock:
+            self._snapshot = self._snapshot._replace(updated=wall_clock(), **fields)
+
+    def reset(self, **fields):
+        """
+        Clear the status and set the given fields, at the start of a run.
+        """
+        with self._lock:
+            self._snapshot = StatusSnapshot(updated=wall_clock(), **fields)
+
+
+STATUS = Status()
+"""
+The status of the simulation being run in this process.
+"""
+
+
+def current_status():
+    """
+    Return a :obj:`StatusSnapshot` of the simulation being run in this process.
+    """
+    return STATUS.snapshot()
@@ -32,6 +32,47 @@ def lazy_reduce(reduction, block, launches, contexts):
     return reduction(results)
 
 
+def pack_on_device(values, xp):
+    """
+    Pack a sequence of device scalars and arrays into a single flat buffer.
+
+    Returns the buffer, which is still allocated on the device, and a layout
+    which is needed to recover the original values with `unpack_on_host`.
+    This allows several reduction results to be brought to the host with a
+    single transfer. The buffer is complex if any of the values are complex.
+    """
+    layout = [(getattr(v, "shape", ()), xp.iscomplexobj(v)) for v in values]
+    dtype = complex if any(c for _, c in layout) else float
+    buffer = xp.concatenate([xp.ravel(xp.asarray(v, dtype=dtype)) for v in values])
+    return buffer, layout
+
+
+def unpack_on_host(buffer, layout):
+    """
+    Recover a list of values from a buffer created by `pack_on_device`.
+
+    The buffer is transferred to the host if necessary. Values which were
+    real are returned as real arrays, and scalars are returned as numpy
+    scalars.
+    """
+    buffer = to_host(buffer)
+    result = []
+    i = 0
+
+    for shape, is_complex in layout:
+        n = 1
+
+        for s in shape:
+            n *= s
+
+        value = buffer[i : i + n].reshape(shape)
+        value = value if is_complex else value.real
+        result.append(value[()] if shape == () else value)
+        i += n
+
+    return result
+
+
 def partition(elements, num_parts):
     """
     Equitably divide the given number of elements into `num_parts` partitions.
@@ -0,0 +1,86 @@
+"""
+Conversion factors between code units and physical (CGS or SI) units.
+
+Setups which have a physical scale define a :obj:`UnitSystem`, giving the
+code units of mass, length, and time in CGS. The unit system is written to
+checkpoints, so output can be converted without re-deriving the scalings.
+Quantities to be converted are described by the exponents of mass, length,
+and time in their dimensions, as in the field registry
+(:py:mod:`sailfish.fields`).
+"""
+
+from typing import NamedTuple
+
+GRAVITATIONAL_CONSTANT = 6.6743e-8
+""" Newton's constant, in cm^3 g^-1 s^-2 """
+
+SOLAR_MASS = 1.98847e33
+""" The solar mass, in grams """
+
+ASTRONOMICAL_UNIT = 1.495978707e13
+""" The astronomical unit, in centimeters """
+
+SPEED_OF_LIGHT = 2.99792458e10
+""" The speed of light, in cm s^-1 """
+
+SYSTEMS = ("code", "cgs", "si")
+
+SYMBOLS = dict(code=("M", "L", "T"), cgs=("g", "cm", "s"), si=("kg", "m", "s"))
+
+
+def unit_string(dimensions, system="code"):
+    """
+    Return a string representation of the units of a quantity with the given
+    dimensions, e.g. `M L^-2` in code units or `g cm^-2` in CGS.
+    """
+    terms = []
+
+    for symbol, power in zip(SYMBOLS[system], dimensions):
+        if power == 1:
+            terms.append(symbol)
+        elif power != 0:
+            terms.append(f"{symbol}^{power}")
+
+    return " ".join(terms)
+
+
+class UnitSystem(NamedTuple):
+    """
+    The code units of mass, length, and time, in grams, centimeters, and
+    seconds
+    """
+
+    mass: float = 1.0
+    length: float = 1.0
+    time: float = 1.0
+
+    @classmethod
+    def gravitational(cls, mass, length):
+        """
+        Return a unit system where the gravitational constant is 1, given the
+        mass and length units in CGS.
+        """
+        time = (length**3 / (GRAVITATIONAL_CONSTANT * mass)) ** 0.5
+        return cls(mass=mass, length=length, time=time)
+
+    def factor(self, dimensions, system="cgs"):
+        """
+        Return the factor which converts a quantity with the given dimensions
+        from code units to the given system: code, cgs, or si.
+        """
+        a, b, c = dimensions
+
+        if system == "code":
+            return 1.0
+        elif system == "cgs":
+            return self.mass**a * self.length**b * self.time**c
+        elif system == "si":
+            return (1e-3 * self.mass) ** a * (1e-2 * self.length) ** b * self.time**c
+        else:
+            raise ValueError(f"unknown unit system {system}, must be {SYSTEMS}")
+
+    def convert(self, value, dimensions, system="cgs"):
+        """
+        Convert a value or array from code units to the given system.
+        """
+        return value * self.factor(dimensions, system)
@@ -0,0 +1,48 @@
+"""
+Convergence tests of the srhd_1d solver and its boundary conditions, using
+the method of manufactured solutions. Run with `python -m pytest tests` from
+the repository root.
+"""
+
+import pytest
+from sailfish.driver import run
+from sailfish.physics.manufactured import convergence_orders, l1_error
+
+RESOLUTIONS = (64, 128, 256)
+
+
+def manufactured_solution_error(boundary, resolution, end_time=0.25):
+    """
+    Run the manufactured solution setup with the given boundary condition,
+    and return the L1 error of the mass density at the end time.
+    """
+    from numpy import array
+
+    state = run(
+        "manufactured-solution",
+        end_time=end_time,
+        resolution=resolution,
+        model_parameters=dict(boundary=boundary),
+    )
+    solver = state.solver
+    x = array([solver.mesh.zone_center(solver.time, i) for i in range(resolution)])
+    exact = state.setup.exact_primitive(solver.time, x)[0]
+    return l1_error(solver.primitive[:, 0], exact, 1.0 / resolution)
+
+
+def test_forcing_vanishes_for_uniform_flow():
+    from numpy import linspace
+    from sailfish.physics.manufactured import srhd_1d_forcing
+
+    forcing = srhd_1d_forcing(lambda t, x: (1.0 + 0 * x, 0.5, 0.1, 0.5))
+
+    for s in forcing(0.0, linspace(0.0, 1.0, 11)):
+        assert abs(s).max() < 1e-8
+
+
+@pytest.mark.parametrize("boundary", ["periodic", "outflow", "inflow", "reflect"])
+def test_second_order_convergence(boundary):
+    errors = [manufactured_solution_error(boundary, n) for n in RESOLUTIONS]
+
+    for order in convergence_orders(RESOLUTIONS, errors):
+        assert order > 1.6, f"{boundary}: errors {errors}"
@@ -0,0 +1,103 @@
+"""
+Regression tests, which run short reference problems on the CPU and compare
+the results with golden checkpoints stored in tests/golden. Run with
+`python -m pytest tests` from the repository root.
+
+After a change which is meant to alter the numerical results, regenerate
+the golden checkpoints by running the tests with the environment variable
+`SAILFISH_REGENERATE_GOLDENS=1`, and commit the new files with the change.
+A reference problem without a golden checkpoint fails, so a new problem
+must be committed together with its golden checkpoint.
+"""
+
+import os
+import pickle
+import pytest
+from sailfish.compare import compare_checkpoints
+from sailfish.driver import checkpoint_dict, run
+from sailfish.export import checkpoint_fields
+
+GOLDEN_DIR = os.path.join(os.path.dirname(__file__), "golden")
+REGENERATE = os.environ.get("SAILFISH_REGENERATE_GOLDENS", "0") not in ("", "0")
+
+TOLERANCE = 1e-10
+"""
+The largest difference allowed in each field, relative to the largest
+magnitude of the field in the golden checkpoint. This allows for rounding
+differences between compilers and CPUs, but not for changes to the numerics.
+"""
+
+REFERENCE_PROBLEMS = dict(
+    shocktube=dict(setup_name="shocktube", resolution=200, end_time=0.1),
+    burgers=dict(setup_name="burgers", resolution=64, end_time=0.1),
+    explosion_isothermal=dict(
+        setup_name="cylindrical-explosion",
+        resolution=64,
+        end_time=0.02,
+        model_parameters=dict(eos="isothermal"),
+    ),
+    explosion_gamma_law=dict(
+        setup_name="cylindrical-explosion",
+        resolution=64,
+        end_time=0.02,
+        model_parameters=dict(eos="gamma-law"),
+    ),
+    kitp_code_comparison=dict(
+        setup_name="kitp-code-comparison",
+        resolution=64,
+        end_time=0.05,
+    ),
+    shearing_box=dict(setup_name="shearing-box", resolution=32, end_time=0.1),
+)
+"""
+Short runs which together cover each CPU solver, and the isothermal and
+gamma-law disk physics.
+"""
+
+
+def golden_filename(name):
+    return os.path.join(GOLDEN_DIR, f"{name}.pk")
+
+
+def run_reference_problem(name):
+    """
+    Run a reference problem on the CPU, and return its final checkpoint
+    dictionary.
+    """
+    state = run(execution_mode="cpu", **REFERENCE_PROBLEMS[name])
+    return checkpoint_dict(state)
+
+
+@pytest.mark.parametrize("name", sorted(REFERENCE_PROBLEMS))
+def test_matches_golden_checkpoint(name):
+    chkpt = run_reference_problem(name)
+    filename = golden_filename(name)
+
+    if REGENERATE:
+        os.makedirs(GOLDEN_DIR, exist_ok=True)
+
+        with open(filename, "wb") as f:
+            pickle.dump(chkpt, f)
+
+        pytest.skip(f"regenerated {filename}")
+
+    if not os.path.exists(filename):
+        pytest.fail(
+            f"no golden checkpoint {filename}; "
+            "run with SAILFISH_REGENERATE_GOLDENS=1 to create it"
+        )
+
+    with open(filename, "rb") as f:
+        golden = pickle.load(f)
+
+    assert chkpt["iteration"] == golden["iteration"]
+    assert chkpt["time"] == pytest.approx(golden["time"], rel=TOLERANCE)
+
+    fields = checkpoint_fields(golden, auxiliary=False)
+
+    for d in compare_checkpoints(chkpt, golden):
+        scale = max(float(abs(fields[d.field]).max()), 1e-300)
+        assert d.linf <= TOLERANCE * scale, (
+            f"{name}: field {d.field} differs from the golden checkpoint by "
+            f"{d.linf:.3e} (relative {d.linf / scale:.3e}) at {d.location}"
+        )